@@ -0,0 +1,143 @@
+//! Audit log of the requests changing a daemon, one `audit_log` row per request.
+
+use std::sync::Arc;
+
+use chrono::{Local, NaiveDateTime};
+use serde::Serialize;
+use sqlx::MySqlPool;
+use tide::{http::Method, Middleware, Next, Request};
+use uuid::Uuid;
+
+use crate::{service::source_ip, State};
+
+/// One audited request and the status it was answered with
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditRecord {
+    pub data_id: String,
+    pub docker_id: String,
+    pub method: String,
+    pub path: String,
+    pub from_ip: String,
+    pub status: u16,
+    pub create_time: NaiveDateTime,
+}
+
+/// Where audit records are written to
+#[tide::utils::async_trait]
+pub trait AuditSink: Send + Sync + 'static {
+    async fn record(&self, record: &AuditRecord) -> tide::Result<()>;
+}
+
+#[tide::utils::async_trait]
+impl AuditSink for MySqlPool {
+    async fn record(&self, record: &AuditRecord) -> tide::Result<()> {
+        let insert = "insert into audit_log \
+            (data_id, docker_id, method, path, from_ip, status, create_time) \
+            values (?, ?, ?, ?, ?, ?, ?)";
+        sqlx::query(insert)
+            .bind(&record.data_id)
+            .bind(&record.docker_id)
+            .bind(&record.method)
+            .bind(&record.path)
+            .bind(&record.from_ip)
+            .bind(record.status)
+            .bind(record.create_time)
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Records every request with a method other than GET, HEAD or OPTIONS once it is
+/// answered. Records are written in the background, a failing write is logged and
+/// never fails the request.
+#[derive(Clone)]
+pub struct AuditLog {
+    sink: Arc<dyn AuditSink>,
+}
+
+impl AuditLog {
+    pub fn new<S: AuditSink>(sink: S) -> Self {
+        AuditLog {
+            sink: Arc::new(sink),
+        }
+    }
+}
+
+#[tide::utils::async_trait]
+impl Middleware<State> for AuditLog {
+    async fn handle(&self, request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        if matches!(request.method(), Method::Get | Method::Head | Method::Options) {
+            return Ok(next.run(request).await);
+        }
+        let docker_id = request.param("docker").unwrap_or_default().to_owned();
+        let method = request.method().to_string();
+        let path = request.url().path().to_owned();
+        let from_ip = source_ip(&request);
+
+        let response = next.run(request).await;
+        let record = AuditRecord {
+            data_id: Uuid::new_v4().to_string(),
+            docker_id,
+            method,
+            path,
+            from_ip,
+            status: response.status() as u16,
+            create_time: Local::now().naive_local(),
+        };
+        let sink = self.sink.clone();
+        async_std::task::spawn(async move {
+            if let Err(e) = sink.record(&record).await {
+                log::error!("audit of {} {} not recorded: {}", record.method, record.path, e);
+            }
+        });
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{channel::mpsc, StreamExt};
+    use std::sync::Mutex;
+
+    /// passes the records on to the test
+    struct Recorder(Mutex<mpsc::UnboundedSender<AuditRecord>>);
+
+    #[tide::utils::async_trait]
+    impl AuditSink for Recorder {
+        async fn record(&self, record: &AuditRecord) -> tide::Result<()> {
+            let _ = self.0.lock().unwrap().unbounded_send(record.clone());
+            Ok(())
+        }
+    }
+
+    /// Test a mutating request being recorded with its status, and reads being skipped
+    #[async_std::test]
+    async fn audit_mutating_requests() {
+        let (sender, mut records) = mpsc::unbounded();
+        let mut app = tide::with_state(State::for_test());
+        app.at("/docker/:docker/containers/:id")
+            .with(AuditLog::new(Recorder(Mutex::new(sender))))
+            .get(|_| async { Ok("web") })
+            .delete(|_| async { Ok(tide::Response::new(204)) });
+
+        let url = "http://localhost/docker/7/containers/web";
+        let response: http_types::Response =
+            app.respond(http_types::Request::get(url)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let response: http_types::Response =
+            app.respond(http_types::Request::delete(url)).await.unwrap();
+        assert_eq!(response.status(), 204);
+
+        let record = records.next().await.unwrap();
+        assert_eq!(record.docker_id, "7");
+        assert_eq!(record.method, "DELETE");
+        assert_eq!(record.path, "/docker/7/containers/web");
+        assert_eq!(record.status, 204);
+        assert!(Uuid::parse_str(&record.data_id).is_ok());
+        // the GET was not recorded
+        drop(app);
+        assert!(records.next().await.is_none());
+    }
+}
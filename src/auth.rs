@@ -0,0 +1,142 @@
+//! Api keys guarding the daemon routes: every `/docker/:docker` request needs an
+//! `Authorization: Bearer <key>` header with a key valid for that docker id.
+
+use std::{collections::HashSet, env, sync::Arc};
+
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tide::{http::headers, Middleware, Next, Request, Response, StatusCode};
+
+use crate::State;
+
+/// keys valid for a docker id, by their [`key_hash`]: `docker_id` null grants every daemon
+const KEY_QUERY: &str = "select 1 from proxy_api_key \
+    where key_hash = ? and (docker_id is null or docker_id = ?) and revoked = 0";
+
+/// Where the valid keys come from
+#[derive(Debug, Clone)]
+pub enum ApiKeys {
+    /// the keys of `API_KEYS`, each valid for every daemon
+    Static(Arc<HashSet<String>>),
+    /// the `proxy_api_key` table of key hashes, keys may be limited to a single daemon
+    Database,
+}
+
+impl ApiKeys {
+    pub fn new<I, S>(keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        ApiKeys::Static(Arc::new(keys.into_iter().map(Into::into).collect()))
+    }
+
+    /// the comma separated keys of `API_KEYS` if set, the database otherwise
+    pub fn from_env() -> Self {
+        match env::var("API_KEYS") {
+            Ok(keys) => ApiKeys::new(
+                keys.split(',')
+                    .map(str::trim)
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_owned),
+            ),
+            Err(_) => ApiKeys::Database,
+        }
+    }
+
+    async fn allows(&self, state: &State, key: &str, docker_id: &str) -> tide::Result<bool> {
+        match self {
+            ApiKeys::Static(keys) => Ok(keys.contains(key)),
+            ApiKeys::Database => {
+                let row = sqlx::query(KEY_QUERY)
+                    .bind(key_hash(key))
+                    .bind(docker_id)
+                    .fetch_optional(&state.db)
+                    .await?;
+                Ok(row.is_some())
+            }
+        }
+    }
+}
+
+/// the hex sha-256 of `key`, the database never holds the keys themselves
+fn key_hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// the key of a `Bearer` authorization header
+fn bearer<S>(req: &Request<S>) -> Option<&str> {
+    let value = req.header(headers::AUTHORIZATION)?.as_str();
+    let (scheme, key) = value.split_once(' ')?;
+    Some(key.trim()).filter(|key| scheme.eq_ignore_ascii_case("bearer") && !key.is_empty())
+}
+
+fn unauthorized(reason: &str) -> Response {
+    Response::builder(StatusCode::Unauthorized)
+        .header(headers::WWW_AUTHENTICATE, "Bearer")
+        .body(json!({ "error": reason }))
+        .build()
+}
+
+#[tide::utils::async_trait]
+impl Middleware<State> for ApiKeys {
+    async fn handle(&self, request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let docker_id = request.param("docker").unwrap_or_default().to_owned();
+        let key = match bearer(&request) {
+            Some(key) => key.to_owned(),
+            None => return Ok(unauthorized("missing api key")),
+        };
+        if !self.allows(request.state(), &key, &docker_id).await? {
+            log::warn!("api key rejected for docker {}", docker_id);
+            return Ok(unauthorized("invalid api key"));
+        }
+        Ok(next.run(request).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test keys being checked on the guarded routes only
+    #[async_std::test]
+    async fn api_key_accepted_and_rejected() {
+        let mut app = tide::with_state(State::for_test());
+        app.at("/health").get(|_| async { Ok("ok") });
+        app.at("/docker/:docker/info")
+            .with(ApiKeys::new(vec!["k-1", "k-2"]))
+            .get(|_| async { Ok("info") });
+
+        let send = |path: &str, authorization: Option<&str>| {
+            let mut request =
+                http_types::Request::get(format!("http://localhost{}", path).as_str());
+            if let Some(authorization) = authorization {
+                request.insert_header(headers::AUTHORIZATION, authorization);
+            }
+            app.respond::<_, http_types::Response>(request)
+        };
+
+        let response = send("/docker/7/info", Some("Bearer k-2")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let response = send("/docker/7/info", Some("bearer k-1")).await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        for authorization in &[None, Some("Bearer k-3"), Some("Basic k-1"), Some("Bearer ")] {
+            let response = send("/docker/7/info", *authorization).await.unwrap();
+            assert_eq!(response.status(), 401, "{:?}", authorization);
+            assert_eq!(response.header(headers::WWW_AUTHENTICATE).unwrap(), "Bearer");
+        }
+
+        let response = send("/health", None).await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    /// Test the hash keys are looked up by in the database
+    #[test]
+    fn api_key_hash() {
+        assert_eq!(
+            key_hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Short lived cache of the daemon each docker id resolves to.
+
+use std::{
+    collections::HashMap,
+    env,
+    future::Future,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use http_types::Url;
+use sqlx::MySqlPool;
+use url::Host;
+
+use crate::{docker::docker::Docker, errors::Error, DockerDaemonInfo, State};
+
+/// the daemon registered for a docker id
+const DAEMON_QUERY: &str = "select host_ip, docker_port from host_docker_info where host_id = ?";
+
+/// A resolved daemon: its url and the api version negotiated with it, if it told one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Daemon {
+    pub url: Url,
+    pub api_version: Option<String>,
+}
+
+/// api version negotiated with the daemon of a request, kept next to its url in the
+/// request extensions
+#[derive(Debug, Clone)]
+pub struct ApiVersion(pub String);
+
+#[derive(Debug)]
+pub struct DaemonCache {
+    ttl: Duration,
+    daemons: RwLock<HashMap<String, (Instant, Daemon)>>,
+}
+
+impl DaemonCache {
+    pub fn new(ttl: Duration) -> Self {
+        DaemonCache {
+            ttl,
+            daemons: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// `DAEMON_CACHE_SECONDS` overrides the default of a minute, 0 disables caching
+    pub fn from_env() -> Self {
+        let secs = env::var("DAEMON_CACHE_SECONDS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60);
+        DaemonCache::new(Duration::from_secs(secs))
+    }
+
+    /// the cached daemon of `id`, or the result of `lookup` which is cached if it succeeds.
+    /// A failed lookup drops whatever was cached for `id`.
+    pub async fn resolve<F, Fut, E>(&self, id: &str, lookup: F) -> Result<Daemon, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Daemon, E>>,
+    {
+        if let Some(daemon) = self.get(id) {
+            return Ok(daemon);
+        }
+        match lookup().await {
+            Ok(daemon) => {
+                if self.ttl > Duration::from_secs(0) {
+                    let mut daemons = self.daemons.write().unwrap();
+                    daemons.insert(id.to_owned(), (Instant::now(), daemon.clone()));
+                }
+                Ok(daemon)
+            }
+            Err(e) => {
+                self.invalidate(id);
+                Err(e)
+            }
+        }
+    }
+
+    fn get(&self, id: &str) -> Option<Daemon> {
+        let daemons = self.daemons.read().unwrap();
+        daemons
+            .get(id)
+            .filter(|(resolved, _)| resolved.elapsed() < self.ttl)
+            .map(|(_, daemon)| daemon.clone())
+    }
+
+    pub fn invalidate(&self, id: &str) {
+        self.daemons.write().unwrap().remove(id);
+    }
+}
+
+/// The daemon `id` is registered with in `host_docker_info`, unknown ids are a 404. Its
+/// api version is negotiated right away, a daemon that can not tell is used unversioned.
+pub async fn lookup(state: &State, id: &str) -> tide::Result<Daemon> {
+    let url = registered_url(&state.db, id).await?;
+    let mut docker = Docker::with_endpoint(url.clone())?;
+    let api_version = docker
+        .negotiate_api_version(&*state.client)
+        .await
+        .map(str::to_owned);
+    log::debug!("docker {} at {} speaks api {:?}", id, url, api_version);
+    Ok(Daemon { url, api_version })
+}
+
+async fn registered_url(db: &MySqlPool, id: &str) -> tide::Result<Url> {
+    let daemon = sqlx::query_as::<_, DockerDaemonInfo>(DAEMON_QUERY)
+        .bind(id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(Error::DaemonNotFound)?;
+    daemon_url(&daemon.host_ip, daemon.docker_port).map_err(|e| {
+        log::error!("docker {} is registered with a bad address: {}", id, e);
+        tide::Error::from_str(500, format!("docker {} is registered with a bad address", id))
+    })
+}
+
+/// plain http url of a daemon at `host_ip`, which may be an IPv6 address, and `port`
+fn daemon_url(host_ip: &str, port: i32) -> Result<Url, url::ParseError> {
+    let host_ip = host_ip.trim();
+    let host = if host_ip.contains(':') && !host_ip.starts_with('[') {
+        Host::parse(&format!("[{}]", host_ip))?
+    } else {
+        Host::parse(host_ip)?
+    };
+    Url::parse(&format!("http://{}:{}", host, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Test lookups within the ttl hitting the database once, and failures not being cached
+    #[async_std::test]
+    async fn daemon_cache_lookups() {
+        let cache = DaemonCache::new(Duration::from_secs(60));
+        let queries = AtomicUsize::new(0);
+        let query = |fail: bool| {
+            queries.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if fail {
+                    Err("database gone")
+                } else {
+                    Ok(Daemon {
+                        url: Url::parse("http://10.0.0.5:2375").unwrap(),
+                        api_version: Some("1.41".to_owned()),
+                    })
+                }
+            }
+        };
+
+        let first = cache.resolve("7", || query(false)).await.unwrap();
+        let second = cache.resolve("7", || query(false)).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+
+        cache.invalidate("7");
+        assert!(cache.resolve("7", || query(true)).await.is_err());
+        assert!(cache.resolve("7", || query(false)).await.is_ok());
+        assert_eq!(queries.load(Ordering::SeqCst), 3);
+
+        let uncached = DaemonCache::new(Duration::from_secs(0));
+        uncached.resolve("7", || query(false)).await.unwrap();
+        uncached.resolve("7", || query(false)).await.unwrap();
+        assert_eq!(queries.load(Ordering::SeqCst), 5);
+    }
+
+    /// Test registered addresses becoming daemon urls, IPv6 ones bracketed
+    #[test]
+    fn daemon_urls() {
+        let url = daemon_url("10.0.0.5", 2375).unwrap();
+        assert_eq!(url.as_str(), "http://10.0.0.5:2375/");
+        let url = daemon_url("fd00::5", 2375).unwrap();
+        assert_eq!(url.as_str(), "http://[fd00::5]:2375/");
+        assert!(daemon_url("10.0.0.5/24", 2375).is_err());
+        assert!(daemon_url("10.0.0.5", 70000).is_err());
+    }
+}
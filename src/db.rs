@@ -0,0 +1,106 @@
+//! Settings of the MySQL connection pool.
+
+use std::{env, time::Duration};
+
+use sqlx::mysql::MySqlPoolOptions;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbConfig {
+    pub max_connections: u32,
+    /// longest a request waits for a free connection
+    pub acquire_timeout: Duration,
+    /// idle connections are closed after this long, `None` keeps them open
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for DbConfig {
+    /// the defaults of sqlx
+    fn default() -> Self {
+        DbConfig {
+            max_connections: 10,
+            acquire_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
+        }
+    }
+}
+
+impl DbConfig {
+    /// `DB_MAX_CONNS`, `DB_ACQUIRE_TIMEOUT_SECONDS` and `DB_IDLE_TIMEOUT_SECONDS` override
+    /// the defaults, an idle timeout of 0 keeps idle connections open
+    pub fn from_env() -> Self {
+        DbConfig::from_vars(|key| env::var(key).ok())
+    }
+
+    /// the settings of [`DbConfig::from_env`] with the variables looked up by `var`
+    pub fn from_vars<F>(vars: F) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut config = DbConfig::default();
+        if let Some(max) = var(&vars, "DB_MAX_CONNS") {
+            config.max_connections = max;
+        }
+        if let Some(secs) = var(&vars, "DB_ACQUIRE_TIMEOUT_SECONDS") {
+            config.acquire_timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = var(&vars, "DB_IDLE_TIMEOUT_SECONDS") {
+            config.idle_timeout = Some(secs).filter(|s| *s > 0).map(Duration::from_secs);
+        }
+        config
+    }
+
+    pub fn options(&self) -> MySqlPoolOptions {
+        MySqlPoolOptions::new()
+            .max_connections(self.max_connections)
+            .connect_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout)
+    }
+}
+
+fn var<T, F>(vars: &F, key: &str) -> Option<T>
+where
+    T: std::str::FromStr,
+    F: Fn(&str) -> Option<String>,
+{
+    let value = vars(key)?;
+    let parsed = value.trim().parse().ok();
+    if parsed.is_none() {
+        log::warn!("ignore invalid {}: {}", key, value);
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Test the pool settings read from the variables, invalid values keep the defaults
+    #[test]
+    fn db_config_from_vars() {
+        let vars: HashMap<&str, &str> = [
+            ("DB_MAX_CONNS", "40"),
+            ("DB_ACQUIRE_TIMEOUT_SECONDS", "5"),
+            ("DB_IDLE_TIMEOUT_SECONDS", "0"),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let config = DbConfig::from_vars(|key| vars.get(key).map(|v| v.to_string()));
+        assert_eq!(config.max_connections, 40);
+        assert_eq!(config.acquire_timeout, Duration::from_secs(5));
+        assert_eq!(config.idle_timeout, None);
+
+        let vars: HashMap<&str, &str> = [
+            ("DB_MAX_CONNS", "many"),
+            ("DB_IDLE_TIMEOUT_SECONDS", "120"),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let config = DbConfig::from_vars(|key| vars.get(key).map(|v| v.to_string()));
+        assert_eq!(config.max_connections, DbConfig::default().max_connections);
+        assert_eq!(config.acquire_timeout, DbConfig::default().acquire_timeout);
+        assert_eq!(config.idle_timeout, Some(Duration::from_secs(120)));
+    }
+}
@@ -10,13 +10,16 @@ use serde_json::{json, Map, Value};
 use url::form_urlencoded;
 
 use crate::docker::{
-    docker::Docker,
+    docker::{
+        serialize_filters, Docker, EventFilter, EventFilterType, EventsOptions, QueryOptions,
+    },
     image::Config,
     network::{NetworkInfo, NetworkSettings},
+    stream::{self, StreamType},
 };
 
 #[cfg(feature = "chrono")]
-use crate::datetime::datetime_from_unix_timestamp;
+use crate::docker::datetime::{datetime_from_rfc3339, datetime_from_unix_timestamp};
 #[cfg(feature = "chrono")]
 use chrono::{DateTime, Utc};
 
@@ -49,7 +52,17 @@ impl<'docker> Container<'docker> {
     ///
     /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ContainerInspect>
     pub fn inspect(&self) -> Result<Request, Error> {
-        self.docker.get(&format!("/containers/{}/json", self.id))
+        self.inspect_with_size(false)
+    }
+
+    /// Inspects the container, with `size` the details include `SizeRw` and `SizeRootFs`
+    pub fn inspect_with_size(&self, size: bool) -> Result<Request, Error> {
+        if size {
+            self.docker
+                .get(&format!("/containers/{}/json?size=true", self.id))
+        } else {
+            self.docker.get(&format!("/containers/{}/json", self.id))
+        }
     }
 
     /// Returns a `top` view of information about the container process
@@ -78,15 +91,45 @@ impl<'docker> Container<'docker> {
         self.docker.get(&path.join("?"))
     }
 
+    /// Past `start` events of the container up to the unix timestamp `until`, newline
+    /// delimited. See [`generation_since`] for turning them into a logs `since`.
+    pub fn start_events(&self, until: u64) -> Result<Request, Error> {
+        let opts = EventsOptions::builder()
+            .since(&0)
+            .until(&until)
+            .filter(vec![
+                EventFilter::Type(EventFilterType::Container),
+                EventFilter::Container(self.id.clone()),
+                EventFilter::Event("start".to_owned()),
+            ])
+            .build();
+        self.docker.events(&opts)
+    }
+
     /// Attaches a multiplexed TCP stream to the container that can be used to read Stdout, Stderr and write Stdin.
-    pub fn attach(&self) -> Result<Request, Error> {
-        self.docker.post(
-            &format!(
-                "/containers/{}/attach?stream=1&stdout=1&stderr=1&stdin=1",
-                self.id
-            ),
-            None,
-        )
+    ///
+    /// `detach_keys` overrides the key sequence for detaching, e.g. `ctrl-a,ctrl-b`, the daemon
+    /// default is used when it is `None`.
+    pub fn attach(&self, detach_keys: Option<&str>) -> Result<Request, Error> {
+        let mut path = format!(
+            "/containers/{}/attach?stream=1&stdout=1&stderr=1&stdin=1",
+            self.id
+        );
+        if let Some(keys) = detach_keys {
+            if !valid_detach_keys(keys) {
+                return Err(Error::from_str(
+                    http_types::StatusCode::BadRequest,
+                    format!("invalid detach keys {:?}", keys),
+                ));
+            }
+            path.push('&');
+            path.push_str(
+                &form_urlencoded::Serializer::new(String::new())
+                    .append_pair("detachKeys", keys)
+                    .finish(),
+            );
+        }
+        self.docker.post(&path, None)
     }
 
     /// Returns a set of changes made to the container instance
@@ -110,6 +153,24 @@ impl<'docker> Container<'docker> {
         self.docker.get(&format!("/containers/{}/stats", self.id))
     }
 
+    /// Returns a single stats sample, decodable into [`Stats`]
+    ///
+    /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ContainerStats>
+    pub fn stats_once(&self) -> Result<Request, Error> {
+        self.docker
+            .get(&format!("/containers/{}/stats?stream=false", self.id))
+    }
+
+    /// Returns a single stats sample without waiting for the second read the daemon makes
+    /// to fill `precpu_stats`, which is left zeroed. The cpu percent can not be computed
+    /// from such a sample alone, it takes a [`cpu_percent`] against an earlier one.
+    ///
+    /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ContainerStats>
+    pub fn stats_oneshot(&self) -> Result<Request, Error> {
+        self.docker
+            .get(&format!("/containers/{}/stats?stream=false&one-shot=true", self.id))
+    }
+
     /// Start the container instance
     ///
     /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ContainerStart>
@@ -192,8 +253,20 @@ impl<'docker> Container<'docker> {
     ///
     /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ContainerWait>
     pub fn wait(&self) -> Result<Request, Error> {
-        self.docker
-            .post(&format!("/containers/{}/wait", self.id), None)
+        self.wait_for(None)
+    }
+
+    /// Wait until the container reaches `condition`, the daemon default is `not-running`.
+    /// The response is a [`WaitResponse`].
+    ///
+    /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ContainerWait>
+    pub fn wait_for(&self, condition: Option<WaitCondition>) -> Result<Request, Error> {
+        let mut path = format!("/containers/{}/wait", self.id);
+        if let Some(condition) = condition {
+            path.push_str("?condition=");
+            path.push_str(condition.as_str());
+        }
+        self.docker.post(&path, None)
     }
 
     /// Delete the container instance
@@ -205,7 +278,7 @@ impl<'docker> Container<'docker> {
         self.docker.delete(&format!("/containers/{}", self.id))
     }
 
-    /// Delete the container instance (todo: force/v)
+    /// Delete the container instance
     ///
     /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ContainerRemove>
     pub fn remove(&self, opts: RmContainerOptions) -> Result<Request, Error> {
@@ -245,6 +318,18 @@ impl<'docker> Container<'docker> {
         self.docker.get(&endpoint)
     }
 
+    /// Stat `path` in the container without downloading it, the answer has no body but
+    /// an `X-Docker-Container-Path-Stat` header, see [`PathStat::from_header`]
+    ///
+    /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ContainerArchiveInfo>
+    pub fn stat_path(&self, path: &str) -> Result<Request, Error> {
+        let path_arg = form_urlencoded::Serializer::new(String::new())
+            .append_pair("path", path)
+            .finish();
+        self.docker
+            .head(&format!("/containers/{}/archive?{}", self.id, path_arg))
+    }
+
     /// Copy a tarball (see `body`) to the container.
     ///
     /// The tarball will be copied to the container and extracted at the given location (see `path`).
@@ -301,27 +386,84 @@ impl<'docker> Containers<'docker> {
         let body: Body = opts.serialize()?.into();
         let mut path = vec!["/containers/create".to_owned()];
 
+        let mut query = form_urlencoded::Serializer::new(String::new());
         if let Some(ref name) = opts.name {
-            path.push(
-                form_urlencoded::Serializer::new(String::new())
-                    .append_pair("name", name)
-                    .finish(),
-            );
+            query.append_pair("name", name);
+        }
+        if let Some(ref platform) = opts.platform {
+            query.append_pair("platform", platform);
+        }
+        let query = query.finish();
+        if !query.is_empty() {
+            path.push(query);
         }
 
         self.docker.post(
             &path.join("?"),
-            Some((body, Mime::from("application/json"))),
+            Some((body, crate::docker::json())),
         )
     }
 }
 
+/// Logs `since` of the current run of a container: the time of the last `start` event
+/// in the newline delimited `events`, with nanosecond precision. `None` without one.
+pub fn generation_since(events: &[u8]) -> Option<String> {
+    serde_json::Deserializer::from_slice(events)
+        .into_iter::<Value>()
+        .filter_map(|event| event.ok())
+        .filter(|event| event["Action"] == "start" || event["status"] == "start")
+        .filter_map(|event| event["timeNano"].as_u64())
+        .max()
+        .map(|nanos| format!("{}.{:09}", nanos / 1_000_000_000, nanos % 1_000_000_000))
+}
+
+/// Whether `keys` is a detach sequence the daemon accepts: comma separated single
+/// characters or `ctrl-<key>` with `<key>` one of `a-z @ [ \ ] ^ _`
+pub fn valid_detach_keys(keys: &str) -> bool {
+    keys.split(',').all(|key| {
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(_), None) => true,
+            _ => match key.strip_prefix("ctrl-") {
+                Some(ctrl) => {
+                    ctrl.len() == 1
+                        && ctrl
+                            .chars()
+                            .all(|c| c.is_ascii_lowercase() || "@[\\]^_".contains(c))
+                }
+                None => false,
+            },
+        }
+    })
+}
+
+/// Whether `name` is a container name the daemon accepts, `[a-zA-Z0-9][a-zA-Z0-9_.-]+`
+pub fn valid_container_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphanumeric() => {
+            let rest = chars.as_str();
+            !rest.is_empty()
+                && rest
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c))
+        }
+        _ => false,
+    }
+}
+
 /// Options for filtering container list results
 #[derive(Default, Debug)]
 pub struct ContainerListOptions {
     params: HashMap<&'static str, String>,
 }
 
+impl QueryOptions for ContainerListOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
 impl ContainerListOptions {
     /// return a new instance of a builder for options
     pub fn builder() -> ContainerListOptionsBuilder {
@@ -330,15 +472,7 @@ impl ContainerListOptions {
 
     /// serialize options as a string. returns None if no options are defined
     pub fn serialize(&self) -> Option<String> {
-        if self.params.is_empty() {
-            None
-        } else {
-            Some(
-                form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(&self.params)
-                    .finish(),
-            )
-        }
+        QueryOptions::serialize(self)
     }
 }
 
@@ -348,6 +482,31 @@ pub enum ContainerFilter {
     Status(String),
     LabelName(String),
     Label(String, String),
+    Name(String),
+    /// containers created from an image, given by name, `name:tag` or id
+    Ancestor(String),
+    Health(HealthStatus),
+}
+
+/// Health of a container with a healthcheck, `none` for containers without one
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Starting,
+    Healthy,
+    Unhealthy,
+    None,
+}
+
+impl HealthStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthStatus::Starting => "starting",
+            HealthStatus::Healthy => "healthy",
+            HealthStatus::Unhealthy => "unhealthy",
+            HealthStatus::None => "none",
+        }
+    }
 }
 
 /// Builder interface for `ContainerListOptions`
@@ -358,19 +517,20 @@ pub struct ContainerListOptionsBuilder {
 
 impl ContainerListOptionsBuilder {
     pub fn filter(&mut self, filters: Vec<ContainerFilter>) -> &mut Self {
-        let mut param = HashMap::new();
+        let mut param: HashMap<&str, Vec<String>> = HashMap::new();
         for f in filters {
-            match f {
-                ContainerFilter::ExitCode(c) => param.insert("exit", vec![c.to_string()]),
-                ContainerFilter::Status(s) => param.insert("status", vec![s]),
-                ContainerFilter::LabelName(n) => param.insert("label", vec![n]),
-                ContainerFilter::Label(n, v) => param.insert("label", vec![format!("{}={}", n, v)]),
+            let (key, value) = match f {
+                ContainerFilter::ExitCode(c) => ("exit", c.to_string()),
+                ContainerFilter::Status(s) => ("status", s),
+                ContainerFilter::LabelName(n) => ("label", n),
+                ContainerFilter::Label(n, v) => ("label", format!("{}={}", n, v)),
+                ContainerFilter::Name(n) => ("name", n),
+                ContainerFilter::Ancestor(i) => ("ancestor", i),
+                ContainerFilter::Health(h) => ("health", h.as_str().to_owned()),
             };
+            param.entry(key).or_default().push(value);
         }
-        // structure is a a json encoded object mapping string keys to a list
-        // of string values
-        self.params
-            .insert("filters", serde_json::to_string(&param).unwrap());
+        self.params.insert("filters", serialize_filters(&param));
         self
     }
 
@@ -379,6 +539,12 @@ impl ContainerListOptionsBuilder {
         self
     }
 
+    /// return only the `limit` most recently created containers
+    pub fn limit(&mut self, limit: u64) -> &mut Self {
+        self.params.insert("limit", limit.to_string());
+        self
+    }
+
     pub fn since(&mut self, since: &str) -> &mut Self {
         self.params.insert("since", since.to_owned());
         self
@@ -405,6 +571,8 @@ impl ContainerListOptionsBuilder {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ContainerOptions {
     pub name: Option<String>,
+    /// platform of the image, `os[/arch[/variant]]`
+    pub platform: Option<String>,
     params: HashMap<String, Value>,
 }
 
@@ -454,6 +622,31 @@ impl ContainerOptions {
         body
     }
 
+    /// resource limits requested in `HostConfig`
+    pub fn resources(&self) -> Resources {
+        let body = self.to_json();
+        let host_config = &body["HostConfig"];
+        Resources {
+            memory: host_config["Memory"].as_u64(),
+            cpu_shares: host_config["CpuShares"].as_i64(),
+            nano_cpus: host_config["NanoCpus"].as_u64(),
+            runtime: host_config["Runtime"].as_str().map(str::to_owned),
+        }
+    }
+
+    /// restart policy of the create request, an error names an unknown policy
+    pub fn restart_policy(&self) -> Result<Option<RestartPolicy>, String> {
+        let body = self.to_json();
+        let policy = &body["HostConfig"]["RestartPolicy"];
+        if policy.is_null() {
+            return Ok(None);
+        }
+        let policy: RestartPolicy =
+            serde_json::from_value(policy.clone()).map_err(|e| e.to_string())?;
+        policy.policy()?;
+        Ok(Some(policy))
+    }
+
     pub fn parse_from<'a, K, V>(&self, params: &'a HashMap<K, V>, body: &mut Value)
     where
         &'a HashMap<K, V>: IntoIterator,
@@ -467,9 +660,19 @@ impl ContainerOptions {
     }
 }
 
+/// Resource limits of a container create request
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Resources {
+    pub memory: Option<u64>,
+    pub cpu_shares: Option<i64>,
+    pub nano_cpus: Option<u64>,
+    pub runtime: Option<String>,
+}
+
 #[derive(Default)]
 pub struct ContainerOptionsBuilder {
     name: Option<String>,
+    platform: Option<String>,
     params: HashMap<&'static str, Value>,
 }
 
@@ -478,7 +681,11 @@ impl ContainerOptionsBuilder {
         let mut params = HashMap::new();
 
         params.insert("Image", Value::String(image.to_owned()));
-        ContainerOptionsBuilder { name: None, params }
+        ContainerOptionsBuilder {
+            name: None,
+            platform: None,
+            params,
+        }
     }
 
     pub fn name(&mut self, name: &str) -> &mut Self {
@@ -486,6 +693,19 @@ impl ContainerOptionsBuilder {
         self
     }
 
+    /// Platform of the image to create the container from, e.g. `linux/arm64`
+    pub fn platform(&mut self, platform: &str) -> &mut Self {
+        self.platform = Some(platform.to_owned());
+        self
+    }
+
+    /// OCI runtime of the container, e.g. `runc`, `nvidia` or `kata`. It has to be
+    /// configured on the daemon.
+    pub fn runtime(&mut self, runtime: &str) -> &mut Self {
+        self.params.insert("HostConfig.Runtime", json!(runtime));
+        self
+    }
+
     /// Specify the working dir (corresponds to the `-w` docker cli argument)
     pub fn working_dir(&mut self, working_dir: &str) -> &mut Self {
         self.params.insert("WorkingDir", json!(working_dir));
@@ -498,6 +718,41 @@ impl ContainerOptionsBuilder {
         self
     }
 
+    /// Add a single bind mount, `/some/host/path:/some/container/path[:ro]` or
+    /// `volume-name:/some/container/path`, keeping the earlier ones
+    pub fn volume(&mut self, bind_spec: &str) -> &mut Self {
+        self.push_param("HostConfig.Binds", json!(bind_spec))
+    }
+
+    /// Add a structured mount of `source` at `target` in the container. `source` is a host
+    /// path for `bind`, a volume name for `volume` and ignored for `tmpfs`.
+    pub fn mount(
+        &mut self,
+        source: &str,
+        target: &str,
+        read_only: bool,
+        typ: MountType,
+    ) -> &mut Self {
+        let mut mount = json!({
+            "Target": target,
+            "Type": typ.as_str(),
+            "ReadOnly": read_only,
+        });
+        if typ != MountType::Tmpfs {
+            mount["Source"] = json!(source);
+        }
+        self.push_param("HostConfig.Mounts", mount)
+    }
+
+    /// append `value` to the list parameter `key`
+    fn push_param(&mut self, key: &'static str, value: Value) -> &mut Self {
+        let list = self.params.entry(key).or_insert_with(|| json!([]));
+        if let Some(list) = list.as_array_mut() {
+            list.push(value);
+        }
+        self
+    }
+
     /// enable all exposed ports on the container to be mapped to random, available, ports on the host
     pub fn publish_all_ports(&mut self) -> &mut Self {
         self.params
@@ -505,41 +760,46 @@ impl ContainerOptionsBuilder {
         self
     }
 
+    /// Publish `srcport/protocol` of the container on `hostport` of all host interfaces
     pub fn expose(&mut self, srcport: u32, protocol: &str, hostport: u32) -> &mut Self {
-        let mut exposedport: HashMap<String, String> = HashMap::new();
-        exposedport.insert("HostPort".to_string(), hostport.to_string());
+        self.bind_port(srcport, protocol, json!({ "HostPort": hostport.to_string() }))
+    }
+
+    /// Publish `srcport/protocol` of the container on `hostport` of the host address `host_ip`.
+    /// The same container port may be bound on several addresses.
+    pub fn expose_on(
+        &mut self,
+        srcport: u32,
+        protocol: &str,
+        host_ip: &str,
+        hostport: u32,
+    ) -> &mut Self {
+        self.bind_port(
+            srcport,
+            protocol,
+            json!({ "HostIp": host_ip, "HostPort": hostport.to_string() }),
+        )
+    }
 
-        // The idea here is to go thought the 'old' port binds and to apply them to the local
-        // 'port_bindings' variable, add the bind we want and replace the 'old' value
-        let mut port_bindings: HashMap<String, Value> = HashMap::new();
-        for (key, val) in self
+    fn bind_port(&mut self, srcport: u32, protocol: &str, binding: Value) -> &mut Self {
+        let port = format!("{}/{}", srcport, protocol);
+
+        // keep the earlier bindings, of this port as well as of the others
+        let mut port_bindings = self
             .params
             .get("HostConfig.PortBindings")
-            .unwrap_or(&json!(null))
-            .as_object()
-            .unwrap_or(&Map::new())
-            .iter()
-        {
-            port_bindings.insert(key.to_string(), json!(val));
+            .and_then(Value::as_object)
+            .cloned()
+            .unwrap_or_default();
+        let bindings = port_bindings.entry(port).or_insert_with(|| json!([]));
+        if let Some(bindings) = bindings.as_array_mut() {
+            bindings.push(binding);
         }
-        port_bindings.insert(
-            format!("{}/{}", srcport, protocol),
-            json!(vec![exposedport]),
-        );
-
         self.params
-            .insert("HostConfig.PortBindings", json!(port_bindings));
-
-        // Replicate the port bindings over to the exposed ports config
-        let mut exposed_ports: HashMap<String, Value> = HashMap::new();
-        let empty_config: HashMap<String, Value> = HashMap::new();
-        for key in port_bindings.keys() {
-            exposed_ports.insert(key.to_string(), json!(empty_config));
-        }
-
-        self.params.insert("ExposedPorts", json!(exposed_ports));
+            .insert("HostConfig.PortBindings", Value::Object(port_bindings));
 
-        self
+        // every bound port has to be exposed as well
+        self.publish(srcport, protocol)
     }
 
     /// Publish a port in the container without assigning a port on the host
@@ -667,6 +927,18 @@ impl ContainerOptionsBuilder {
         self
     }
 
+    /// Append the entries of a `.env` style file to `Env`, like `docker run --env-file`.
+    /// See [`parse_env_file`] for the format.
+    pub fn env_file<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<&mut Self> {
+        let content = std::fs::read_to_string(path)?;
+        let entries = parse_env_file(&content)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        for entry in entries {
+            self.push_param("Env", json!(entry));
+        }
+        Ok(self)
+    }
+
     pub fn cmd(&mut self, cmds: Vec<&str>) -> &mut Self {
         self.params.insert("Cmd", json!(cmds));
         self
@@ -723,11 +995,53 @@ impl ContainerOptionsBuilder {
     }
 
     /// Timeout to stop a container. Only seconds are counted. Default is 10s
+    ///
+    /// This is how long the daemon waits before killing the container on stop when the
+    /// stop request itself carries no timeout. A `Duration` can never be negative.
     pub fn stop_timeout(&mut self, timeout: Duration) -> &mut Self {
         self.params.insert("StopTimeout", json!(timeout.as_secs()));
         self
     }
 
+    /// Check run to determine whether the container is healthy.
+    ///
+    /// `test` is `["CMD", args...]` to run a command directly, `["CMD-SHELL", command]` to run
+    /// it with the default shell or `["NONE"]` to disable a healthcheck of the image. The
+    /// durations are sent in nanoseconds, a zero duration means the daemon default.
+    pub fn healthcheck(
+        &mut self,
+        test: Vec<&str>,
+        interval: Duration,
+        timeout: Duration,
+        retries: u64,
+        start_period: Duration,
+    ) -> &mut Self {
+        self.params.insert(
+            "Healthcheck",
+            json!({
+                "Test": test,
+                "Interval": interval.as_nanos() as u64,
+                "Timeout": timeout.as_nanos() as u64,
+                "Retries": retries,
+                "StartPeriod": start_period.as_nanos() as u64,
+            }),
+        );
+        self
+    }
+
+    /// Path to the cgroup under which the container's cgroup is created, e.g. a systemd slice
+    pub fn cgroup_parent(&mut self, parent: &str) -> &mut Self {
+        self.params.insert("HostConfig.CgroupParent", json!(parent));
+        self
+    }
+
+    /// Cgroup namespace of the container. The daemon default depends on the cgroup version.
+    pub fn cgroupns_mode(&mut self, mode: CgroupnsMode) -> &mut Self {
+        self.params
+            .insert("HostConfig.CgroupnsMode", json!(mode.as_str()));
+        self
+    }
+
     pub fn userns_mode(&mut self, mode: &str) -> &mut Self {
         self.params.insert("HostConfig.UsernsMode", json!(mode));
         self
@@ -746,6 +1060,7 @@ impl ContainerOptionsBuilder {
     pub fn build(&self) -> ContainerOptions {
         ContainerOptions {
             name: self.name.clone(),
+            platform: self.platform.clone(),
             params: self
                 .params
                 .clone()
@@ -756,12 +1071,158 @@ impl ContainerOptionsBuilder {
     }
 }
 
+/// Cgroup namespace mode of a container
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CgroupnsMode {
+    Host,
+    Private,
+}
+
+impl CgroupnsMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CgroupnsMode::Host => "host",
+            CgroupnsMode::Private => "private",
+        }
+    }
+}
+
+impl std::str::FromStr for CgroupnsMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "host" => Ok(CgroupnsMode::Host),
+            "private" => Ok(CgroupnsMode::Private),
+            _ => Err(format!(
+                "invalid cgroupns mode {:?}, expected \"host\" or \"private\"",
+                s
+            )),
+        }
+    }
+}
+
+/// `KEY=VALUE` entries of a `.env` style file, read like `docker run --env-file` does:
+/// blank lines and lines starting with `#` are skipped, leading whitespace is dropped and
+/// the value is kept verbatim, quotes included.
+pub fn parse_env_file(content: &str) -> Result<Vec<String>, String> {
+    let mut entries = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, _) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY=VALUE, got {:?}", number + 1, line))?;
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(format!("line {}: invalid variable name {:?}", number + 1, key));
+        }
+        entries.push(line.to_owned());
+    }
+    Ok(entries)
+}
+
+/// Kinds of `HostConfig.Mounts` entries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MountType {
+    Bind,
+    Volume,
+    Tmpfs,
+}
+
+impl MountType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MountType::Bind => "bind",
+            MountType::Volume => "volume",
+            MountType::Tmpfs => "tmpfs",
+        }
+    }
+}
+
+impl std::str::FromStr for MountType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bind" => Ok(MountType::Bind),
+            "volume" => Ok(MountType::Volume),
+            "tmpfs" => Ok(MountType::Tmpfs),
+            _ => Err(format!(
+                "invalid mount type {:?}, expected \"bind\", \"volume\" or \"tmpfs\"",
+                s
+            )),
+        }
+    }
+}
+
+/// Names of the restart policies the daemon knows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestartPolicyName {
+    No,
+    Always,
+    UnlessStopped,
+    OnFailure,
+}
+
+impl RestartPolicyName {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestartPolicyName::No => "no",
+            RestartPolicyName::Always => "always",
+            RestartPolicyName::UnlessStopped => "unless-stopped",
+            RestartPolicyName::OnFailure => "on-failure",
+        }
+    }
+}
+
+impl std::str::FromStr for RestartPolicyName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            // inspect reports an empty name for containers created without a policy
+            "" | "no" => Ok(RestartPolicyName::No),
+            "always" => Ok(RestartPolicyName::Always),
+            "unless-stopped" => Ok(RestartPolicyName::UnlessStopped),
+            "on-failure" => Ok(RestartPolicyName::OnFailure),
+            _ => Err(format!(
+                "invalid restart policy {:?}, expected \"no\", \"always\", \"unless-stopped\" or \"on-failure\"",
+                s
+            )),
+        }
+    }
+}
+
+/// `HostConfig.RestartPolicy` of create and inspect
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct RestartPolicy {
+    pub name: String,
+    /// only used by `on-failure`
+    #[serde(default)]
+    pub maximum_retry_count: u64,
+}
+
+impl RestartPolicy {
+    pub fn policy(&self) -> Result<RestartPolicyName, String> {
+        self.name.parse()
+    }
+}
+
 /// Options for controlling log request results
 #[derive(Default, Debug)]
 pub struct LogsOptions {
     params: HashMap<&'static str, String>,
 }
 
+impl QueryOptions for LogsOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
 impl LogsOptions {
     /// return a new instance of a builder for options
     pub fn builder() -> LogsOptionsBuilder {
@@ -770,15 +1231,7 @@ impl LogsOptions {
 
     /// serialize options as a string. returns None if no options are defined
     pub fn serialize(&self) -> Option<String> {
-        if self.params.is_empty() {
-            None
-        } else {
-            Some(
-                form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(&self.params)
-                    .finish(),
-            )
-        }
+        QueryOptions::serialize(self)
     }
 }
 
@@ -831,6 +1284,28 @@ impl LogsOptionsBuilder {
         self
     }
 
+    #[cfg(feature = "chrono")]
+    pub fn until<Tz>(&mut self, timestamp: &chrono::DateTime<Tz>) -> &mut Self
+    where
+        Tz: chrono::TimeZone,
+    {
+        self.params
+            .insert("until", timestamp.timestamp().to_string());
+        self
+    }
+
+    #[cfg(not(feature = "chrono"))]
+    pub fn until(&mut self, timestamp: i64) -> &mut Self {
+        self.params.insert("until", timestamp.to_string());
+        self
+    }
+
+    /// since as a unix timestamp with fractional nanoseconds, e.g. `1620000000.000000001`
+    pub fn since_nanos(&mut self, timestamp: &str) -> &mut Self {
+        self.params.insert("since", timestamp.to_owned());
+        self
+    }
+
     pub fn build(&self) -> LogsOptions {
         LogsOptions {
             params: self.params.clone(),
@@ -844,6 +1319,12 @@ pub struct RmContainerOptions {
     params: HashMap<&'static str, String>,
 }
 
+impl QueryOptions for RmContainerOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
 impl RmContainerOptions {
     /// return a new instance of a builder for options
     pub fn builder() -> RmContainerOptionsBuilder {
@@ -852,15 +1333,7 @@ impl RmContainerOptions {
 
     /// serialize options as a string. returns None if no options are defined
     pub fn serialize(&self) -> Option<String> {
-        if self.params.is_empty() {
-            None
-        } else {
-            Some(
-                form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(&self.params)
-                    .finish(),
-            )
-        }
+        QueryOptions::serialize(self)
     }
 }
 
@@ -881,6 +1354,12 @@ impl RmContainerOptionsBuilder {
         self
     }
 
+    /// remove the network link of the container instead of the container
+    pub fn link(&mut self, l: bool) -> &mut Self {
+        self.params.insert("link", l.to_string());
+        self
+    }
+
     pub fn build(&self) -> RmContainerOptions {
         RmContainerOptions {
             params: self.params.clone(),
@@ -904,12 +1383,32 @@ pub struct ContainerInfo {
     pub labels: HashMap<String, String>,
     pub names: Vec<String>,
     pub ports: Vec<Port>,
-    pub state: String,
+    pub state: ContainerStatus,
     pub status: String,
     pub size_rw: Option<u64>,
     pub size_root_fs: Option<u64>,
 }
 
+/// One container of the list. Lists some daemons send `null` for are left empty.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerSummary {
+    pub id: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub names: Vec<String>,
+    pub image: String,
+    pub state: ContainerStatus,
+    /// human readable status, e.g. `Up 2 hours (healthy)`
+    pub status: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub ports: Vec<Port>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub labels: HashMap<String, String>,
+    /// only listed with `size=true`
+    pub size_rw: Option<u64>,
+    pub size_root_fs: Option<u64>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ContainerDetails {
@@ -917,6 +1416,7 @@ pub struct ContainerDetails {
     pub args: Vec<String>,
     pub config: Config,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub created: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub created: String,
@@ -937,11 +1437,16 @@ pub struct ContainerDetails {
     pub restart_count: u64,
     pub state: State,
     pub mounts: Vec<Mount>,
+    /// only reported when inspecting with size
+    pub size_rw: Option<u64>,
+    pub size_root_fs: Option<u64>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Mount {
+    #[serde(rename = "Type")]
+    pub typ: Option<String>,
     pub source: String,
     pub destination: String,
     pub mode: String,
@@ -955,6 +1460,7 @@ pub struct State {
     pub error: String,
     pub exit_code: u64,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub finished_at: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub finished_at: String,
@@ -965,10 +1471,73 @@ pub struct State {
     pub restarting: bool,
     pub running: bool,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub started_at: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub started_at: String,
-    pub status: String,
+    pub status: ContainerStatus,
+}
+
+/// Status of a container as reported by inspect and list
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContainerStatus {
+    Created,
+    Running,
+    Paused,
+    Restarting,
+    Removing,
+    Exited,
+    Dead,
+    /// a status this crate does not know about yet
+    Unknown(String),
+}
+
+impl ContainerStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContainerStatus::Created => "created",
+            ContainerStatus::Running => "running",
+            ContainerStatus::Paused => "paused",
+            ContainerStatus::Restarting => "restarting",
+            ContainerStatus::Removing => "removing",
+            ContainerStatus::Exited => "exited",
+            ContainerStatus::Dead => "dead",
+            ContainerStatus::Unknown(status) => status,
+        }
+    }
+}
+
+impl From<String> for ContainerStatus {
+    fn from(status: String) -> Self {
+        match status.as_str() {
+            "created" => ContainerStatus::Created,
+            "running" => ContainerStatus::Running,
+            "paused" => ContainerStatus::Paused,
+            "restarting" => ContainerStatus::Restarting,
+            "removing" => ContainerStatus::Removing,
+            "exited" => ContainerStatus::Exited,
+            "dead" => ContainerStatus::Dead,
+            _ => ContainerStatus::Unknown(status),
+        }
+    }
+}
+
+impl Serialize for ContainerStatus {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ContainerStatus {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(String::deserialize(deserializer)?.into())
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -986,8 +1555,8 @@ pub struct HostConfig {
     pub port_bindings: Option<HashMap<String, Vec<HashMap<String, String>>>>,
     pub privileged: bool,
     pub publish_all_ports: bool,
-    pub readonly_rootfs: Option<bool>, /* pub RestartPolicy: ???
-                                        * pub SecurityOpt: Option<???>,
+    pub readonly_rootfs: Option<bool>,
+    pub restart_policy: Option<RestartPolicy>, /* pub SecurityOpt: Option<???>,
                                         * pub Ulimits: Option<???>
                                         * pub VolumesFrom: Option<??/> */
 }
@@ -1002,16 +1571,84 @@ pub struct Port {
     pub typ: String,
 }
 
+/// One stats sample. Fields missing on cgroup v2 hosts or for containers without a
+/// network are left at their defaults rather than failing the whole sample.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Stats {
     pub read: String,
+    #[serde(default)]
     pub networks: HashMap<String, NetworkInfo>,
     pub memory_stats: MemoryStats,
+    #[serde(default)]
     pub blkio_stats: BlkioStats,
     pub cpu_stats: CpuStats,
+    /// the sample before `cpu_stats`, all zero for the first sample
+    pub precpu_stats: Option<CpuStats>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Usage of one stats sample as `docker stats` shows it
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StatsSummary {
+    /// share of one cpu in percent, up to 100 times the online cpus
+    pub cpu_percent: f64,
+    pub mem_percent: f64,
+    /// memory in use in bytes, without the inactive page cache
+    pub mem_usage: u64,
+    pub mem_limit: u64,
+}
+
+impl StatsSummary {
+    /// Summary of `stats`. The cpu usage is the delta to `previous`, the cpu stats of the
+    /// sample before, falling back to the `precpu_stats` of `stats`.
+    pub fn new(stats: &Stats, previous: Option<&CpuStats>) -> Self {
+        let cpu_percent = previous
+            .or_else(|| stats.precpu_stats.as_ref())
+            .map_or(0.0, |previous| cpu_percent(previous, &stats.cpu_stats));
+
+        let memory = &stats.memory_stats;
+        // cgroup v1 reports the inactive cache as total_inactive_file, v2 as inactive_file
+        let inactive = match memory.stats.total_inactive_file {
+            0 => memory.stats.inactive_file,
+            total => total,
+        };
+        let mem_usage = memory.usage.saturating_sub(inactive);
+        let mem_percent = if memory.limit > 0 {
+            mem_usage as f64 / memory.limit as f64 * 100.0
+        } else {
+            0.0
+        };
+        StatsSummary {
+            cpu_percent,
+            mem_percent,
+            mem_usage,
+            mem_limit: memory.limit,
+        }
+    }
+}
+
+/// Cpu usage between two samples in percent of one cpu, 0 when the samples show no
+/// progress, e.g. for the first sample of a stream
+pub fn cpu_percent(previous: &CpuStats, current: &CpuStats) -> f64 {
+    let cpu_delta = current
+        .cpu_usage
+        .total_usage
+        .saturating_sub(previous.cpu_usage.total_usage);
+    let system_delta = current
+        .system_cpu_usage
+        .saturating_sub(previous.system_cpu_usage);
+    if cpu_delta == 0 || system_delta == 0 {
+        return 0.0;
+    }
+    let cpus = current
+        .online_cpus
+        .filter(|cpus| *cpus > 0)
+        .unwrap_or(current.cpu_usage.percpu_usage.len() as u64)
+        .max(1);
+    cpu_delta as f64 / system_delta as f64 * cpus as f64 * 100.0
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MemoryStats {
     pub max_usage: u64,
     pub usage: u64,
@@ -1020,7 +1657,8 @@ pub struct MemoryStats {
     pub stats: MemoryStat,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MemoryStat {
     pub total_pgmajfault: u64,
     pub cache: u64,
@@ -1054,40 +1692,64 @@ pub struct MemoryStat {
     pub total_pgpgin: u64,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CpuStats {
     pub cpu_usage: CpuUsage,
     pub system_cpu_usage: u64,
+    pub online_cpus: Option<u64>,
     pub throttling_data: ThrottlingData,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CpuUsage {
+    /// not reported on cgroup v2 hosts
+    #[serde(deserialize_with = "null_as_default")]
     pub percpu_usage: Vec<u64>,
     pub usage_in_usermode: u64,
     pub total_usage: u64,
     pub usage_in_kernelmode: u64,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ThrottlingData {
     pub periods: u64,
     pub throttled_periods: u64,
     pub throttled_time: u64,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// The daemon sends `null` for every list it has no values for
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BlkioStats {
+    #[serde(deserialize_with = "null_as_default")]
     pub io_service_bytes_recursive: Vec<BlkioStat>,
+    #[serde(deserialize_with = "null_as_default")]
     pub io_serviced_recursive: Vec<BlkioStat>,
+    #[serde(deserialize_with = "null_as_default")]
     pub io_queue_recursive: Vec<BlkioStat>,
+    #[serde(deserialize_with = "null_as_default")]
     pub io_service_time_recursive: Vec<BlkioStat>,
+    #[serde(deserialize_with = "null_as_default")]
     pub io_wait_time_recursive: Vec<BlkioStat>,
+    #[serde(deserialize_with = "null_as_default")]
     pub io_merged_recursive: Vec<BlkioStat>,
+    #[serde(deserialize_with = "null_as_default")]
     pub io_time_recursive: Vec<BlkioStat>,
+    #[serde(deserialize_with = "null_as_default")]
     pub sectors_recursive: Vec<BlkioStat>,
 }
 
+pub(crate) fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BlkioStat {
     pub major: u64,
@@ -1096,6 +1758,39 @@ pub struct BlkioStat {
     pub value: u64,
 }
 
+/// header of a `stat_path` answer holding the base64 encoded `PathStat`
+pub const PATH_STAT_HEADER: &str = "X-Docker-Container-Path-Stat";
+
+/// A file or directory in a container, as stat by `stat_path`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathStat {
+    pub name: String,
+    pub size: i64,
+    /// go `FileMode`: the permission bits and the type in the high bits
+    pub mode: u32,
+    #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
+    pub mtime: DateTime<Utc>,
+    #[cfg(not(feature = "chrono"))]
+    pub mtime: String,
+    /// target of a symlink, empty otherwise
+    #[serde(default)]
+    pub link_target: String,
+}
+
+impl PathStat {
+    /// decode the value of the `X-Docker-Container-Path-Stat` header
+    pub fn from_header(value: &str) -> std::result::Result<PathStat, String> {
+        let json = base64::decode(value.trim()).map_err(|e| e.to_string())?;
+        serde_json::from_slice(&json).map_err(|e| e.to_string())
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & (1 << 31) != 0
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Change {
@@ -1105,8 +1800,11 @@ pub struct Change {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-pub struct Top {
+pub struct TopResult {
+    /// the `ps` column headers
     pub titles: Vec<String>,
+    /// one row per process, in the order of `titles`
+    #[serde(default, deserialize_with = "null_as_default")]
     pub processes: Vec<Vec<String>>,
 }
 
@@ -1114,7 +1812,34 @@ pub struct Top {
 #[serde(rename_all = "PascalCase")]
 pub struct ContainerCreateInfo {
     pub id: String,
-    pub warnings: Option<Vec<String>>,
+    /// older daemons send `null` instead of an empty list
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub warnings: Vec<String>,
+}
+
+/// Complete, non streamed output of `logs` split by stream
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContainerLogs {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl ContainerLogs {
+    /// strip the stream framing of a logs body, payloads are decoded as lossy utf-8
+    pub fn from_bytes(bytes: &[u8]) -> std::io::Result<ContainerLogs> {
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        for frame in stream::decode(bytes)? {
+            match frame.stream {
+                StreamType::Stderr => stderr.extend(frame.data),
+                _ => stdout.extend(frame.data),
+            }
+        }
+        Ok(ContainerLogs {
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        })
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -1122,3 +1847,699 @@ pub struct ContainerCreateInfo {
 pub struct Exit {
     pub status_code: u64,
 }
+
+/// What a container wait waits for
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WaitCondition {
+    NotRunning,
+    NextExit,
+    Removed,
+}
+
+impl WaitCondition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WaitCondition::NotRunning => "not-running",
+            WaitCondition::NextExit => "next-exit",
+            WaitCondition::Removed => "removed",
+        }
+    }
+}
+
+/// Result of a container wait
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct WaitResponse {
+    /// exit code of the container
+    pub status_code: i64,
+    #[serde(default)]
+    pub error: Option<WaitError>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct WaitError {
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_status_deserialize() {
+        let known = [
+            ("created", ContainerStatus::Created),
+            ("running", ContainerStatus::Running),
+            ("paused", ContainerStatus::Paused),
+            ("restarting", ContainerStatus::Restarting),
+            ("removing", ContainerStatus::Removing),
+            ("exited", ContainerStatus::Exited),
+            ("dead", ContainerStatus::Dead),
+        ];
+        for (raw, status) in known.iter() {
+            let parsed: ContainerStatus = serde_json::from_value(json!(raw)).unwrap();
+            assert_eq!(&parsed, status);
+            assert_eq!(serde_json::to_value(&parsed).unwrap(), json!(raw));
+        }
+
+        let parsed: ContainerStatus = serde_json::from_value(json!("hibernating")).unwrap();
+        assert_eq!(parsed, ContainerStatus::Unknown("hibernating".to_owned()));
+    }
+
+    #[test]
+    fn container_options_cgroup() {
+        let options = ContainerOptions::builder("alpine")
+            .cgroup_parent("/system.slice/app.slice")
+            .cgroupns_mode("private".parse().unwrap())
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(
+            body["HostConfig"]["CgroupParent"],
+            json!("/system.slice/app.slice")
+        );
+        assert_eq!(body["HostConfig"]["CgroupnsMode"], json!("private"));
+        assert!("shared".parse::<CgroupnsMode>().is_err());
+    }
+
+    #[test]
+    fn container_logs_from_frames() {
+        let mut bytes = Vec::new();
+        for (stream, data) in &[(1u8, "starting\n"), (2, "warning: low memory\n"), (1, "ready\n")] {
+            bytes.extend_from_slice(&[*stream, 0, 0, 0]);
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(data.as_bytes());
+        }
+        assert_eq!(
+            ContainerLogs::from_bytes(&bytes).unwrap(),
+            ContainerLogs {
+                stdout: "starting\nready\n".to_owned(),
+                stderr: "warning: low memory\n".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn container_options_stop_timeout() {
+        let options = ContainerOptions::builder("alpine")
+            .stop_timeout(Duration::from_millis(30_900))
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(body["StopTimeout"], json!(30));
+    }
+
+    #[test]
+    fn container_options_healthcheck() {
+        let options = ContainerOptions::builder("nginx")
+            .healthcheck(
+                vec!["CMD-SHELL", "curl -f http://localhost/ || exit 1"],
+                Duration::from_secs(30),
+                Duration::from_secs(5),
+                3,
+                Duration::from_secs(10),
+            )
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(
+            body["Healthcheck"],
+            json!({
+                "Test": ["CMD-SHELL", "curl -f http://localhost/ || exit 1"],
+                "Interval": 30_000_000_000u64,
+                "Timeout": 5_000_000_000u64,
+                "Retries": 3,
+                "StartPeriod": 10_000_000_000u64,
+            })
+        );
+    }
+
+    /// Test the serialized restart policy, its inspect round trip and unknown names
+    #[test]
+    fn container_options_restart_policy() {
+        let options = ContainerOptions::builder("alpine")
+            .restart_policy("on-failure", 5)
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(
+            body["HostConfig"]["RestartPolicy"],
+            json!({ "Name": "on-failure", "MaximumRetryCount": 5 })
+        );
+
+        let policy = options.restart_policy().unwrap().unwrap();
+        assert_eq!(policy.policy(), Ok(RestartPolicyName::OnFailure));
+        let inspected: RestartPolicy =
+            serde_json::from_value(json!({ "Name": "on-failure", "MaximumRetryCount": 5 }))
+                .unwrap();
+        assert_eq!(policy, inspected);
+
+        let options = ContainerOptions::builder("alpine")
+            .restart_policy("unless-stopped", 0)
+            .build();
+        let policy = options.restart_policy().unwrap().unwrap();
+        assert_eq!(policy.policy(), Ok(RestartPolicyName::UnlessStopped));
+        assert_eq!(policy.maximum_retry_count, 0);
+
+        let options = ContainerOptions::builder("alpine")
+            .restart_policy("sometimes", 0)
+            .build();
+        assert!(options.restart_policy().is_err());
+        assert_eq!(
+            ContainerOptions::builder("alpine").build().restart_policy(),
+            Ok(None)
+        );
+    }
+
+    /// Test the port bindings and exposed ports of a tcp and an udp port
+    #[test]
+    fn container_options_ports() {
+        let options = ContainerOptions::builder("dns")
+            .expose(80, "tcp", 8080)
+            .expose_on(53, "udp", "127.0.0.1", 5353)
+            .expose_on(53, "udp", "::1", 5353)
+            .publish(9000, "tcp")
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(
+            body["HostConfig"]["PortBindings"],
+            json!({
+                "80/tcp": [{ "HostPort": "8080" }],
+                "53/udp": [
+                    { "HostIp": "127.0.0.1", "HostPort": "5353" },
+                    { "HostIp": "::1", "HostPort": "5353" },
+                ],
+            })
+        );
+        assert_eq!(
+            body["ExposedPorts"],
+            json!({ "80/tcp": {}, "53/udp": {}, "9000/tcp": {} })
+        );
+
+        let options = ContainerOptions::builder("web").publish_all_ports().build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(body["HostConfig"]["PublishAllPorts"], json!(true));
+    }
+
+    /// Test the legacy binds and the structured mounts
+    #[test]
+    fn container_options_mounts() {
+        let options = ContainerOptions::builder("alpine")
+            .volume("/srv/data:/data")
+            .volume("cache:/cache:ro")
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(
+            body["HostConfig"]["Binds"],
+            json!(["/srv/data:/data", "cache:/cache:ro"])
+        );
+
+        let options = ContainerOptions::builder("alpine")
+            .mount("/srv/data", "/data", false, MountType::Bind)
+            .mount("cache", "/cache", true, "volume".parse().unwrap())
+            .mount("", "/tmp", false, MountType::Tmpfs)
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(
+            body["HostConfig"]["Mounts"],
+            json!([
+                { "Source": "/srv/data", "Target": "/data", "Type": "bind", "ReadOnly": false },
+                { "Source": "cache", "Target": "/cache", "Type": "volume", "ReadOnly": true },
+                { "Target": "/tmp", "Type": "tmpfs", "ReadOnly": false },
+            ])
+        );
+        assert!("npipe".parse::<MountType>().is_err());
+    }
+
+    /// Test reading an env file with comments, blank lines and values kept verbatim
+    #[test]
+    fn container_options_env_file() {
+        let dir = std::env::temp_dir().join(format!("beekeeper-env-file-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".env");
+        std::fs::write(
+            &path,
+            "# database\nDB_HOST=db.internal\n\nDB_PASSWORD=\"s3cr=t # not a comment\"\n  LOG_LEVEL=debug \n",
+        )
+        .unwrap();
+
+        let options = ContainerOptions::builder("alpine")
+            .env_file(&path)
+            .unwrap()
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(
+            body["Env"],
+            json!([
+                "DB_HOST=db.internal",
+                "DB_PASSWORD=\"s3cr=t # not a comment\"",
+                "LOG_LEVEL=debug ",
+            ])
+        );
+
+        assert!(parse_env_file("VALID=1\nexport\n").is_err());
+        assert!(parse_env_file("=value\n").is_err());
+        assert!(parse_env_file("LOG_LEVEL = debug\n").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Test decoding a representative inspect payload including the sizes
+    #[test]
+    fn container_details_deserialize() {
+        let payload = json!({
+            "AppArmorProfile": "",
+            "Args": ["-g", "daemon off;"],
+            "Config": {
+                "AttachStderr": false,
+                "AttachStdin": false,
+                "AttachStdout": false,
+                "Cmd": ["nginx", "-g", "daemon off;"],
+                "Domainname": "",
+                "Env": ["PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"],
+                "ExposedPorts": { "80/tcp": {} },
+                "Hostname": "4fa6e0f0c678",
+                "Image": "nginx:1.21",
+                "Labels": { "maintainer": "NGINX Docker Maintainers" },
+                "OpenStdin": false,
+                "StdinOnce": false,
+                "Tty": false,
+                "User": "",
+                "WorkingDir": ""
+            },
+            "Created": "2021-06-01T08:12:45.123456789Z",
+            "Driver": "overlay2",
+            "HostConfig": {
+                "ContainerIDFile": "",
+                "Memory": 0,
+                "NetworkMode": "default",
+                "PortBindings": { "80/tcp": [{ "HostIp": "", "HostPort": "8080" }] },
+                "Privileged": false,
+                "PublishAllPorts": false,
+                "ReadonlyRootfs": false,
+                "RestartPolicy": { "Name": "always", "MaximumRetryCount": 0 }
+            },
+            "HostnamePath": "/var/lib/docker/containers/4fa6e0f0c678/hostname",
+            "HostsPath": "/var/lib/docker/containers/4fa6e0f0c678/hosts",
+            "LogPath": "/var/lib/docker/containers/4fa6e0f0c678/4fa6e0f0c678-json.log",
+            "Id": "4fa6e0f0c6786287e131c3852c58a2e01cc697a68231826813597e4994f1d6e2",
+            "Image": "sha256:d1a364dc548d5357f0da3268c888e1971bbdb957ee3f028fe7194f1d61c6fdee",
+            "MountLabel": "",
+            "Name": "/web",
+            "NetworkSettings": {
+                "Bridge": "",
+                "Gateway": "172.17.0.1",
+                "IPAddress": "172.17.0.2",
+                "IPPrefixLen": 16,
+                "MacAddress": "02:42:ac:11:00:02",
+                "Ports": { "80/tcp": [{ "HostIp": "0.0.0.0", "HostPort": "8080" }] },
+                "Networks": {
+                    "bridge": {
+                        "NetworkID": "7ea29fc1412292a2d7bba362f9253545fecdfa8ce9a6e37dd10ba8bee7129812",
+                        "EndpointID": "7587b82f0dada3656fda26588aee72630c6fab1536d36e394b2bfbcf898c971d",
+                        "Gateway": "172.17.0.1",
+                        "IPAddress": "172.17.0.2",
+                        "IPPrefixLen": 16,
+                        "IPv6Gateway": "",
+                        "GlobalIPv6Address": "",
+                        "GlobalIPv6PrefixLen": 0,
+                        "MacAddress": "02:42:ac:11:00:02"
+                    }
+                }
+            },
+            "Path": "/docker-entrypoint.sh",
+            "ProcessLabel": "",
+            "ResolvConfPath": "/var/lib/docker/containers/4fa6e0f0c678/resolv.conf",
+            "RestartCount": 0,
+            "State": {
+                "Error": "",
+                "ExitCode": 0,
+                "FinishedAt": "0001-01-01T00:00:00Z",
+                "OOMKilled": false,
+                "Paused": false,
+                "Pid": 4242,
+                "Restarting": false,
+                "Running": true,
+                "StartedAt": "2021-06-01T08:12:46.001Z",
+                "Status": "running"
+            },
+            "Mounts": [{
+                "Type": "volume",
+                "Name": "html",
+                "Source": "/var/lib/docker/volumes/html/_data",
+                "Destination": "/usr/share/nginx/html",
+                "Driver": "local",
+                "Mode": "z",
+                "RW": true,
+                "Propagation": ""
+            }],
+            "SizeRw": 1024,
+            "SizeRootFs": 133169152
+        });
+        let details: ContainerDetails = serde_json::from_value(payload).unwrap();
+        assert_eq!(details.name, "/web");
+        assert_eq!(details.state.status, ContainerStatus::Running);
+        assert_eq!(details.network_settings.ip_address, "172.17.0.2");
+        assert_eq!(details.mounts[0].typ.as_deref(), Some("volume"));
+        assert_eq!(
+            details.host_config.restart_policy.map(|p| p.name),
+            Some("always".to_owned())
+        );
+        assert_eq!(details.size_rw, Some(1024));
+        assert_eq!(details.size_root_fs, Some(133169152));
+
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let request = docker.containers().get("web").inspect_with_size(true).unwrap();
+        assert_eq!(request.url().path(), "/containers/web/json");
+        assert_eq!(request.url().query(), Some("size=true"));
+        let request = docker.containers().get("web").inspect().unwrap();
+        assert_eq!(request.url().query(), None);
+    }
+
+    /// Test the list filters ending up in the filters json param
+    #[test]
+    fn container_list_filters() {
+        let options = ContainerListOptions::builder()
+            .filter(vec![
+                ContainerFilter::Label("env".to_owned(), "prod".to_owned()),
+                ContainerFilter::LabelName("tier".to_owned()),
+                ContainerFilter::Status("running".to_owned()),
+                ContainerFilter::Name("web".to_owned()),
+                ContainerFilter::Ancestor("chia:1.2".to_owned()),
+                ContainerFilter::Health(HealthStatus::Unhealthy),
+            ])
+            .build();
+        let serialized = options.serialize().unwrap();
+        let params: HashMap<String, String> =
+            form_urlencoded::parse(serialized.as_bytes()).into_owned().collect();
+        let filters: Value = serde_json::from_str(&params["filters"]).unwrap();
+        assert_eq!(filters["label"], json!(["env=prod", "tier"]));
+        assert_eq!(filters["status"], json!(["running"]));
+        assert_eq!(filters["name"], json!(["web"]));
+        assert_eq!(filters["ancestor"], json!(["chia:1.2"]));
+        assert_eq!(filters["health"], json!(["unhealthy"]));
+
+        let options = ContainerListOptions::builder()
+            .filter(vec![ContainerFilter::Health(HealthStatus::Healthy)])
+            .build();
+        let serialized = options.serialize().unwrap();
+        let params: HashMap<String, String> =
+            form_urlencoded::parse(serialized.as_bytes()).into_owned().collect();
+        assert_eq!(params["filters"], r#"{"health":["healthy"]}"#);
+    }
+
+    /// Test decoding a container list, with the lists a daemon may send as `null`
+    #[test]
+    fn container_summary_list() {
+        let body = r#"[
+            {
+                "Id": "8dfafdbc3a40",
+                "Names": ["/web"],
+                "Image": "nginx:1.21",
+                "ImageID": "sha256:4f380adfc10f",
+                "Command": "/docker-entrypoint.sh nginx -g 'daemon off;'",
+                "Created": 1622540000,
+                "State": "running",
+                "Status": "Up 2 hours (healthy)",
+                "Ports": [{"IP": "0.0.0.0", "PrivatePort": 80, "PublicPort": 8080, "Type": "tcp"}],
+                "Labels": {"env": "prod"}
+            },
+            {
+                "Id": "9cd87474be90",
+                "Names": ["/job"],
+                "Image": "alpine",
+                "State": "exited",
+                "Status": "Exited (0) 5 minutes ago",
+                "Ports": null,
+                "Labels": null
+            }
+        ]"#;
+        let containers: Vec<ContainerSummary> = serde_json::from_str(body).unwrap();
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers[0].names, vec!["/web"]);
+        assert_eq!(containers[0].state, ContainerStatus::Running);
+        assert_eq!(containers[0].ports[0].public_port, Some(8080));
+        assert_eq!(containers[0].labels["env"], "prod");
+        assert_eq!(containers[1].state, ContainerStatus::Exited);
+        assert!(containers[1].ports.is_empty());
+        assert!(containers[1].labels.is_empty());
+    }
+
+    /// Test the wait condition query and decoding the exit code
+    #[test]
+    fn wait_condition_and_response() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let container = docker.containers().get("web");
+        let request = container.wait_for(Some(WaitCondition::NextExit)).unwrap();
+        assert_eq!(request.url().path(), "/containers/web/wait");
+        assert_eq!(request.url().query(), Some("condition=next-exit"));
+        assert_eq!(container.wait().unwrap().url().query(), None);
+        let condition: WaitCondition = serde_json::from_value(json!("not-running")).unwrap();
+        assert_eq!(condition, WaitCondition::NotRunning);
+
+        let exited: WaitResponse =
+            serde_json::from_value(json!({ "StatusCode": 137, "Error": null })).unwrap();
+        assert_eq!(exited.status_code, 137);
+        assert_eq!(exited.error, None);
+        let failed: WaitResponse = serde_json::from_value(json!({
+            "StatusCode": -1,
+            "Error": { "Message": "container web: driver failed" }
+        }))
+        .unwrap();
+        assert_eq!(failed.error.unwrap().message, "container web: driver failed");
+    }
+
+    /// Test the single sample query and decoding a cgroup v2 sample
+    #[test]
+    fn stats_once() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let request = docker.containers().get("web").stats_once().unwrap();
+        assert_eq!(request.url().path(), "/containers/web/stats");
+        assert_eq!(request.url().query(), Some("stream=false"));
+
+        let sample = json!({
+            "read": "2021-06-01T08:15:00.123456789Z",
+            "networks": {
+                "eth0": {
+                    "rx_bytes": 5338, "rx_dropped": 0, "rx_errors": 0, "rx_packets": 36,
+                    "tx_bytes": 648, "tx_dropped": 0, "tx_errors": 0, "tx_packets": 8
+                }
+            },
+            "memory_stats": {
+                "usage": 6537216,
+                "limit": 67108864,
+                "stats": { "active_anon": 1024, "anon": 4096, "file": 8192 }
+            },
+            "blkio_stats": {
+                "io_service_bytes_recursive": [
+                    { "major": 8, "minor": 0, "op": "read", "value": 4096 }
+                ],
+                "io_serviced_recursive": null
+            },
+            "cpu_stats": {
+                "cpu_usage": { "total_usage": 100093996, "usage_in_kernelmode": 20000000, "usage_in_usermode": 80000000 },
+                "system_cpu_usage": 9492140000000u64,
+                "online_cpus": 4,
+                "throttling_data": { "periods": 0, "throttled_periods": 0, "throttled_time": 0 }
+            },
+            "precpu_stats": {
+                "cpu_usage": { "total_usage": 0, "usage_in_kernelmode": 0, "usage_in_usermode": 0 },
+                "throttling_data": { "periods": 0, "throttled_periods": 0, "throttled_time": 0 }
+            }
+        });
+        let stats: Stats = serde_json::from_value(sample).unwrap();
+        assert_eq!(stats.networks["eth0"].rx_bytes, 5338);
+        assert_eq!(stats.memory_stats.usage, 6537216);
+        assert_eq!(stats.memory_stats.limit, 67108864);
+        assert_eq!(stats.memory_stats.stats.active_anon, 1024);
+        assert_eq!(stats.cpu_stats.cpu_usage.total_usage, 100093996);
+        assert!(stats.cpu_stats.cpu_usage.percpu_usage.is_empty());
+        assert_eq!(stats.cpu_stats.online_cpus, Some(4));
+        assert_eq!(stats.blkio_stats.io_service_bytes_recursive.len(), 1);
+        assert!(stats.blkio_stats.io_serviced_recursive.is_empty());
+        assert_eq!(stats.precpu_stats.unwrap().system_cpu_usage, 0);
+    }
+
+    /// Test the detach keys ending up in the attach query and bad sequences being rejected
+    #[test]
+    fn attach_detach_keys() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let container = docker.containers().get("web");
+
+        let request = container.attach(None).unwrap();
+        assert_eq!(
+            request.url().query(),
+            Some("stream=1&stdout=1&stderr=1&stdin=1")
+        );
+
+        let request = container.attach(Some("ctrl-a,ctrl-b")).unwrap();
+        assert_eq!(
+            request.url().query(),
+            Some("stream=1&stdout=1&stderr=1&stdin=1&detachKeys=ctrl-a%2Cctrl-b")
+        );
+
+        assert!(valid_detach_keys("q"));
+        assert!(valid_detach_keys("ctrl-@,ctrl-_"));
+        for keys in &["", "ctrl-", "ctrl-A", "ctrl-ab", "alt-a", "ctrl-a,"] {
+            assert!(!valid_detach_keys(keys), "{:?}", keys);
+            assert!(container.attach(Some(keys)).is_err());
+        }
+    }
+
+    /// Test the logs `since` coming from the last start event
+    #[test]
+    fn generation_since_last_start() {
+        let events = br#"{"status":"start","id":"web","Type":"container","Action":"start","time":1620000000,"timeNano":1620000000000000001}
+{"status":"die","id":"web","Type":"container","Action":"die","time":1620000100,"timeNano":1620000100000000000}
+{"status":"start","id":"web","Type":"container","Action":"start","time":1620000200,"timeNano":1620000200123456789}
+"#;
+        assert_eq!(
+            generation_since(events).as_deref(),
+            Some("1620000200.123456789")
+        );
+        assert_eq!(generation_since(b""), None);
+
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let request = docker.containers().get("web").start_events(1620000300).unwrap();
+        assert_eq!(request.url().path(), "/events");
+    }
+
+    /// Test the cpu percent of two consecutive samples and the memory of the summary
+    #[test]
+    fn stats_cpu_percent() {
+        let sample = |total_usage, system_cpu_usage| CpuStats {
+            cpu_usage: CpuUsage {
+                total_usage,
+                ..CpuUsage::default()
+            },
+            system_cpu_usage,
+            online_cpus: Some(4),
+            ..CpuStats::default()
+        };
+        let previous = sample(100_000_000, 1_000_000_000);
+        let current = sample(300_000_000, 2_000_000_000);
+        // 0.2s of cpu time in 1s of system time over 4 cpus
+        assert!((cpu_percent(&previous, &current) - 80.0).abs() < 1e-9);
+        assert_eq!(cpu_percent(&current, &current), 0.0);
+
+        let stats: Stats = serde_json::from_value(json!({
+            "read": "2021-06-01T10:00:01.000000000Z",
+            "memory_stats": {
+                "usage": 150_000_000u64,
+                "limit": 1_000_000_000u64,
+                "stats": { "inactive_file": 50_000_000u64 }
+            },
+            "cpu_stats": serde_json::to_value(&current).unwrap(),
+            "precpu_stats": serde_json::to_value(&previous).unwrap(),
+        }))
+        .unwrap();
+        let summary = StatsSummary::new(&stats, None);
+        assert!((summary.cpu_percent - 80.0).abs() < 1e-9);
+        assert_eq!(summary.mem_usage, 100_000_000);
+        assert_eq!(summary.mem_limit, 1_000_000_000);
+        assert!((summary.mem_percent - 10.0).abs() < 1e-9);
+        assert_eq!(StatsSummary::new(&stats, Some(&current)).cpu_percent, 0.0);
+    }
+
+    /// Test decoding the create response with its warnings, and without any
+    #[test]
+    fn container_create_info() {
+        let body = r#"{
+            "Id": "e90e34656806",
+            "Warnings": [
+                "Your kernel does not support swap limit capabilities or the cgroup is not mounted. Memory limited without swap."
+            ]
+        }"#;
+        let info: ContainerCreateInfo = serde_json::from_str(body).unwrap();
+        assert_eq!(info.id, "e90e34656806");
+        assert_eq!(info.warnings.len(), 1);
+        assert!(info.warnings[0].starts_with("Your kernel does not support swap"));
+
+        let info: ContainerCreateInfo =
+            serde_json::from_str(r#"{"Id": "e90e34656806", "Warnings": null}"#).unwrap();
+        assert!(info.warnings.is_empty());
+    }
+
+    #[test]
+    fn stats_oneshot_query() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let request = docker.containers().get("web").stats_oneshot().unwrap();
+        assert_eq!(request.url().path(), "/containers/web/stats");
+        let params: HashMap<String, String> = request.url().query_pairs().into_owned().collect();
+        assert_eq!(params.len(), 2);
+        assert_eq!(params["stream"], "false");
+        assert_eq!(params["one-shot"], "true");
+    }
+
+    /// Test a stat header of a file and of a directory being decoded
+    #[test]
+    fn path_stat_header() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let request = docker.containers().get("web").stat_path("/etc/hosts").unwrap();
+        assert_eq!(request.method(), http_types::Method::Head);
+        assert_eq!(request.url().query(), Some("path=%2Fetc%2Fhosts"));
+
+        let stat = PathStat::from_header(
+            "eyJuYW1lIjoiaG9zdHMiLCJzaXplIjoxNzQsIm1vZGUiOjQyMCwibXRpbWUiOiIyMDIxLTA2LTAxVDA5OjE0OjMzLjI2MTkxMzc2NVoiLCJsaW5rVGFyZ2V0IjoiIn0=",
+        )
+        .unwrap();
+        assert_eq!(stat.name, "hosts");
+        assert_eq!(stat.size, 174);
+        assert_eq!(stat.mode, 0o644);
+        assert!(!stat.is_dir());
+        #[cfg(not(feature = "chrono"))]
+        assert_eq!(stat.mtime, "2021-06-01T09:14:33.261913765Z");
+
+        let stat = PathStat::from_header(
+            "eyJuYW1lIjoibG9nIiwic2l6ZSI6NDA5NiwibW9kZSI6MjE0NzQ4NDE0MSwibXRpbWUiOiIyMDIxLTA2LTAxVDA5OjE0OjMzWiIsImxpbmtUYXJnZXQiOiIifQ==",
+        )
+        .unwrap();
+        assert!(stat.is_dir());
+        assert_eq!(stat.mode & 0o777, 0o755);
+        assert!(PathStat::from_header("not base64!").is_err());
+    }
+
+    #[test]
+    fn top_result() {
+        let body = r#"{
+            "Titles": ["UID", "PID", "PPID", "C", "STIME", "TTY", "TIME", "CMD"],
+            "Processes": [
+                ["root", "13642", "882", "0", "17:03", "pts/0", "00:00:00", "/bin/bash"],
+                ["root", "13735", "13642", "0", "17:06", "pts/0", "00:00:00", "sleep 10"]
+            ]
+        }"#;
+        let top: TopResult = serde_json::from_str(body).unwrap();
+        let pid = top.titles.iter().position(|title| title == "PID").unwrap();
+        assert_eq!(top.processes.len(), 2);
+        assert_eq!(top.processes[1][pid], "13735");
+        assert_eq!(top.processes[1][top.titles.len() - 1], "sleep 10");
+
+        let top: TopResult =
+            serde_json::from_str(r#"{"Titles": ["PID", "CMD"], "Processes": null}"#).unwrap();
+        assert!(top.processes.is_empty());
+    }
+
+    /// Test the runtime in the host config and the platform in the create query
+    #[test]
+    fn container_options_platform_runtime() {
+        let options = ContainerOptions::builder("alpine")
+            .name("gpu")
+            .platform("linux/arm64")
+            .runtime("nvidia")
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(body["HostConfig"]["Runtime"], json!("nvidia"));
+        assert_eq!(options.resources().runtime.as_deref(), Some("nvidia"));
+
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let request = docker.containers().create(&options).unwrap();
+        assert_eq!(request.url().path(), "/containers/create");
+        assert_eq!(
+            request.url().query(),
+            Some("name=gpu&platform=linux%2Farm64")
+        );
+
+        let request = docker
+            .containers()
+            .create(&ContainerOptions::builder("alpine").build())
+            .unwrap();
+        assert_eq!(request.url().query(), None);
+    }
+}
@@ -1,3 +1,5 @@
+//! Deserializers of the timestamp formats the daemon uses, for the `chrono` feature.
+
 use chrono::{DateTime, Utc};
 use serde::Deserialize;
 
@@ -24,3 +26,58 @@ where
     );
     Ok(DateTime::<Utc>::from_utc(timestamp, Utc))
 }
+
+/// RFC 3339 timestamps with any offset, e.g. `2021-06-01T08:15:00.123456789Z`
+pub(crate) fn datetime_from_rfc3339<'de, D>(
+    deserializer: D
+) -> Result<DateTime<Utc>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let timestamp = String::deserialize(deserializer)?;
+    DateTime::parse_from_rfc3339(&timestamp)
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Unix(#[serde(deserialize_with = "datetime_from_unix_timestamp")] DateTime<Utc>);
+
+    #[derive(Deserialize)]
+    struct Nano(#[serde(deserialize_with = "datetime_from_nano_timestamp")] DateTime<Utc>);
+
+    #[derive(Deserialize)]
+    struct Rfc3339(#[serde(deserialize_with = "datetime_from_rfc3339")] DateTime<Utc>);
+
+    #[test]
+    fn unix_timestamp() {
+        let Unix(datetime) = serde_json::from_value(json!(1620000000)).unwrap();
+        assert_eq!(datetime.to_rfc3339(), "2021-05-03T00:00:00+00:00");
+    }
+
+    #[test]
+    fn nano_timestamp() {
+        let Nano(datetime) = serde_json::from_value(json!(1620000000123456789u64)).unwrap();
+        assert_eq!(datetime.timestamp(), 1620000000);
+        assert_eq!(datetime.timestamp_subsec_nanos(), 123456789);
+    }
+
+    #[test]
+    fn rfc3339_timestamp() {
+        let Rfc3339(datetime) =
+            serde_json::from_value(json!("2021-05-03T02:00:00.5+02:00")).unwrap();
+        assert_eq!(datetime.timestamp(), 1620000000);
+        assert_eq!(datetime.timestamp_subsec_millis(), 500);
+
+        // the zero time of containers that never ran
+        let Rfc3339(datetime) = serde_json::from_value(json!("0001-01-01T00:00:00Z")).unwrap();
+        assert_eq!(datetime.timestamp(), -62135596800);
+
+        assert!(serde_json::from_value::<Rfc3339>(json!("yesterday")).is_err());
+    }
+}
@@ -2,22 +2,37 @@
 //!
 //! API Reference: <https://docs.docker.com/engine/api/v1.41/>
 
-use std::{collections::HashMap, env};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+};
 
+use async_std::os::unix::net::UnixStream;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
 use url::Url;
 
+use http_client::HttpClient;
 use http_types::{Method, Mime, Request, Body, headers, Error};
 
 use serde::{Deserialize, Serialize};
 use url::form_urlencoded;
 
-use crate::{docker::{image::Images, container::Containers, network::Networks, service::Services, volume::Volumes}};
+use crate::{docker::{json, image::{Images, RegistryAuth}, container::Containers, network::Networks, service::Services, volume::Volumes}};
+
+#[cfg(feature = "chrono")]
+use crate::docker::datetime::{
+    datetime_from_nano_timestamp, datetime_from_rfc3339, datetime_from_unix_timestamp,
+};
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
 
 
 /// Entrypoint interface for communicating with docker daemon
 #[derive(Clone)]
 pub struct Docker {
     endpoint: Url,
+    /// api version of the daemon the paths are prefixed with, e.g. `1.41`
+    api_version: Option<String>,
 }
 
 
@@ -28,49 +43,140 @@ impl Docker {
     pub fn new() -> Docker {
         match env::var("DOCKER_HOST").ok() {
             Some(host) => {
-                #[cfg(feature = "unix-socket")]
-                if let Some(path) = host.strip_prefix("unix://") {
-                    return Docker::unix(path);
-                }
                 let host: Url = host.parse().expect("invalid url");
                 Docker::host(host)
             }
-            #[cfg(feature = "unix-socket")]
             None => Docker::unix("/var/run/docker.sock"),
-            #[cfg(not(feature = "unix-socket"))]
-            None => panic!("Unix socket support is disabled"),
         }
     }
 
     /// Creates a new docker instance for a docker host
     /// listening on a given Unix socket.
-    #[cfg(feature = "unix-socket")]
     pub fn unix<S>(socket_path: S) -> Docker
     where
         S: Into<String>,
     {
         Docker {
-            transport: Transport::Unix {
-                client: Client::builder()
-                    .pool_max_idle_per_host(0)
-                    .build(UnixConnector),
-                path: socket_path.into(),
-            },
+            endpoint: unix_endpoint(&socket_path.into()),
+            api_version: None,
         }
     }
 
-    /// constructs a new Docker instance for docker host listening at the given host url
+    /// constructs a new Docker instance for docker host listening at the given host url,
+    /// see [`Docker::with_endpoint`]. Panics on the urls it refuses.
     pub fn host(host: Url) -> Docker {
+        Docker::with_endpoint(host).expect("invalid docker url")
+    }
+
+    /// constructs a new Docker instance for the daemon at `endpoint`, an `http`, `https`
+    /// or `tcp` (plain http) url with a host, or a `unix` url with the path of a socket.
+    /// Any path of a network url is dropped and a missing port defaults to 80.
+    pub fn with_endpoint(endpoint: Url) -> crate::errors::Result<Docker> {
+        use crate::errors::Error::InvalidEndpoint;
+
+        // `tcp` is docker's name for plain http, the http client only speaks the latter
+        let scheme = match endpoint.scheme() {
+            "unix" if endpoint.path().len() > 1 => {
+                let path = percent_decode_str(endpoint.path()).decode_utf8_lossy();
+                return Ok(Docker::unix(path));
+            }
+            "unix" => {
+                let reason = format!("docker url {} has no socket path", endpoint);
+                return Err(InvalidEndpoint(reason));
+            }
+            "tcp" => "http",
+            scheme @ ("http" | "https") => scheme,
+            scheme => {
+                let reason = format!("unsupported docker url scheme {:?}", scheme);
+                return Err(InvalidEndpoint(reason));
+            }
+        };
+        let host = endpoint
+            .host()
+            .ok_or_else(|| InvalidEndpoint(format!("docker url {} has no host", endpoint)))?;
         let tcp_host_str = format!(
             "{}://{}:{}",
-            host.scheme(),
-            host.host().unwrap().to_owned(),
-            host.port().unwrap_or(80)
+            scheme,
+            host,
+            endpoint.port_or_known_default().unwrap_or(80)
         );
         log::debug!("docker: {}", tcp_host_str);
 
-        Docker {
-            endpoint: host,
+        Ok(Docker {
+            endpoint: tcp_host_str
+                .parse()
+                .map_err(|e| InvalidEndpoint(format!("docker url {}: {}", tcp_host_str, e)))?,
+            api_version: None,
+        })
+    }
+
+    /// Ask the daemon for its api version with `client` and prefix the paths of all later
+    /// requests with the older of it and [`MAX_API_VERSION`], e.g. `/v1.41/containers/json`.
+    /// The paths stay unprefixed when the daemon can not tell.
+    pub async fn negotiate_api_version<C: HttpClient>(&mut self, client: &C) -> Option<&str> {
+        self.api_version = None;
+        let version = async {
+            let mut response = client.send(self.version()?).await?;
+            if !response.status().is_success() {
+                return Err(Error::from_str(
+                    response.status(),
+                    "docker did not report its version",
+                ));
+            }
+            let version: serde_json::Value = response.body_json().await?;
+            version["ApiVersion"]
+                .as_str()
+                .map(str::to_owned)
+                .ok_or_else(|| Error::from_str(500, "docker version without ApiVersion"))
+        }
+        .await;
+        match version {
+            Ok(version) => self.set_api_version(Some(older_api_version(&version))),
+            Err(e) => log::warn!("api version negotiation with {} failed: {}", self.endpoint, e),
+        }
+        self.api_version.as_deref()
+    }
+
+    /// Prefix the paths of all requests with a fixed api version, `1.41` or `v1.41`
+    pub fn with_api_version(mut self, version: &str) -> Docker {
+        self.set_api_version(Some(version));
+        self
+    }
+
+    /// Set or clear the api version the paths are prefixed with
+    pub fn set_api_version(&mut self, version: Option<&str>) {
+        self.api_version = version
+            .map(|v| v.trim_start_matches('v').to_owned())
+            .filter(|v| !v.is_empty());
+    }
+
+    /// api version the paths are prefixed with, if any
+    pub fn api_version(&self) -> Option<&str> {
+        self.api_version.as_deref()
+    }
+
+    /// url of `path` on the daemon, prefixed with the api version if one is set.
+    /// The path is always taken from the root of the endpoint, with or without a
+    /// leading slash, so the prefix can not end up glued to a relative path.
+    fn url(&self, path: &str) -> Result<Url, Error> {
+        let path = path.trim_start_matches('/');
+        let url = match &self.api_version {
+            Some(version) => self.endpoint.join(&format!("/v{}/{}", version, path))?,
+            None => self.endpoint.join(&format!("/{}", path))?,
+        };
+        Ok(url)
+    }
+
+    /// the api path of a `url` made by [`Docker::url`], without the version prefix. Dot
+    /// segments are resolved by then, so this is the path the daemon serves.
+    pub(crate) fn api_path<'u>(&self, url: &'u Url) -> &'u str {
+        let path = url.path();
+        match &self.api_version {
+            Some(version) => path
+                .strip_prefix(&format!("/v{}", version))
+                .filter(|rest| rest.starts_with('/'))
+                .unwrap_or(path),
+            None => path,
         }
     }
 
@@ -107,6 +213,15 @@ impl Docker {
         self.get("/info")
     }
 
+    /// Check registry credentials with the daemon, answered with an `AuthResponse`. The
+    /// credentials are sent as plain json, not base64 encoded as for pull and build.
+    ///
+    /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/SystemAuth>
+    pub fn auth(&self, auth: &RegistryAuth) -> Result<Request, Error> {
+        let body = Body::from_json(auth)?;
+        self.post("/auth", Some((body, json())))
+    }
+
     /// Returns a simple ping response indicating the docker daemon is accessible
     pub fn ping(&self) -> Result<Request, Error> {
         self.get("/_ping")
@@ -125,20 +240,36 @@ impl Docker {
         self.get(&path.join("?"))
     }
 
+    /// A request to any daemon path, for endpoints without a wrapper. `path` may carry a query.
+    pub fn raw(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<(Body, Mime)>,
+    ) -> Result<Request, Error> {
+        let url = self.url(path)?;
+        request(url, method, body, vec![])
+    }
+
     pub(crate) fn get(
         &self,
         path: &str,
     ) -> Result<Request, Error> {
-        let url = self.endpoint.join(path)?;
+        let url = self.url(path)?;
         request(url, Method::Get, None, vec![])
     }
 
+    pub(crate) fn head(&self, path: &str) -> Result<Request, Error> {
+        let url = self.url(path)?;
+        request(url, Method::Head, None, vec![])
+    }
+
     pub(crate) fn get_with_header (
         &self,
         path: &str,
         headers: Vec<(&str, String)>,
     ) -> Result<Request, Error> {
-        let url = self.endpoint.join(path)?;
+        let url = self.url(path)?;
         request(url, Method::Get, None, headers)
     }
 
@@ -147,7 +278,7 @@ impl Docker {
         path: &str,
         body: Option<(Body, Mime)>,
     ) -> Result<Request, Error> {
-        let url = self.endpoint.join(path)?;
+        let url = self.url(path)?;
         request(url, Method::Post, body, vec![])
     }
 
@@ -157,7 +288,7 @@ impl Docker {
         headers: Vec<(&str, String)>,
         body: Option<(Body, Mime)>,
     ) -> Result<Request, Error> {
-        let url = self.endpoint.join(path)?;
+        let url = self.url(path)?;
         request(url, Method::Post, body, headers)
     }
 
@@ -166,7 +297,7 @@ impl Docker {
         path: &str,
         body: Option<(Body, Mime)>,
     ) -> Result<Request, Error> {
-        let url = self.endpoint.join(path)?;
+        let url = self.url(path)?;
         request(url, Method::Put, body, vec![])
     }
 
@@ -176,7 +307,7 @@ impl Docker {
         headers: Vec<(&str, String)>,
         body: Option<(Body, Mime)>,
     ) -> Result<Request, Error> {
-        let url = self.endpoint.join(path)?;
+        let url = self.url(path)?;
         request(url, Method::Put, body, headers)
     }
 
@@ -185,7 +316,7 @@ impl Docker {
         path: &str,
         body: Option<(Body, Mime)>,
     ) -> Result<Request, Error> {
-        let url = self.endpoint.join(path)?;
+        let url = self.url(path)?;
         request(url, Method::Patch, body, vec![])
     }
 
@@ -195,7 +326,7 @@ impl Docker {
         headers: Vec<(&str, String)>,
         body: Option<(Body, Mime)>,
     ) -> Result<Request, Error> {
-        let url = self.endpoint.join(path)?;
+        let url = self.url(path)?;
         request(url, Method::Patch, body, headers)
     }
 
@@ -203,7 +334,7 @@ impl Docker {
         &self,
         path: &str,
     ) -> Result<Request, Error> {
-        let url = self.endpoint.join(path)?;
+        let url = self.url(path)?;
         request(url, Method::Delete, None, vec![])
     }
 
@@ -212,7 +343,7 @@ impl Docker {
         path: &str,
         headers: Vec<(&str, String)>
     ) -> Result<Request, Error> {
-        let url = self.endpoint.join(path)?;
+        let url = self.url(path)?;
         request(url, Method::Delete, None, headers)
     }
 }
@@ -224,14 +355,93 @@ pub fn request(url: Url, method: Method, body: Option<(Body, Mime)>, headers: Ve
         request.insert_header(name, value);
     }
 
-    if let Some((body, mime)) = body {
-        request.set_body(body);
-        request.insert_header(headers::CONTENT_TYPE, mime);
+    match body {
+        Some((body, mime)) => {
+            request.set_body(body);
+            request.insert_header(headers::CONTENT_TYPE, mime);
+        }
+        // some daemons wait for a body unless told there is none
+        None if matches!(method, Method::Post | Method::Put | Method::Patch) => {
+            request.insert_header(headers::CONTENT_LENGTH, "0");
+        }
+        None => {}
     }
-    
+
     Ok(request)
 }
 
+/// Options sent as the query string of a request, kept as a map of params
+pub trait QueryOptions {
+    fn params(&self) -> &HashMap<&'static str, String>;
+
+    /// url encoded params, `None` without any
+    fn serialize(&self) -> Option<String> {
+        let params = self.params();
+        if params.is_empty() {
+            None
+        } else {
+            Some(
+                form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(params)
+                    .finish(),
+            )
+        }
+    }
+}
+
+/// Newest api version the requests of this crate are written for
+pub const MAX_API_VERSION: &str = "1.41";
+
+/// the older of the api version a daemon reported and [`MAX_API_VERSION`], so a newer
+/// daemon answers in the shape the types here decode
+fn older_api_version(daemon: &str) -> &str {
+    fn parse(version: &str) -> Option<(u32, u32)> {
+        let (major, minor) = version.trim_start_matches('v').split_once('.')?;
+        Some((major.parse().ok()?, minor.parse().ok()?))
+    }
+
+    match (parse(daemon), parse(MAX_API_VERSION)) {
+        (Some(daemon_version), Some(max)) if daemon_version > max => MAX_API_VERSION,
+        _ => daemon,
+    }
+}
+
+/// Scheme of the endpoint of a daemon listening on a unix socket. The host is the percent
+/// encoded path of the socket, so request paths join onto the endpoint like any other.
+const UNIX_SCHEME: &str = "http+unix";
+
+fn unix_endpoint(socket_path: &str) -> Url {
+    let host = utf8_percent_encode(socket_path, NON_ALPHANUMERIC);
+    Url::parse(&format!("{}://{}/", UNIX_SCHEME, host)).expect("percent encoded socket path")
+}
+
+/// the path of the socket a request to a daemon on a unix socket is sent to
+pub fn socket_path(url: &Url) -> Option<String> {
+    if url.scheme() != UNIX_SCHEME {
+        return None;
+    }
+    let host = url.host_str()?;
+    percent_decode_str(host).decode_utf8().ok().map(String::from)
+}
+
+/// Send `request` to the daemon on the unix socket of its url, one connection per request.
+/// The http client only connects over tcp.
+pub async fn send_unix(mut request: Request) -> Result<http_types::Response, Error> {
+    let path = socket_path(request.url())
+        .ok_or_else(|| Error::from_str(500, format!("{} is not a unix socket", request.url())))?;
+    // the encoded path is no host name, the daemon accepts any
+    request.insert_header(headers::HOST, "localhost");
+    let stream = UnixStream::connect(path).await?;
+    async_h1::client::connect(stream, request).await
+}
+
+/// Encode filters as the `filters` query param: a json object mapping every key to a
+/// list of values. Keys are sorted so equal filters always encode the same way.
+pub fn serialize_filters(filters: &HashMap<&str, Vec<String>>) -> String {
+    let sorted: BTreeMap<&&str, &Vec<String>> = filters.iter().collect();
+    serde_json::to_string(&sorted).unwrap()
+}
+
 impl Default for Docker {
     fn default() -> Self {
         Self::new()
@@ -244,6 +454,12 @@ pub struct EventsOptions {
     params: HashMap<&'static str, String>,
 }
 
+impl QueryOptions for EventsOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
 impl EventsOptions {
     pub fn builder() -> EventsOptionsBuilder {
         EventsOptionsBuilder::default()
@@ -251,15 +467,7 @@ impl EventsOptions {
 
     /// serialize options as a string. returns None if no options are defined
     pub fn serialize(&self) -> Option<String> {
-        if self.params.is_empty() {
-            None
-        } else {
-            Some(
-                form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(&self.params)
-                    .finish(),
-            )
-        }
+        QueryOptions::serialize(self)
     }
 }
 
@@ -369,8 +577,7 @@ impl EventsOptionsBuilder {
                 }
             };
         }
-        self.params
-            .insert("filters", serde_json::to_string(&params).unwrap());
+        self.params.insert("filters", serialize_filters(&params));
         self
     }
 
@@ -381,6 +588,35 @@ impl EventsOptionsBuilder {
     }
 }
 
+/// Outcome of a successful `auth`, some registries hand out a token to use instead of
+/// the password
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AuthResponse {
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_token: Option<String>,
+}
+
+/// What a daemon tells about itself in the headers of its `_ping` response
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PingInfo {
+    pub api_version: Option<String>,
+    pub experimental: bool,
+    pub os_type: Option<String>,
+}
+
+impl PingInfo {
+    pub fn from_headers(response: &http_types::Response) -> Self {
+        let header = |name: &str| response.header(name).map(|value| value.last().to_string());
+        PingInfo {
+            api_version: header("Api-Version"),
+            experimental: header("Docker-Experimental").map_or(false, |value| value == "true"),
+            os_type: header("Ostype"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Version {
@@ -392,34 +628,51 @@ pub struct Version {
     pub arch: String,
     pub kernel_version: String,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub build_time: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub build_time: String,
 }
 
+/// Daemon wide information of `/info`. Daemons leave out fields depending on their
+/// version, platform and storage driver, so the counters default to 0 and everything
+/// else, the capacity included, is optional.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Info {
+    #[serde(rename = "ID", default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
     pub containers: u64,
+    pub containers_running: Option<u64>,
+    pub containers_paused: Option<u64>,
+    pub containers_stopped: Option<u64>,
+    #[serde(default)]
     pub images: u64,
-    pub driver: String,
-    pub docker_root_dir: String,
-    pub driver_status: Vec<Vec<String>>,
-    #[serde(rename = "ID")]
-    pub id: String,
-    pub kernel_version: String,
-    // pub Labels: Option<???>,
-    pub mem_total: u64,
-    pub memory_limit: bool,
+    pub driver: Option<String>,
+    pub docker_root_dir: Option<String>,
+    pub driver_status: Option<Vec<Vec<String>>>,
+    pub kernel_version: Option<String>,
+    pub labels: Option<Vec<String>>,
+    pub mem_total: Option<u64>,
+    pub memory_limit: Option<bool>,
     #[serde(rename = "NCPU")]
-    pub n_cpu: u64,
-    pub n_events_listener: u64,
-    pub n_goroutines: u64,
-    pub name: String,
-    pub operating_system: String,
+    pub n_cpu: Option<u64>,
+    pub n_events_listener: Option<u64>,
+    pub n_goroutines: Option<u64>,
+    pub operating_system: Option<String>,
+    #[serde(rename = "OSType")]
+    pub os_type: Option<String>,
+    pub architecture: Option<String>,
+    pub server_version: Option<String>,
     // pub RegistryConfig:???
-    pub swap_limit: bool,
+    pub swap_limit: Option<bool>,
     pub system_time: Option<String>,
+    /// runtimes a container can be created with, by name
+    #[serde(default)]
+    pub runtimes: HashMap<String, serde_json::Value>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -456,30 +709,285 @@ pub struct Actor {
 
 #[cfg(test)]
 mod tests {
-    #[cfg(feature = "unix-socket")]
+    use super::Docker;
+    use url::Url;
+
     #[test]
-    fn unix_host_env() {
-        use super::Docker;
-        use std::env;
-        env::set_var("DOCKER_HOST", "unix:///docker.sock");
-        let d = Docker::new();
-        match d.transport {
-            crate::transport::Transport::Unix { path, .. } => {
-                assert_eq!(path, "/docker.sock");
-            }
-            _ => {
-                panic!("Expected transport to be unix.");
-            }
+    fn host_with_port() {
+        let d = Docker::host(Url::parse("http://127.0.0.1:8010/ignored/path").unwrap());
+        assert_eq!(d.endpoint.as_str(), "http://127.0.0.1:8010/");
+        assert_eq!(
+            d.version().unwrap().url().as_str(),
+            "http://127.0.0.1:8010/version"
+        );
+    }
+
+    #[test]
+    fn host_without_port() {
+        let d = Docker::host(Url::parse("tcp://127.0.0.1").unwrap());
+        assert_eq!(d.endpoint.as_str(), "http://127.0.0.1/");
+        assert_eq!(d.info().unwrap().url().as_str(), "http://127.0.0.1/info");
+
+        let d = Docker::host(Url::parse("https://docker.example.org").unwrap());
+        assert_eq!(d.endpoint.as_str(), "https://docker.example.org/");
+        assert_eq!(
+            d.ping().unwrap().url().as_str(),
+            "https://docker.example.org/_ping"
+        );
+    }
+
+    /// Test `tcp` urls being reached over http, and urls without a host or with a scheme the
+    /// client can not speak being refused
+    #[test]
+    fn endpoint_validation() {
+        let docker = Docker::with_endpoint(Url::parse("https://docker.example.org/v1").unwrap());
+        assert_eq!(docker.unwrap().endpoint.as_str(), "https://docker.example.org/");
+        let docker = Docker::with_endpoint(Url::parse("tcp://10.0.0.5:2375").unwrap());
+        assert_eq!(docker.unwrap().endpoint.as_str(), "http://10.0.0.5:2375/");
+
+        for url in &["ftp://10.0.0.5", "data:text/plain,hi"] {
+            let error = Docker::with_endpoint(Url::parse(url).unwrap()).err().unwrap();
+            assert!(error.to_string().contains("scheme"), "{}: {}", url, error);
         }
-        env::set_var("DOCKER_HOST", "http://localhost:8000");
-        let d = Docker::new();
-        match d.transport {
-            crate::transport::Transport::Tcp { host, .. } => {
-                assert_eq!(host, "http://localhost:8000");
-            }
-            _ => {
-                panic!("Expected transport to be http.");
-            }
+        for url in &["tcp:10.0.0.5:2375", "tcp:///var/run/docker.sock"] {
+            let error = Docker::with_endpoint(Url::parse(url).unwrap()).err().unwrap();
+            assert!(error.to_string().contains("has no host"), "{}: {}", url, error);
         }
+        let error = Docker::with_endpoint(Url::parse("unix://").unwrap()).err().unwrap();
+        assert!(error.to_string().contains("no socket path"), "{}", error);
+        assert_eq!(error.status(), 500);
+    }
+
+    /// Test a daemon on a unix socket being reached through the socket of its url
+    #[async_std::test]
+    async fn unix_endpoint() {
+        use async_std::{io::prelude::*, os::unix::net::UnixListener};
+
+        let docker = Docker::with_endpoint(Url::parse("unix:///var/run/docker.sock").unwrap());
+        let request = docker.unwrap().with_api_version("1.41").info().unwrap();
+        assert_eq!(super::socket_path(request.url()).unwrap(), "/var/run/docker.sock");
+        assert_eq!(request.url().path(), "/v1.41/info");
+        assert_eq!(super::socket_path(&Url::parse("http://127.0.0.1:2375").unwrap()), None);
+
+        let path = std::env::temp_dir().join(format!("beekeeper-{}.sock", uuid::Uuid::new_v4()));
+        let listener = UnixListener::bind(&path).await.unwrap();
+        async_std::task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let n = stream.read(&mut request).await.unwrap();
+            assert!(request[..n].starts_with(b"GET /_ping HTTP/1.1\r\n"));
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nOK")
+                .await
+                .unwrap();
+        });
+        let docker = Docker::unix(path.to_str().unwrap());
+        let mut response = super::send_unix(docker.ping().unwrap()).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body_string().await.unwrap(), "OK");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Daemon answering every request with a fixed `/version` body
+    #[derive(Debug)]
+    struct VersionDaemon(&'static str);
+
+    #[http_client::async_trait]
+    impl http_client::HttpClient for VersionDaemon {
+        async fn send(
+            &self,
+            req: http_types::Request,
+        ) -> Result<http_types::Response, http_types::Error> {
+            assert_eq!(req.url().path(), "/version");
+            let mut response = http_types::Response::new(200);
+            response.set_body(self.0);
+            Ok(response)
+        }
+    }
+
+    /// Test the version a daemon reports prefixing later paths, and a daemon that can not
+    /// tell leaving them unprefixed
+    #[test]
+    fn negotiated_api_version_prefix() {
+        let mut d = Docker::host(Url::parse("http://127.0.0.1:2375").unwrap());
+        let daemon = VersionDaemon(r#"{"Version":"20.10.7","ApiVersion":"1.41"}"#);
+        let version = futures::executor::block_on(d.negotiate_api_version(&daemon));
+        assert_eq!(version, Some("1.41"));
+        let request = d.containers().list(&Default::default()).unwrap();
+        assert_eq!(request.url().path(), "/v1.41/containers/json");
+
+        // a newer daemon is spoken to in the newest version known here, an older in its own
+        let daemon = VersionDaemon(r#"{"Version":"24.0.7","ApiVersion":"1.43"}"#);
+        let version = futures::executor::block_on(d.negotiate_api_version(&daemon));
+        assert_eq!(version, Some("1.41"));
+        let daemon = VersionDaemon(r#"{"Version":"19.03.15","ApiVersion":"1.40"}"#);
+        let version = futures::executor::block_on(d.negotiate_api_version(&daemon));
+        assert_eq!(version, Some("1.40"));
+        let daemon = VersionDaemon(r#"{"Version":"25.0.0","ApiVersion":"1.100"}"#);
+        let version = futures::executor::block_on(d.negotiate_api_version(&daemon));
+        assert_eq!(version, Some("1.41"));
+
+        // a daemon that can not tell leaves the paths unprefixed
+        let daemon = VersionDaemon("not json");
+        let version = futures::executor::block_on(d.negotiate_api_version(&daemon));
+        assert_eq!(version, None);
+        let request = d.containers().list(&Default::default()).unwrap();
+        assert_eq!(request.url().path(), "/containers/json");
+    }
+
+    #[test]
+    fn api_version_prefix() {
+        let d = Docker::host(Url::parse("http://127.0.0.1:2375").unwrap());
+        assert_eq!(d.api_version(), None);
+        assert_eq!(
+            d.get("/containers/json?all=true").unwrap().url().as_str(),
+            "http://127.0.0.1:2375/containers/json?all=true"
+        );
+        assert_eq!(
+            d.get("containers/json").unwrap().url().as_str(),
+            "http://127.0.0.1:2375/containers/json"
+        );
+
+        for version in &["1.41", "v1.41"] {
+            let d = Docker::host(Url::parse("http://127.0.0.1:2375").unwrap())
+                .with_api_version(version);
+            assert_eq!(d.api_version(), Some("1.41"));
+            assert_eq!(
+                d.get("/containers/json?all=true").unwrap().url().as_str(),
+                "http://127.0.0.1:2375/v1.41/containers/json?all=true"
+            );
+            assert_eq!(
+                d.post("containers/web/start", None).unwrap().url().as_str(),
+                "http://127.0.0.1:2375/v1.41/containers/web/start"
+            );
+        }
+
+        let mut d =
+            Docker::host(Url::parse("http://127.0.0.1:2375").unwrap()).with_api_version("1.41");
+        d.set_api_version(None);
+        assert_eq!(d.ping().unwrap().url().path(), "/_ping");
+    }
+
+    /// Test the capabilities of a daemon being read from its ping headers
+    #[test]
+    fn ping_info_headers() {
+        use super::PingInfo;
+
+        let mut response = http_types::Response::new(200);
+        response.insert_header("Api-Version", "1.41");
+        response.insert_header("Docker-Experimental", "true");
+        response.insert_header("Ostype", "linux");
+        response.set_body("OK");
+        assert_eq!(
+            PingInfo::from_headers(&response),
+            PingInfo {
+                api_version: Some("1.41".to_owned()),
+                experimental: true,
+                os_type: Some("linux".to_owned()),
+            }
+        );
+
+        let response = http_types::Response::new(200);
+        let info = PingInfo::from_headers(&response);
+        assert_eq!(info.api_version, None);
+        assert!(!info.experimental);
+    }
+
+    /// Test bodyless writes announcing an empty body, and bodies keeping their mime
+    #[test]
+    fn bodyless_post_content_length() {
+        let d = Docker::host(Url::parse("http://127.0.0.1:2375").unwrap());
+        let request = d.post("containers/web/start", None).unwrap();
+        assert_eq!(request.header(http_types::headers::CONTENT_LENGTH).unwrap(), "0");
+        let request = d.put("containers/web/archive", None).unwrap();
+        assert_eq!(request.header(http_types::headers::CONTENT_LENGTH).unwrap(), "0");
+
+        let request = d.get("containers/json").unwrap();
+        assert!(request.header(http_types::headers::CONTENT_LENGTH).is_none());
+        let body = Some((http_types::Body::from("{}"), crate::docker::json()));
+        let request = d.post("volumes/create", body).unwrap();
+        assert!(request.header(http_types::headers::CONTENT_LENGTH).is_none());
+        assert_eq!(request.content_type(), Some(http_types::mime::JSON));
+    }
+
+    /// Test the exact encoding of a filter map, whatever the insertion order
+    #[test]
+    fn serialize_filters_sorted() {
+        use super::serialize_filters;
+        use std::collections::HashMap;
+
+        let mut filters = HashMap::new();
+        filters.insert("label", vec!["env=prod".to_owned(), "tier".to_owned()]);
+        filters.insert("dangling", vec!["true".to_owned()]);
+        assert_eq!(
+            serialize_filters(&filters),
+            r#"{"dangling":["true"],"label":["env=prod","tier"]}"#
+        );
+    }
+
+    /// Test the auth request carrying the credentials as a plain json body
+    #[test]
+    fn auth_request_body() {
+        use super::{AuthResponse, Method, RegistryAuth};
+
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let auth = RegistryAuth::builder()
+            .username("bee")
+            .password("s3cret")
+            .server_address("registry.example.com")
+            .build();
+        let mut request = docker.auth(&auth).unwrap();
+        assert_eq!(request.method(), Method::Post);
+        assert_eq!(request.url().path(), "/auth");
+        assert_eq!(request.content_type().unwrap().essence(), "application/json");
+        let body = futures::executor::block_on(request.body_string()).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "username": "bee",
+                "password": "s3cret",
+                "serveraddress": "registry.example.com",
+            })
+        );
+        // the same structure `serialize` encodes for the registry headers
+        let encoded = base64::decode_config(auth.serialize(), base64::URL_SAFE).unwrap();
+        assert_eq!(body, serde_json::from_slice::<serde_json::Value>(&encoded).unwrap());
+
+        let response: AuthResponse = serde_json::from_str(
+            r#"{"Status":"Login Succeeded","IdentityToken":"9cbaf023786cd7"}"#,
+        )
+        .unwrap();
+        assert_eq!(response.status, "Login Succeeded");
+        assert_eq!(response.identity_token.as_deref(), Some("9cbaf023786cd7"));
+    }
+
+    /// Test decoding the info of a daemon reporting only a few fields
+    #[test]
+    fn info_minimal() {
+        use super::Info;
+
+        let info: Info = serde_json::from_value(serde_json::json!({
+            "ID": "7TRN:IPZB:QYBB:VPBQ:UWS3:A5UB:4RXH:ZQMW:5JCF:2CYP:BZEV:YWKE",
+            "Name": "farm-01",
+            "Containers": 3,
+            "Images": 12,
+            "NCPU": 8,
+            "MemTotal": 16777216000u64,
+            "DriverStatus": null
+        }))
+        .unwrap();
+        assert_eq!(info.name, "farm-01");
+        assert_eq!(info.containers, 3);
+        assert_eq!(info.n_cpu, Some(8));
+        assert_eq!(info.mem_total, Some(16777216000));
+        assert_eq!(info.driver_status, None);
+        assert_eq!(info.kernel_version, None);
+        assert!(info.runtimes.is_empty());
+
+        let info: Info = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(info.images, 0);
+        assert_eq!(info.mem_total, None);
+        assert_eq!(info.n_cpu, None);
     }
 }
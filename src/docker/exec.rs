@@ -147,7 +147,7 @@ impl<'docker> Exec<'docker> {
         // We convert `self.id` into the (owned) endpoint outside of the stream to not needlessly
         // tie the stream to the lifetime of `self`.
         let path = format!("/exec/{}/start", &self.id);
-        self.docker.post(path, vec![], Some(("{}".into(), Mime::from("application/json"))))
+        self.docker.post(path, vec![], Some(("{}".into(), crate::docker::json())))
     }
 
     /// Inspect this exec instance to aquire detailed information
@@ -172,7 +172,7 @@ impl<'docker> Exec<'docker> {
             .post(
                 &format!("/exec/{}/resize", &self.id),
                 vec![],
-                Some((body, Mime::from("application/json"))),
+                Some((body, crate::docker::json())),
             )
     }
 }
@@ -2,13 +2,20 @@
 //!
 //! API Reference: <https://docs.docker.com/engine/api/v1.41/#tag/Image>
 
-use std::{collections::HashMap, io::Read, iter};
+use std::{collections::{BTreeMap, HashMap}, io::{Read, Write}, iter};
+
+use flate2::{write::GzEncoder, Compression};
 
 use serde::{Deserialize, Serialize};
 use url::form_urlencoded;
 
-use http_types::{Request, Body, Error};
-use crate::docker::{docker::Docker, tarball, tar};
+use http_types::{headers, Request, Body, Error};
+use crate::docker::{docker::{serialize_filters, Docker, QueryOptions}, tarball, tar};
+
+#[cfg(feature = "chrono")]
+use crate::docker::datetime::{datetime_from_rfc3339, datetime_from_unix_timestamp};
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
 
 /// Interface for accessing and manipulating a named docker image
 ///
@@ -42,7 +49,21 @@ impl<'docker> Image<'docker> {
     }
 
     pub  fn delete(&self) -> Result<Request, Error> {
-        self.docker.delete(&format!("/images/{}", self.name))
+        self.remove(&Default::default())
+    }
+
+    /// Removes this image, the response is a list of [`Status`]
+    ///
+    /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ImageDelete>
+    pub fn remove(
+        &self,
+        opts: &ImageRemoveOptions,
+    ) -> Result<Request, Error> {
+        let mut path = vec![format!("/images/{}", self.name)];
+        if let Some(query) = opts.serialize() {
+            path.push(query);
+        }
+        self.docker.delete(&path.join("?"))
     }
 
     /// Export this image to a tarball
@@ -90,15 +111,59 @@ impl<'docker> Images<'docker> {
             path.push(query)
         }
 
+        let mut headers = Vec::<(&str, String)>::new();
+        if let Some(config) = opts.registry_config_header() {
+            headers.push(("X-Registry-Config", config));
+        }
+
+        // the daemon fetches a remote context itself, there is nothing to send
+        if opts.params.contains_key("remote") {
+            if !opts.path.is_empty() {
+                return Err(Error::from_str(
+                    http_types::StatusCode::BadRequest,
+                    "a build takes either a context path or a remote, not both",
+                ));
+            }
+            return self.docker.post_with_header(&path.join("?"), headers, None);
+        }
+
         // To not tie the lifetime of `opts` to the 'stream, we do the tarring work outside of the
         // stream. But for backwards compatability, we have to return the error inside of the
         // stream.
         let mut bytes = Vec::default();
-        let _ = tarball::dir(&mut bytes, opts.path.as_str())?;
+        let _ = tarball::dir(&mut bytes, opts.path.as_str(), !opts.skip_dockerignore)?;
+
+        if opts.gzip {
+            let mut encoder = GzEncoder::new(Vec::default(), Compression::best());
+            encoder.write_all(&bytes)?;
+            bytes = encoder.finish()?;
+            headers.push(("Content-Encoding", "gzip".to_owned()));
+        }
 
         // We must take ownership of the Docker reference. If we don't then the lifetime of 'stream
         // is incorrectly tied to `self`.
-        self.docker.post(&path.join("?"),  Some((Body::from(bytes), tar())))
+        self.docker.post_with_header(&path.join("?"), headers, Some((Body::from(bytes), tar())))
+    }
+
+    /// Builds a new image from a tar build context the caller already has, e.g. an upload.
+    /// The path, gzip and dockerignore settings of `opts` are not used, the daemon
+    /// detects a compressed context by itself.
+    ///
+    /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ImageBuild>
+    pub fn build_from(
+        &self,
+        opts: &BuildOptions,
+        context: Body,
+    ) -> Result<Request, Error> {
+        let mut path = vec!["/build".to_owned()];
+        if let Some(query) = opts.serialize() {
+            path.push(query)
+        }
+        let mut headers = Vec::<(&str, String)>::new();
+        if let Some(config) = opts.registry_config_header() {
+            headers.push(("X-Registry-Config", config));
+        }
+        self.docker.post_with_header(&path.join("?"), headers, Some((context, tar())))
     }
 
     /// Lists the docker images on the current docker host
@@ -133,10 +198,18 @@ impl<'docker> Images<'docker> {
         &self,
         term: &str,
     ) -> Result<Request, Error> {
-        let query = form_urlencoded::Serializer::new(String::new())
-            .append_pair("term", term)
-            .finish();
-        self.docker.get(&format!("/images/search?{}", query))
+        self.search_with(&ImageSearchOptions::builder(term).build())
+    }
+
+    /// Search for docker images with a result limit and filters
+    ///
+    /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ImageSearch>
+    pub fn search_with(
+        &self,
+        opts: &ImageSearchOptions,
+    ) -> Result<Request, Error> {
+        self.docker
+            .get(&format!("/images/search?{}", opts.serialize()))
     }
 
     /// Pull and create a new docker images from an existing image
@@ -189,9 +262,67 @@ impl<'docker> Images<'docker> {
 
         self.docker.post("/images/load", Some((Body::from(bytes), tar())))
     }
+
+    /// imports images from a tarball relayed as a chunked body as it is read, instead of
+    /// collecting it first like `import`. The `encoding` of a compressed tarball, see
+    /// [`TarballEncoding::detect`], is sent as its `Content-Encoding`. That header allows any
+    /// proxy in between to decompress the body and forward it as a plain tar without it,
+    /// which the daemon loads all the same as it tells the compression from the bytes too.
+    ///
+    /// Api Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ImageLoad>
+    pub fn import_stream(
+        &self,
+        tarball: Body,
+        encoding: Option<TarballEncoding>,
+    ) -> Result<Request, Error> {
+        let body = Body::from_reader(tarball, None);
+        let mut request = self.docker.post("/images/load", Some((body, tar())))?;
+        if let Some(encoding) = encoding {
+            request.insert_header(headers::CONTENT_ENCODING, encoding.as_str());
+        }
+        Ok(request)
+    }
 }
 
-#[derive(Clone, Serialize, Debug)]
+/// Compression of an image tarball, which the daemon undoes itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TarballEncoding {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl TarballEncoding {
+    /// bytes of a tarball [`TarballEncoding::detect`] needs to tell every compression
+    pub const MAGIC_LEN: usize = 6;
+
+    /// the compression told by the magic bytes `prefix` starts with, `None` for a plain tar
+    pub fn detect(prefix: &[u8]) -> Option<TarballEncoding> {
+        if prefix.starts_with(&[0x1f, 0x8b]) {
+            Some(TarballEncoding::Gzip)
+        } else if prefix.starts_with(b"BZh") {
+            Some(TarballEncoding::Bzip2)
+        } else if prefix.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(TarballEncoding::Xz)
+        } else if prefix.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(TarballEncoding::Zstd)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TarballEncoding::Gzip => "gzip",
+            TarballEncoding::Bzip2 => "bzip2",
+            TarballEncoding::Xz => "xz",
+            TarballEncoding::Zstd => "zstd",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum RegistryAuth {
     Password {
@@ -233,8 +364,27 @@ impl RegistryAuth {
             .map(|c| base64::encode_config(&c, base64::URL_SAFE))
             .unwrap()
     }
+
+    /// serialize authentication as the JSON map in base64 expected by `build`,
+    /// keyed by the registry host (docker hub when no server address is set)
+    pub fn serialize_config(&self) -> String {
+        let registry = match self {
+            RegistryAuth::Password {
+                server_address: Some(address),
+                ..
+            } => address.clone(),
+            _ => DEFAULT_REGISTRY.to_owned(),
+        };
+        let mut config = HashMap::new();
+        config.insert(registry, self);
+        serde_json::to_string(&config)
+            .map(|c| base64::encode_config(&c, base64::URL_SAFE))
+            .unwrap()
+    }
 }
 
+const DEFAULT_REGISTRY: &str = "https://index.docker.io/v1/";
+
 #[derive(Default)]
 pub struct RegistryAuthBuilder {
     username: Option<String>,
@@ -303,6 +453,12 @@ pub struct TagOptions {
     pub params: HashMap<&'static str, String>,
 }
 
+impl QueryOptions for TagOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
 impl TagOptions {
     /// return a new instance of a builder for options
     pub fn builder() -> TagOptionsBuilder {
@@ -311,15 +467,7 @@ impl TagOptions {
 
     /// serialize options as a string. returns None if no options are defined
     pub fn serialize(&self) -> Option<String> {
-        if self.params.is_empty() {
-            None
-        } else {
-            Some(
-                form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(&self.params)
-                    .finish(),
-            )
-        }
+        QueryOptions::serialize(self)
     }
 }
 
@@ -358,12 +506,83 @@ impl TagOptionsBuilder {
     }
 }
 
+/// Options for removing an image
+#[derive(Default, Debug)]
+pub struct ImageRemoveOptions {
+    params: HashMap<&'static str, String>,
+}
+
+impl QueryOptions for ImageRemoveOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
+impl ImageRemoveOptions {
+    /// return a new instance of a builder for options
+    pub fn builder() -> ImageRemoveOptionsBuilder {
+        ImageRemoveOptionsBuilder::default()
+    }
+
+    /// serialize options as a string. returns None if no options are defined
+    pub fn serialize(&self) -> Option<String> {
+        QueryOptions::serialize(self)
+    }
+}
+
+#[derive(Default)]
+pub struct ImageRemoveOptionsBuilder {
+    params: HashMap<&'static str, String>,
+}
+
+impl ImageRemoveOptionsBuilder {
+    /// remove the image even if it is used by stopped containers or has other tags
+    pub fn force(
+        &mut self,
+        f: bool,
+    ) -> &mut Self {
+        self.params.insert("force", f.to_string());
+        self
+    }
+
+    /// keep the untagged parents of the image
+    pub fn noprune(
+        &mut self,
+        n: bool,
+    ) -> &mut Self {
+        self.params.insert("noprune", n.to_string());
+        self
+    }
+
+    pub fn build(&self) -> ImageRemoveOptions {
+        ImageRemoveOptions {
+            params: self.params.clone(),
+        }
+    }
+}
+
+/// Whether `platform` has the `os/arch[/variant]` form of pull and create, e.g. `linux/amd64`
+/// or `linux/arm/v7`
+pub fn valid_platform(platform: &str) -> bool {
+    let parts: Vec<&str> = platform.split('/').collect();
+    (2..=3).contains(&parts.len())
+        && parts.iter().all(|part| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
 #[derive(Default, Debug)]
 pub struct PullOptions {
     auth: Option<RegistryAuth>,
     params: HashMap<&'static str, String>,
 }
 
+impl QueryOptions for PullOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
 impl PullOptions {
     /// return a new instance of a builder for options
     pub fn builder() -> PullOptionsBuilder {
@@ -372,15 +591,7 @@ impl PullOptions {
 
     /// serialize options as a string. returns None if no options are defined
     pub fn serialize(&self) -> Option<String> {
-        if self.params.is_empty() {
-            None
-        } else {
-            Some(
-                form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(&self.params)
-                    .finish(),
-            )
-        }
+        QueryOptions::serialize(self)
     }
 
     pub(crate) fn auth_header(&self) -> Option<String> {
@@ -448,6 +659,19 @@ impl PullOptionsBuilder {
         self
     }
 
+    /// Platform to pull, `os/arch[/variant]` e.g. `linux/arm64`, when the image is
+    /// multi-arch. Check it with [`valid_platform`] first.
+    pub fn platform<P>(
+        &mut self,
+        p: P,
+    ) -> &mut Self
+    where
+        P: Into<String>,
+    {
+        self.params.insert("platform", p.into());
+        self
+    }
+
     pub fn auth(
         &mut self,
         auth: RegistryAuth,
@@ -467,9 +691,18 @@ impl PullOptionsBuilder {
 #[derive(Default, Debug)]
 pub struct BuildOptions {
     pub path: String,
+    gzip: bool,
+    skip_dockerignore: bool,
+    auth: Option<RegistryAuth>,
     params: HashMap<&'static str, String>,
 }
 
+impl QueryOptions for BuildOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
 impl BuildOptions {
     /// return a new instance of a builder for options
     /// path is expected to be a file path to a directory containing a Dockerfile
@@ -483,21 +716,20 @@ impl BuildOptions {
 
     /// serialize options as a string. returns None if no options are defined
     pub fn serialize(&self) -> Option<String> {
-        if self.params.is_empty() {
-            None
-        } else {
-            Some(
-                form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(&self.params)
-                    .finish(),
-            )
-        }
+        QueryOptions::serialize(self)
+    }
+
+    pub(crate) fn registry_config_header(&self) -> Option<String> {
+        self.auth.as_ref().map(|a| a.serialize_config())
     }
 }
 
 #[derive(Default)]
 pub struct BuildOptionsBuilder {
     path: String,
+    gzip: bool,
+    skip_dockerignore: bool,
+    auth: Option<RegistryAuth>,
     params: HashMap<&'static str, String>,
 }
 
@@ -538,6 +770,8 @@ impl BuildOptionsBuilder {
         self
     }
 
+    /// Build from a remote context the daemon fetches, a git repository, a tarball or a
+    /// Dockerfile url. The options then have to be built with an empty path.
     pub fn remote<R>(
         &mut self,
         r: R,
@@ -602,6 +836,33 @@ impl BuildOptionsBuilder {
         self
     }
 
+    /// leave out the paths matched by `.dockerignore` in the context root. defaults to true
+    pub fn dockerignore(
+        &mut self,
+        enabled: bool,
+    ) -> &mut Self {
+        self.skip_dockerignore = !enabled;
+        self
+    }
+
+    /// gzip the build context before sending it to the daemon. defaults to false
+    pub fn gzip(
+        &mut self,
+        gzip: bool,
+    ) -> &mut Self {
+        self.gzip = gzip;
+        self
+    }
+
+    /// credentials for pulling private base images
+    pub fn auth(
+        &mut self,
+        auth: RegistryAuth,
+    ) -> &mut Self {
+        self.auth = Some(auth);
+        self
+    }
+
     // todo: memswap
     // todo: cpusetcpus
     // todo: cpuperiod
@@ -611,6 +872,9 @@ impl BuildOptionsBuilder {
     pub fn build(&self) -> BuildOptions {
         BuildOptions {
             path: self.path.clone(),
+            gzip: self.gzip,
+            skip_dockerignore: self.skip_dockerignore,
+            auth: self.auth.clone(),
             params: self.params.clone(),
         }
     }
@@ -619,6 +883,8 @@ impl BuildOptionsBuilder {
 /// Filter options for image listings
 pub enum ImageFilter {
     Dangling,
+    /// only images that are tagged or referenced by other images
+    NotDangling,
     LabelName(String),
     Label(String, String),
 }
@@ -629,20 +895,18 @@ pub struct ImageListOptions {
     params: HashMap<&'static str, String>,
 }
 
+impl QueryOptions for ImageListOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
 impl ImageListOptions {
     pub fn builder() -> ImageListOptionsBuilder {
         ImageListOptionsBuilder::default()
     }
     pub fn serialize(&self) -> Option<String> {
-        if self.params.is_empty() {
-            None
-        } else {
-            Some(
-                form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(&self.params)
-                    .finish(),
-            )
-        }
+        QueryOptions::serialize(self)
     }
 }
 
@@ -678,18 +942,17 @@ impl ImageListOptionsBuilder {
         &mut self,
         filters: Vec<ImageFilter>,
     ) -> &mut Self {
-        let mut param = HashMap::new();
+        let mut param: HashMap<&str, Vec<String>> = HashMap::new();
         for f in filters {
-            match f {
-                ImageFilter::Dangling => param.insert("dangling", vec![true.to_string()]),
-                ImageFilter::LabelName(n) => param.insert("label", vec![n]),
-                ImageFilter::Label(n, v) => param.insert("label", vec![format!("{}={}", n, v)]),
+            let (key, value) = match f {
+                ImageFilter::Dangling => ("dangling", true.to_string()),
+                ImageFilter::NotDangling => ("dangling", false.to_string()),
+                ImageFilter::LabelName(n) => ("label", n),
+                ImageFilter::Label(n, v) => ("label", format!("{}={}", n, v)),
             };
+            param.entry(key).or_default().push(value);
         }
-        // structure is a a json encoded object mapping string keys to a list
-        // of string values
-        self.params
-            .insert("filters", serde_json::to_string(&param).unwrap());
+        self.params.insert("filters", serialize_filters(&param));
         self
     }
 
@@ -700,6 +963,78 @@ impl ImageListOptionsBuilder {
     }
 }
 
+/// Filter options for image searches
+pub enum SearchFilter {
+    IsOfficial(bool),
+    IsAutomated(bool),
+    /// only images with at least this many stars
+    Stars(u64),
+}
+
+/// Options for an image search, the search term is required. The params are kept sorted,
+/// so equal options always encode to the same query.
+#[derive(Default, Debug)]
+pub struct ImageSearchOptions {
+    params: BTreeMap<&'static str, String>,
+}
+
+impl ImageSearchOptions {
+    pub fn builder(term: &str) -> ImageSearchOptionsBuilder {
+        ImageSearchOptionsBuilder::new(term)
+    }
+
+    /// url encoded params, never empty as the term is always set
+    pub fn serialize(&self) -> String {
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(&self.params)
+            .finish()
+    }
+}
+
+/// Builder interface for `ImageSearchOptions`
+pub struct ImageSearchOptionsBuilder {
+    params: BTreeMap<&'static str, String>,
+}
+
+impl ImageSearchOptionsBuilder {
+    pub fn new(term: &str) -> Self {
+        let mut params = BTreeMap::new();
+        params.insert("term", term.to_owned());
+        ImageSearchOptionsBuilder { params }
+    }
+
+    pub fn limit(
+        &mut self,
+        limit: u64,
+    ) -> &mut Self {
+        self.params.insert("limit", limit.to_string());
+        self
+    }
+
+    pub fn filter(
+        &mut self,
+        filters: Vec<SearchFilter>,
+    ) -> &mut Self {
+        let mut param: HashMap<&str, Vec<String>> = HashMap::new();
+        for f in filters {
+            let (key, value) = match f {
+                SearchFilter::IsOfficial(b) => ("is-official", b.to_string()),
+                SearchFilter::IsAutomated(b) => ("is-automated", b.to_string()),
+                SearchFilter::Stars(n) => ("stars", n.to_string()),
+            };
+            param.entry(key).or_default().push(value);
+        }
+        self.params.insert("filters", serialize_filters(&param));
+        self
+    }
+
+    pub fn build(&self) -> ImageSearchOptions {
+        ImageSearchOptions {
+            params: self.params.clone(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SearchResult {
     pub description: String,
@@ -733,6 +1068,7 @@ pub struct ImageDetails {
     pub comment: String,
     pub config: Config,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub created: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub created: String,
@@ -742,10 +1078,20 @@ pub struct ImageDetails {
     pub parent: String,
     pub repo_tags: Option<Vec<String>>,
     pub repo_digests: Option<Vec<String>>,
+    #[serde(rename = "RootFS")]
+    pub root_fs: RootFs,
     pub size: u64,
     pub virtual_size: u64,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RootFs {
+    #[serde(rename = "Type")]
+    pub typ: String,
+    pub layers: Option<Vec<String>>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Config {
@@ -786,13 +1132,198 @@ impl Config {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct History {
+    /// `<missing>` for layers built on another host
     pub id: String,
+    /// unix timestamp of the layer
     #[cfg(feature = "chrono")]
     #[serde(deserialize_with = "datetime_from_unix_timestamp")]
     pub created: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub created: u64,
+    #[serde(default)]
+    pub created_by: String,
+    pub tags: Option<Vec<String>>,
+    pub size: u64,
+    #[serde(default)]
+    pub comment: String,
+}
+
+/// One line of the progress the daemon streams while pulling an image
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PullProgress {
+    pub status: String,
+    pub id: Option<String>,
+    #[serde(rename = "progressDetail")]
+    pub progress_detail: Option<ProgressDetail>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ProgressDetail {
+    pub current: Option<u64>,
+    pub total: Option<u64>,
+}
+
+/// Failure reported in the pull stream after the daemon already answered 200
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PullError {
+    pub error: String,
+    #[serde(rename = "errorDetail")]
+    pub error_detail: Option<ErrorDetail>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ErrorDetail {
+    pub code: Option<u16>,
+    pub message: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum PullEvent {
+    Error(PullError),
+    Progress(PullProgress),
+}
+
+impl PullEvent {
+    /// parse one newline delimited line of the pull stream
+    pub fn parse(line: &str) -> serde_json::Result<PullEvent> {
+        serde_json::from_str(line)
+    }
+}
+
+impl PullError {
+    /// http status for the client, the daemon only reports a message for most failures
+    pub fn status(&self) -> u16 {
+        if let Some(code) = self.error_detail.as_ref().and_then(|d| d.code) {
+            if (400..600).contains(&code) {
+                return code;
+            }
+        }
+        let message = self.error.to_lowercase();
+        if message.contains("not found") || message.contains("manifest unknown") {
+            404
+        } else if message.contains("denied") || message.contains("unauthorized") {
+            403
+        } else {
+            502
+        }
+    }
+}
+
+/// Instructions that only change the config of an image, never its filesystem
+const CONFIG_INSTRUCTIONS: &[&str] = &[
+    "ARG", "CMD", "ENTRYPOINT", "ENV", "EXPOSE", "HEALTHCHECK", "LABEL", "MAINTAINER",
+    "ONBUILD", "SHELL", "STOPSIGNAL", "USER", "VOLUME", "WORKDIR",
+];
+
+impl History {
+    /// Whether the step left no layer in the rootfs. The daemon does not tell, so it goes
+    /// by the instruction: the classic builder marks every step but `RUN` with `#(nop)`,
+    /// of which `COPY` and `ADD` still add a layer, BuildKit records the instruction as is.
+    pub fn is_empty_layer(&self) -> bool {
+        let step = self.created_by.trim();
+        if step.is_empty() {
+            return self.size == 0;
+        }
+        let (nop, instruction) = match step.split_once("#(nop)") {
+            Some((_, instruction)) => (true, instruction),
+            None => (false, step),
+        };
+        let keyword = instruction.split_whitespace().next().unwrap_or_default();
+        if nop {
+            !matches!(keyword, "COPY" | "ADD")
+        } else {
+            CONFIG_INSTRUCTIONS.contains(&keyword)
+        }
+    }
+}
+
+/// One line of the output the daemon streams while building an image
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct BuildStep {
+    /// console output of the build, e.g. `Step 1/3 : FROM alpine`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(rename = "errorDetail", skip_serializing_if = "Option::is_none")]
+    pub error_detail: Option<ErrorDetail>,
+    /// structured results, e.g. `{"ID": "sha256:..."}` of the built image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aux: Option<serde_json::Value>,
+    /// progress of pulling a base image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+impl BuildStep {
+    /// parse one newline delimited line of the build output
+    pub fn parse(line: &str) -> serde_json::Result<BuildStep> {
+        serde_json::from_str(line)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// http status for a build that ended with this error step. The code of the detail is
+    /// mostly the exit code of a failed `RUN`, so a failed step is the context's fault.
+    pub fn status(&self) -> u16 {
+        let message = self.error.as_deref().unwrap_or_default().to_lowercase();
+        if message.contains("not found") || message.contains("manifest unknown") {
+            404
+        } else if message.contains("denied") || message.contains("unauthorized") {
+            403
+        } else {
+            422
+        }
+    }
+}
+
+/// Composition of an image, combining the rootfs of `inspect` with the `history` steps
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImageLayers {
+    pub layers: Vec<ImageLayer>,
+    pub total_size: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImageLayer {
+    pub digest: String,
     pub created_by: String,
+    pub size: u64,
+    pub cumulative_size: u64,
+}
+
+impl ImageLayers {
+    /// history is returned newest first and contains metadata only steps (`ENV`, `CMD`, ...)
+    /// that do not produce a filesystem layer, often with a `<missing>` id. Those are skipped,
+    /// the remaining steps, empty ones included, are matched in order with the rootfs layer
+    /// digests.
+    pub fn new(
+        details: &ImageDetails,
+        history: &[History],
+    ) -> ImageLayers {
+        let mut digests = details.root_fs.layers.iter().flatten();
+        let mut total_size = 0;
+        let layers = history
+            .iter()
+            .rev()
+            .filter(|h| !h.is_empty_layer())
+            .map(|h| {
+                total_size += h.size;
+                ImageLayer {
+                    digest: digests.next().cloned().unwrap_or_else(|| h.id.clone()),
+                    created_by: h.created_by.clone(),
+                    size: h.size,
+                    cumulative_size: total_size,
+                }
+            })
+            .collect();
+        ImageLayers { layers, total_size }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -805,6 +1336,360 @@ pub enum Status {
 mod tests {
     use super::*;
 
+    /// Test the unix timestamp of an image list entry becoming a datetime
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn image_info_created_timestamp() {
+        let info: ImageInfo = serde_json::from_value(serde_json::json!({
+            "Created": 1620000000,
+            "Id": "sha256:6dbb9cc54074106d46d4ccb330f2a40a682d49dda5f4844962b7dce9fe44aaec",
+            "ParentId": "",
+            "Labels": null,
+            "RepoTags": ["alpine:3.13"],
+            "RepoDigests": null,
+            "VirtualSize": 5613130
+        }))
+        .unwrap();
+        assert_eq!(info.created.to_rfc3339(), "2021-05-03T00:00:00+00:00");
+    }
+
+    /// Test an uploaded context being sent as is with the tar mime
+    #[async_std::test]
+    async fn build_from_uploaded_context() {
+        let mut context = tar::Builder::new(Vec::new());
+        let dockerfile = b"FROM alpine\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(dockerfile.len() as u64);
+        header.set_cksum();
+        context.append_data(&mut header, "Dockerfile", &dockerfile[..]).unwrap();
+        let context = context.into_inner().unwrap();
+
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let options = BuildOptions::builder("").tag("chia:dev").nocache(true).build();
+        let mut request = docker
+            .images()
+            .build_from(&options, Body::from(context.clone()))
+            .unwrap();
+        assert_eq!(request.url().path(), "/build");
+        let params: HashMap<String, String> = request.url().query_pairs().into_owned().collect();
+        assert_eq!(params["t"], "chia:dev");
+        assert_eq!(params["nocache"], "true");
+        assert_eq!(request.content_type(), Some(tar()));
+        assert_eq!(request.body_bytes().await.unwrap(), context);
+    }
+
+    /// Test parsing a build log that fails on its last step
+    #[test]
+    fn build_step_parse() {
+        let log = r#"{"stream":"Step 1/3 : FROM alpine"}
+{"stream":"\n"}
+{"status":"Pulling from library/alpine","id":"latest"}
+{"stream":" ---\u003e 6dbb9cc54074\n"}
+{"aux":{"ID":"sha256:6dbb9cc54074106d46d4ccb330f2a40a682d49dda5f4844962b7dce9fe44aaec"}}
+{"stream":"Step 2/3 : RUN exit 3"}
+{"errorDetail":{"code":3,"message":"The command '/bin/sh -c exit 3' returned a non-zero code: 3"},"error":"The command '/bin/sh -c exit 3' returned a non-zero code: 3"}"#;
+        let steps: Vec<BuildStep> = log.lines().map(|l| BuildStep::parse(l).unwrap()).collect();
+        assert_eq!(steps.len(), 7);
+        assert_eq!(steps[0].stream.as_deref(), Some("Step 1/3 : FROM alpine"));
+        assert_eq!(steps[2].status.as_deref(), Some("Pulling from library/alpine"));
+        assert_eq!(steps[3].stream.as_deref(), Some(" ---> 6dbb9cc54074\n"));
+        assert!(steps[4].aux.as_ref().unwrap()["ID"].as_str().unwrap().starts_with("sha256:"));
+        assert!(steps[..6].iter().all(|step| !step.is_error()));
+
+        let failed = &steps[6];
+        assert!(failed.is_error());
+        assert_eq!(failed.error_detail.as_ref().unwrap().code, Some(3));
+        assert_eq!(failed.status(), 422);
+        let missing = BuildStep::parse(r#"{"error":"pull access denied for nothere"}"#).unwrap();
+        assert_eq!(missing.status(), 403);
+
+        assert_eq!(
+            serde_json::to_string(&steps[0]).unwrap(),
+            r#"{"stream":"Step 1/3 : FROM alpine"}"#
+        );
+    }
+
+    /// Test the query of options through the shared `QueryOptions` serialization
+    #[test]
+    fn query_options_serialize() {
+        let options = TagOptions::builder().repo("registry.local/chia").tag("1.2").build();
+        let query = QueryOptions::serialize(&options).unwrap();
+        let pairs: HashMap<String, String> =
+            form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+        assert_eq!(pairs["repo"], "registry.local/chia");
+        assert_eq!(pairs["tag"], "1.2");
+        assert_eq!(options.serialize(), Some(query));
+        assert_eq!(options.params().len(), 2);
+
+        assert_eq!(ImageListOptions::builder().build().serialize(), None);
+    }
+
+    /// Test the platform in the pull query and the platforms accepted
+    #[test]
+    fn pull_options_platform() {
+        let options = PullOptions::builder()
+            .image("alpine")
+            .platform("linux/arm64")
+            .build();
+        let query = options.serialize().unwrap();
+        let pairs: HashMap<String, String> =
+            form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+        assert_eq!(pairs["platform"], "linux/arm64");
+        assert_eq!(pairs["fromImage"], "alpine");
+
+        for platform in &["linux/amd64", "linux/arm/v7", "windows/amd64", "linux/x86_64"] {
+            assert!(valid_platform(platform), "{}", platform);
+        }
+        for platform in &["linux", "linux/", "/arm64", "linux/arm/v7/x", "linux/arm 64", ""] {
+            assert!(!valid_platform(platform), "{}", platform);
+        }
+    }
+
+    /// Test the remove query and decoding the untagged/deleted list
+    #[test]
+    fn image_remove_force() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let request = docker
+            .images()
+            .get("alpine:3.13")
+            .remove(&ImageRemoveOptions::builder().force(true).build())
+            .unwrap();
+        assert_eq!(request.method(), http_types::Method::Delete);
+        assert_eq!(request.url().path(), "/images/alpine:3.13");
+        assert_eq!(request.url().query(), Some("force=true"));
+
+        let request = docker.images().get("alpine:3.13").delete().unwrap();
+        assert_eq!(request.url().query(), None);
+
+        let body = r#"[
+            {"Untagged": "alpine:3.13"},
+            {"Untagged": "alpine@sha256:69e70a79f2d41ab5d637de98c1e0b055206ba40a8145e7bddb55ccc04e13cf8f"},
+            {"Deleted": "sha256:6dbb9cc54074106d46d4ccb330f2a40a682d49dda5f4844962b7dce9fe44aaec"}
+        ]"#;
+        let statuses: Vec<Status> = serde_json::from_str(body).unwrap();
+        assert_eq!(statuses.len(), 3);
+        assert!(matches!(&statuses[0], Status::Untagged(name) if name == "alpine:3.13"));
+        assert!(matches!(&statuses[2], Status::Deleted(id) if id.starts_with("sha256:")));
+    }
+
+    /// Test the registry config header on build when auth is configured
+    #[test]
+    fn build_registry_config_header() {
+        let dir = std::env::temp_dir().join("beekeeper-build-auth");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Dockerfile"), "FROM scratch\n").unwrap();
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+
+        let opts = BuildOptions::builder(dir.to_str().unwrap()).build();
+        let request = docker.images().build(&opts).unwrap();
+        assert!(request.header("X-Registry-Config").is_none());
+
+        let auth = RegistryAuth::builder()
+            .username("user_abc")
+            .password("password_abc")
+            .server_address("registry.example.org")
+            .build();
+        let opts = BuildOptions::builder(dir.to_str().unwrap())
+            .auth(auth)
+            .build();
+        let request = docker.images().build(&opts).unwrap();
+        let header = request.header("X-Registry-Config").unwrap().as_str();
+        assert_eq!(
+            base64::encode_config(
+                r#"{"registry.example.org":{"username":"user_abc","password":"password_abc","serveraddress":"registry.example.org"}}"#,
+                base64::URL_SAFE
+            ),
+            header
+        );
+    }
+
+    /// Test the gzip build context and its content encoding header
+    #[test]
+    fn build_gzip_context() {
+        let dir = std::env::temp_dir().join("beekeeper-build-gzip");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Dockerfile"), "FROM scratch\n").unwrap();
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+
+        let opts = BuildOptions::builder(dir.to_str().unwrap()).build();
+        let request = docker.images().build(&opts).unwrap();
+        assert!(request.header("Content-Encoding").is_none());
+
+        let opts = BuildOptions::builder(dir.to_str().unwrap())
+            .gzip(true)
+            .build();
+        let mut request = docker.images().build(&opts).unwrap();
+        assert_eq!(request.header("Content-Encoding").unwrap().as_str(), "gzip");
+
+        let body = futures::executor::block_on(request.body_bytes()).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&body[..]));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["Dockerfile"]);
+    }
+
+    /// Test a remote build sending no context, and refusing a local path next to it
+    #[test]
+    fn build_remote_context() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let remote = "https://github.com/docker-library/hello-world.git";
+        let opts = BuildOptions::builder("").remote(remote).tag("hello").build();
+        let mut request = docker.images().build(&opts).unwrap();
+        let pairs: HashMap<String, String> = request.url().query_pairs().into_owned().collect();
+        assert_eq!(pairs["remote"], remote);
+        assert_eq!(pairs["t"], "hello");
+        assert!(request.content_type().is_none());
+        let body = futures::executor::block_on(request.body_bytes()).unwrap();
+        assert!(body.is_empty());
+
+        let opts = BuildOptions::builder("/tmp").remote(remote).build();
+        let err = docker.images().build(&opts).unwrap_err();
+        assert_eq!(err.status(), 400);
+    }
+
+    /// Test the compression of a tarball being told from its first bytes
+    #[test]
+    fn tarball_encoding_detect() {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(b"layer.tar").unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(TarballEncoding::detect(&gzip), Some(TarballEncoding::Gzip));
+        assert_eq!(TarballEncoding::detect(b"BZh91AY&SY"), Some(TarballEncoding::Bzip2));
+        let xz = [0xfd, b'7', b'z', b'X', b'Z', 0x00, 0x00, 0x04];
+        assert_eq!(TarballEncoding::detect(&xz), Some(TarballEncoding::Xz));
+        let zstd = [0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58];
+        assert_eq!(TarballEncoding::detect(&zstd), Some(TarballEncoding::Zstd));
+
+        // a plain tar starts with the name of its first entry, too short is unknown
+        assert_eq!(TarballEncoding::detect(b"manifest.json\0\0\0"), None);
+        assert_eq!(TarballEncoding::detect(&[0x1f]), None);
+        assert_eq!(TarballEncoding::detect(b""), None);
+
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let request = docker
+            .images()
+            .import_stream(Body::from(gzip), Some(TarballEncoding::Gzip))
+            .unwrap();
+        assert_eq!(request.header(headers::CONTENT_ENCODING).unwrap(), "gzip");
+        assert_eq!(request.len(), None);
+    }
+
+    /// Test decoding a history with layers of other hosts and without tags
+    #[test]
+    fn image_history_decode() {
+        let body = r#"[
+            {
+                "Id": "sha256:6dbb9cc54074106d46d4ccb330f2a40a682d49dda5f4844962b7dce9fe44aaec",
+                "Created": 1618524360,
+                "CreatedBy": "/bin/sh -c #(nop)  CMD [\"/bin/sh\"]",
+                "Tags": ["alpine:3.13"],
+                "Size": 0,
+                "Comment": ""
+            },
+            {
+                "Id": "<missing>",
+                "Created": 1618524359,
+                "CreatedBy": "/bin/sh -c #(nop) ADD file:8ec69d882e7f29f06 in / ",
+                "Tags": null,
+                "Size": 5614125
+            }
+        ]"#;
+        let history: Vec<History> = serde_json::from_str(body).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].tags.as_deref(), Some(&["alpine:3.13".to_owned()][..]));
+        assert_eq!(history[1].id, "<missing>");
+        assert_eq!(history[1].tags, None);
+        assert_eq!(history[1].size, 5614125);
+        assert_eq!(history[1].comment, "");
+        #[cfg(feature = "chrono")]
+        assert_eq!(history[0].created.timestamp(), 1618524360);
+        #[cfg(not(feature = "chrono"))]
+        assert_eq!(history[0].created, 1618524360);
+    }
+
+    /// Test combining inspect and history payloads into the layer view
+    #[test]
+    fn image_layers_from_inspect_and_history() {
+        let details: ImageDetails = serde_json::from_str(
+            r#"{
+                "Id": "sha256:0a1b",
+                "RepoTags": ["app:latest"],
+                "RepoDigests": [],
+                "Parent": "",
+                "Comment": "",
+                "Created": "2021-05-01T10:00:00.000000000Z",
+                "DockerVersion": "20.10.6",
+                "Author": "",
+                "Config": {
+                    "Hostname": "",
+                    "Domainname": "",
+                    "User": "",
+                    "AttachStdin": false,
+                    "AttachStdout": false,
+                    "AttachStderr": false,
+                    "Tty": false,
+                    "OpenStdin": false,
+                    "StdinOnce": false,
+                    "Env": ["PATH=/usr/bin"],
+                    "Cmd": ["/app"],
+                    "Image": "sha256:9f8e",
+                    "WorkingDir": "",
+                    "Entrypoint": null,
+                    "OnBuild": null,
+                    "Labels": null
+                },
+                "Architecture": "amd64",
+                "Os": "linux",
+                "Size": 5600,
+                "VirtualSize": 5600,
+                "RootFS": {
+                    "Type": "layers",
+                    "Layers": ["sha256:aaaa", "sha256:bbbb", "sha256:cccc"]
+                }
+            }"#,
+        )
+        .unwrap();
+        let history: Vec<History> = serde_json::from_str(
+            r#"[
+                {"Id": "sha256:0a1b", "Created": 1619863300, "CreatedBy": "/bin/sh -c #(nop)  CMD [\"/app\"]", "Tags": ["app:latest"], "Size": 0, "Comment": ""},
+                {"Id": "<missing>", "Created": 1619863200, "CreatedBy": "/bin/sh -c touch -c /app", "Tags": null, "Size": 0, "Comment": ""},
+                {"Id": "<missing>", "Created": 1619863100, "CreatedBy": "/bin/sh -c #(nop) COPY file:ab in /app", "Tags": null, "Size": 600, "Comment": ""},
+                {"Id": "<missing>", "Created": 1619863000, "CreatedBy": "/bin/sh -c #(nop)  ENV PATH=/usr/bin", "Tags": null, "Size": 0, "Comment": ""},
+                {"Id": "<missing>", "Created": 1619862900, "CreatedBy": "/bin/sh -c #(nop) ADD file:cd in / ", "Tags": null, "Size": 5000, "Comment": ""}
+            ]"#,
+        )
+        .unwrap();
+
+        let layers = ImageLayers::new(&details, &history);
+        assert_eq!(layers.total_size, 5600);
+        assert_eq!(layers.layers.len(), 3);
+        assert_eq!(layers.layers[0].digest, "sha256:aaaa");
+        assert_eq!(layers.layers[0].size, 5000);
+        assert_eq!(layers.layers[0].cumulative_size, 5000);
+        assert_eq!(layers.layers[1].digest, "sha256:bbbb");
+        assert_eq!(
+            layers.layers[1].created_by,
+            "/bin/sh -c #(nop) COPY file:ab in /app"
+        );
+        assert_eq!(layers.layers[1].cumulative_size, 5600);
+        // a `RUN` changing nothing still adds a layer, of size 0
+        assert_eq!(layers.layers[2].digest, "sha256:cccc");
+        assert_eq!(layers.layers[2].size, 0);
+
+        let buildkit = |created_by: &str, size| History {
+            created_by: created_by.to_owned(),
+            size,
+            ..history[0].clone()
+        };
+        assert!(buildkit("ENV PATH=/usr/bin", 0).is_empty_layer());
+        assert!(buildkit("WORKDIR /app", 0).is_empty_layer());
+        assert!(!buildkit("COPY app /app # buildkit", 0).is_empty_layer());
+        assert!(!buildkit("RUN /bin/sh -c make # buildkit", 1200).is_empty_layer());
+    }
+
     /// Test registry auth with token
     #[test]
     fn registry_auth_token() {
@@ -844,4 +1729,37 @@ mod tests {
             options.serialize()
         );
     }
+
+    /// Test parsing progress lines and the trailing error object of a pull
+    #[test]
+    fn pull_progress_parse() {
+        let event = PullEvent::parse(
+            r#"{"status":"Downloading","progressDetail":{"current":1024,"total":4096},"progress":"[==>  ]","id":"a3ed95caeb02"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            event,
+            PullEvent::Progress(PullProgress {
+                status: "Downloading".to_owned(),
+                id: Some("a3ed95caeb02".to_owned()),
+                progress_detail: Some(ProgressDetail {
+                    current: Some(1024),
+                    total: Some(4096),
+                }),
+            })
+        );
+
+        let event = PullEvent::parse(r#"{"status":"Pulling from library/alpine","id":"latest"}"#)
+            .unwrap();
+        assert!(matches!(event, PullEvent::Progress(PullProgress { progress_detail: None, .. })));
+
+        let event = PullEvent::parse(
+            r#"{"errorDetail":{"message":"manifest for alpine:nope not found: manifest unknown"},"error":"manifest for alpine:nope not found: manifest unknown"}"#,
+        )
+        .unwrap();
+        match event {
+            PullEvent::Error(error) => assert_eq!(error.status(), 404),
+            _ => panic!("expected an error"),
+        }
+    }
 }
@@ -9,11 +9,20 @@ pub mod container;
 pub mod network;
 pub mod service;
 pub mod volume;
+pub mod stream;
+pub mod upgrade;
 
 pub mod tarball;
+#[cfg(feature = "chrono")]
+pub(crate) mod datetime;
 
 
 
 pub fn tar() -> Mime {
     "application/tar".parse().unwrap()
+}
+
+/// `application/json`, the body type of every json request to the daemon
+pub fn json() -> Mime {
+    http_types::mime::JSON
 }
\ No newline at end of file
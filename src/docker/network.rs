@@ -8,13 +8,15 @@ use std::{
 };
 
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use url::form_urlencoded;
+use serde_json::{json, Map, Value};
 
-use http_types::{Mime, Request, Body, Error};
+use http_types::{Request, Body, Error};
 
 use crate::{
-    docker::docker::Docker,
+    docker::{
+        container::null_as_default,
+        docker::{Docker, QueryOptions},
+    },
 };
 
 /// Interface for docker network
@@ -66,7 +68,7 @@ impl<'docker> Networks<'docker> {
         let path = vec!["/networks/create".to_owned()];
 
         self.docker
-            .post(&path.join("?"), Some((body, Mime::from("application/json"))))
+            .post(&path.join("?"), Some((body, crate::docker::json())))
     }
 }
 
@@ -104,6 +106,18 @@ impl<'docker> Network<'docker> {
             .get(&format!("/networks/{}", self.id))
     }
 
+    /// Inspects the network with `verbose` and `scope` params, verbose swarm networks
+    /// include the load-balancer info of their services
+    ///
+    /// API Reference: <https://docs.docker.com/engine/api/v1.41/#operation/NetworkInspect>
+    pub fn inspect_with(&self, opts: &NetworkInspectOptions) -> Result<Request, Error> {
+        let mut path = vec![format!("/networks/{}", self.id)];
+        if let Some(query) = opts.serialize() {
+            path.push(query);
+        }
+        self.docker.get(&path.join("?"))
+    }
+
     /// Delete the network instance
     ///
     /// API Reference: <https://docs.docker.com/engine/api/v1.41/#operation/NetworkDelete>
@@ -115,11 +129,12 @@ impl<'docker> Network<'docker> {
     /// Connect container to network
     ///
     /// API Reference: <https://docs.docker.com/engine/api/v1.41/#operation/NetworkConnect>
-    pub  fn connect(
-        &self,
-        opts: &ContainerConnectionOptions,
-    ) -> Result<Request, Error> {
-        self.do_connection("connect", opts)
+    pub fn connect(&self, opts: &NetworkConnectOptions) -> Result<Request, Error> {
+        let body: Body = opts.serialize()?.into();
+        self.docker.post(
+            &format!("/networks/{}/connect", self.id),
+            Some((body, crate::docker::json())),
+        )
     }
 
     /// Disconnect container to network
@@ -142,7 +157,7 @@ impl<'docker> Network<'docker> {
         self.docker
             .post(
                 &format!("/networks/{}/{}", self.id, segment),
-                Some((body, Mime::from("application/json"))),
+                Some((body, crate::docker::json())),
             )
     }
 }
@@ -153,17 +168,65 @@ pub struct NetworkListOptions {
     params: HashMap<&'static str, String>,
 }
 
+impl QueryOptions for NetworkListOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
 impl NetworkListOptions {
     /// serialize options as a string. returns None if no options are defined
     pub fn serialize(&self) -> Option<String> {
-        if self.params.is_empty() {
-            None
-        } else {
-            Some(
-                form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(&self.params)
-                    .finish(),
-            )
+        QueryOptions::serialize(self)
+    }
+}
+
+/// Options of a network inspect
+#[derive(Default, Debug)]
+pub struct NetworkInspectOptions {
+    params: HashMap<&'static str, String>,
+}
+
+impl QueryOptions for NetworkInspectOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
+impl NetworkInspectOptions {
+    /// return a new instance of a builder for options
+    pub fn builder() -> NetworkInspectOptionsBuilder {
+        NetworkInspectOptionsBuilder::default()
+    }
+
+    /// serialize options as a string. returns None if no options are defined
+    pub fn serialize(&self) -> Option<String> {
+        QueryOptions::serialize(self)
+    }
+}
+
+/// Builder interface for `NetworkInspectOptions`
+#[derive(Default)]
+pub struct NetworkInspectOptionsBuilder {
+    params: HashMap<&'static str, String>,
+}
+
+impl NetworkInspectOptionsBuilder {
+    /// detailed info of the services of a swarm network
+    pub fn verbose(&mut self, verbose: bool) -> &mut Self {
+        self.params.insert("verbose", verbose.to_string());
+        self
+    }
+
+    /// only look for the network in `swarm`, `global` or `local` scope
+    pub fn scope(&mut self, scope: &str) -> &mut Self {
+        self.params.insert("scope", scope.to_owned());
+        self
+    }
+
+    pub fn build(&self) -> NetworkInspectOptions {
+        NetworkInspectOptions {
+            params: self.params.clone(),
         }
     }
 }
@@ -229,10 +292,51 @@ impl NetworkCreateOptionsBuilder {
         &mut self,
         labels: HashMap<String, String>,
     ) -> &mut Self {
+        self.labels(labels)
+    }
+
+    pub fn labels(&mut self, labels: HashMap<String, String>) -> &mut Self {
         self.params.insert("Labels", json!(labels));
         self
     }
 
+    /// add an address pool to the `IPAM` config of the default driver, called again
+    /// for every further pool, e.g. an IPv6 one
+    pub fn ipam(
+        &mut self,
+        subnet: &str,
+        gateway: Option<&str>,
+        ip_range: Option<&str>,
+    ) -> &mut Self {
+        let mut pool = json!({ "Subnet": subnet });
+        if let Some(gateway) = gateway {
+            pool["Gateway"] = json!(gateway);
+        }
+        if let Some(ip_range) = ip_range {
+            pool["IPRange"] = json!(ip_range);
+        }
+        let ipam = self
+            .params
+            .entry("IPAM")
+            .or_insert_with(|| json!({ "Driver": "default", "Config": [] }));
+        if let Some(pools) = ipam["Config"].as_array_mut() {
+            pools.push(pool);
+        }
+        self
+    }
+
+    /// no access to the outside of the network
+    pub fn internal(&mut self, internal: bool) -> &mut Self {
+        self.params.insert("Internal", json!(internal));
+        self
+    }
+
+    /// standalone containers may attach to the swarm network
+    pub fn attachable(&mut self, attachable: bool) -> &mut Self {
+        self.params.insert("Attachable", json!(attachable));
+        self
+    }
+
     pub fn build(&self) -> NetworkCreateOptions {
         NetworkCreateOptions {
             params: self.params.clone(),
@@ -240,6 +344,59 @@ impl NetworkCreateOptionsBuilder {
     }
 }
 
+/// Body of a network connect: the container and its endpoint on the network
+#[derive(Serialize, Debug)]
+pub struct NetworkConnectOptions {
+    params: HashMap<&'static str, Value>,
+}
+
+impl NetworkConnectOptions {
+    /// return a new instance of a builder for options
+    pub fn builder() -> NetworkConnectOptionsBuilder {
+        NetworkConnectOptionsBuilder::default()
+    }
+
+    pub fn serialize(&self) -> crate::errors::Result<String> {
+        serde_json::to_string(&self.params).map_err(crate::errors::Error::from)
+    }
+}
+
+#[derive(Default)]
+pub struct NetworkConnectOptionsBuilder {
+    params: HashMap<&'static str, Value>,
+    endpoint: Map<String, Value>,
+}
+
+impl NetworkConnectOptionsBuilder {
+    /// id or name of the container to connect
+    pub fn container(&mut self, id: &str) -> &mut Self {
+        self.params.insert("Container", json!(id));
+        self
+    }
+
+    /// names the container is also reachable by on the network
+    pub fn aliases<S: AsRef<str>>(&mut self, aliases: &[S]) -> &mut Self {
+        let aliases: Vec<&str> = aliases.iter().map(AsRef::as_ref).collect();
+        self.endpoint.insert("Aliases".to_owned(), json!(aliases));
+        self
+    }
+
+    /// static address of the container, within a subnet of the network
+    pub fn ipv4(&mut self, address: &str) -> &mut Self {
+        self.endpoint
+            .insert("IPAMConfig".to_owned(), json!({ "IPv4Address": address }));
+        self
+    }
+
+    pub fn build(&self) -> NetworkConnectOptions {
+        let mut params = self.params.clone();
+        if !self.endpoint.is_empty() {
+            params.insert("EndpointConfig", Value::Object(self.endpoint.clone()));
+        }
+        NetworkConnectOptions { params }
+    }
+}
+
 /// Interface for connect container to network
 #[derive(Serialize, Debug)]
 pub struct ContainerConnectionOptions {
@@ -376,6 +533,8 @@ pub struct NetworkInfo {
 #[serde(rename_all = "PascalCase")]
 pub struct Ipam {
     pub driver: String,
+    /// `Subnet`, `Gateway` and `IPRange` of every address pool
+    #[serde(default, deserialize_with = "null_as_default")]
     pub config: Vec<HashMap<String, String>>,
     pub options: Option<HashMap<String, String>>,
 }
@@ -393,14 +552,21 @@ pub struct NetworkDetails {
     pub ipam: Ipam,
     pub internal: bool,
     pub attachable: bool,
+    /// the containers attached on the daemon asked, keyed by container id
+    #[serde(default, deserialize_with = "null_as_default")]
     pub containers: HashMap<String, NetworkContainerDetails>,
     pub options: Option<HashMap<String, String>>,
     pub labels: Option<HashMap<String, String>>,
+    /// load-balancer info of the services of a swarm network, for a verbose inspect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub services: Option<HashMap<String, Value>>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct NetworkContainerDetails {
+    #[serde(default)]
+    pub name: String,
     #[serde(rename = "EndpointID")]
     pub endpoint_id: String,
     pub mac_address: String,
@@ -416,3 +582,131 @@ pub struct NetworkCreateInfo {
     pub id: String,
     pub warning: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test the create body with two address pools and the top-level fields
+    #[test]
+    fn network_create_ipam() {
+        let options = NetworkCreateOptions::builder("backend")
+            .driver("bridge")
+            .ipam("172.28.0.0/16", Some("172.28.5.254"), Some("172.28.5.0/24"))
+            .ipam("2001:db8:abcd::/64", None, None)
+            .internal(true)
+            .attachable(false)
+            .labels(vec![("env".to_owned(), "prod".to_owned())].into_iter().collect())
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "Name": "backend",
+                "Driver": "bridge",
+                "IPAM": {
+                    "Driver": "default",
+                    "Config": [
+                        {
+                            "Subnet": "172.28.0.0/16",
+                            "Gateway": "172.28.5.254",
+                            "IPRange": "172.28.5.0/24"
+                        },
+                        { "Subnet": "2001:db8:abcd::/64" }
+                    ]
+                },
+                "Internal": true,
+                "Attachable": false,
+                "Labels": { "env": "prod" }
+            })
+        );
+    }
+
+    /// Test the connect body carrying the aliases and static address in the endpoint
+    #[test]
+    fn network_connect_body() {
+        let options = NetworkConnectOptions::builder()
+            .container("web")
+            .aliases(&["web", "frontend"])
+            .ipv4("172.28.5.10")
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "Container": "web",
+                "EndpointConfig": {
+                    "Aliases": ["web", "frontend"],
+                    "IPAMConfig": { "IPv4Address": "172.28.5.10" }
+                }
+            })
+        );
+
+        let options = NetworkConnectOptions::builder().container("web").build();
+        assert_eq!(options.serialize().unwrap(), r#"{"Container":"web"}"#);
+    }
+
+    /// Test the inspect params and the details of a verbose swarm network inspect
+    #[test]
+    fn network_details_verbose() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let options = NetworkInspectOptions::builder().verbose(true).scope("swarm").build();
+        let request = docker.networks().get("backend").inspect_with(&options).unwrap();
+        let params: HashMap<String, String> = request.url().query_pairs().into_owned().collect();
+        assert_eq!(params["verbose"], "true");
+        assert_eq!(params["scope"], "swarm");
+
+        let body = r#"{
+            "Name": "backend",
+            "Id": "7d86d31b1478e7cca9ebed7e73aa0fdeec46c5ca29497431d3007d2d9e15ed99",
+            "Created": "2021-06-01T09:14:33.261913765Z",
+            "Scope": "swarm",
+            "Driver": "overlay",
+            "EnableIPv6": false,
+            "IPAM": {
+                "Driver": "default",
+                "Options": null,
+                "Config": [{ "Subnet": "10.0.1.0/24", "Gateway": "10.0.1.1" }]
+            },
+            "Internal": false,
+            "Attachable": true,
+            "Containers": {
+                "19a4d5d687db25203351ed79d478946f861258f018fe384f229f2efa4b23513c": {
+                    "Name": "web.1.kq8nbh4fzb2u",
+                    "EndpointID": "628cadb8bcb92de107b2a1e516cbffe463e321f548feb37697cce00ad694f21a",
+                    "MacAddress": "02:42:0a:00:01:03",
+                    "IPv4Address": "10.0.1.3/24",
+                    "IPv6Address": ""
+                }
+            },
+            "Options": { "com.docker.network.driver.overlay.vxlanid_list": "4097" },
+            "Labels": {},
+            "Services": {
+                "web": {
+                    "VIP": "10.0.1.2",
+                    "Ports": [],
+                    "LocalLBIndex": 257,
+                    "Tasks": [{ "Name": "web.1.kq8nbh4fzb2u", "EndpointIP": "10.0.1.3" }]
+                }
+            }
+        }"#;
+        let details: NetworkDetails = serde_json::from_str(body).unwrap();
+        assert_eq!(details.ipam.config[0]["Subnet"], "10.0.1.0/24");
+        let container = details.containers.values().next().unwrap();
+        assert_eq!(container.name, "web.1.kq8nbh4fzb2u");
+        assert_eq!(container.ipv4_address, "10.0.1.3/24");
+        let options = details.options.unwrap();
+        assert_eq!(options["com.docker.network.driver.overlay.vxlanid_list"], "4097");
+        assert_eq!(details.services.unwrap()["web"]["VIP"], "10.0.1.2");
+
+        let body = r#"{
+            "Name": "bridge", "Id": "f2de39df4171", "Scope": "local", "Driver": "bridge",
+            "EnableIPv6": false, "IPAM": { "Driver": "default", "Config": null },
+            "Internal": false, "Attachable": false, "Containers": null
+        }"#;
+        let details: NetworkDetails = serde_json::from_str(body).unwrap();
+        assert!(details.containers.is_empty());
+        assert!(details.ipam.config.is_empty());
+        assert!(details.services.is_none());
+    }
+}
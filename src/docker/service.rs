@@ -4,18 +4,20 @@
 
 use std::{collections::HashMap, iter};
 
-use http_types::{Mime, Request, Body, Error};
+use http_types::{Request, Body, Error};
 
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use url::form_urlencoded;
 
 use crate::docker::{
-    docker::Docker,
+    docker::{serialize_filters, Docker, QueryOptions},
     container::LogsOptions,
     image::RegistryAuth,
 };
 
+#[cfg(feature = "chrono")]
+use crate::docker::datetime::datetime_from_rfc3339;
 #[cfg(feature = "chrono")]
 use chrono::{DateTime, Utc};
 
@@ -54,6 +56,28 @@ impl<'docker> Services<'docker> {
     ) -> Service<'docker> {
         Service::new(self.docker, name)
     }
+
+    /// Creates a new service from ServiceOptions
+    ///
+    /// API Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ServiceCreate>
+    pub fn create(
+        &self,
+        opts: &ServiceOptions,
+    ) -> Result<Request, Error> {
+        let body: Body = opts.serialize()?.into();
+
+        let mut headers = Vec::<(&str, String)>::new();
+        opts
+            .auth_header()
+            .map(|a| iter::once(headers.push(("X-Registry-Auth", a))));
+
+        self.docker
+            .post_with_header (
+                "/services/create",
+                headers,
+                Some((body, crate::docker::json())),
+            )
+    }
 }
 
 /// Interface for accessing and manipulating a named docker volume
@@ -85,9 +109,23 @@ impl<'docker> Service<'docker> {
     pub  fn create(
         &self,
         opts: &ServiceOptions,
+    ) -> Result<Request, Error> {
+        Services::new(self.docker).create(opts)
+    }
+
+    /// Updates the service to the spec of ServiceOptions. `version` is the version of the
+    /// service object being updated, as returned by inspect.
+    ///
+    /// API Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ServiceUpdate>
+    pub  fn update(
+        &self,
+        version: u64,
+        opts: &ServiceOptions,
     ) -> Result<Request, Error> {
         let body: Body = opts.serialize()?.into();
-        let path = vec!["/service/create".to_owned()];
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("version", &version.to_string())
+            .finish();
 
         let mut headers = Vec::<(&str, String)>::new();
         opts
@@ -96,9 +134,9 @@ impl<'docker> Service<'docker> {
 
         self.docker
             .post_with_header (
-                &path.join("?"),
+                &format!("/services/{}/update?{}", self.name, query),
                 headers,
-                Some((body, Mime::from("application/json"))),
+                Some((body, crate::docker::json())),
             )
     }
 
@@ -140,6 +178,12 @@ pub struct ServiceListOptions {
     params: HashMap<&'static str, String>,
 }
 
+impl QueryOptions for ServiceListOptions {
+    fn params(&self) -> &HashMap<&'static str, String> {
+        &self.params
+    }
+}
+
 impl ServiceListOptions {
     /// return a new instance of a builder for options
     pub fn builder() -> ServiceListOptionsBuilder {
@@ -148,15 +192,7 @@ impl ServiceListOptions {
 
     /// serialize options as a string. returns None if no options are defined
     pub fn serialize(&self) -> Option<String> {
-        if self.params.is_empty() {
-            None
-        } else {
-            Some(
-                form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(&self.params)
-                    .finish(),
-            )
-        }
+        QueryOptions::serialize(self)
     }
 }
 
@@ -192,10 +228,7 @@ impl ServiceListOptionsBuilder {
                 ServiceFilter::Name(n) => param.insert("name", vec![n.to_string()]),
             };
         }
-        // structure is a a json encoded object mapping string keys to a list
-        // of string values
-        self.params
-            .insert("filters", serde_json::to_string(&param).unwrap());
+        self.params.insert("filters", serialize_filters(&param));
         self
     }
 
@@ -211,10 +244,13 @@ impl ServiceListOptionsBuilder {
     }
 }
 
-#[derive(Default, Debug)]
+/// Service spec, the json body of create and update
+#[derive(Default, Debug, Deserialize)]
 pub struct ServiceOptions {
+    #[serde(skip)]
     auth: Option<RegistryAuth>,
-    params: HashMap<&'static str, Value>,
+    #[serde(flatten)]
+    params: HashMap<String, Value>,
 }
 
 impl ServiceOptions {
@@ -337,7 +373,7 @@ impl ServiceOptionsBuilder {
         let params = std::mem::take(&mut self.params);
         let mut new_params = HashMap::new();
         for (k, v) in params.into_iter() {
-            new_params.insert(k, v?);
+            new_params.insert(k.to_owned(), v?);
         }
         Ok(ServiceOptions {
             auth: self.auth.take(),
@@ -362,10 +398,12 @@ pub struct ServiceInfo {
     pub id: String,
     pub version: ObjectVersion,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub created_at: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub created_at: String,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub updated_at: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub updated_at: String,
@@ -412,10 +450,12 @@ pub struct EndpointPortConfig {
 pub struct UpdateStatus {
     pub state: String,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub started_at: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub started_at: String,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub completed_at: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub completed_at: String,
@@ -435,6 +475,7 @@ pub struct ServiceStatus {
 pub struct JobStatus {
     pub job_iteration: ObjectVersion,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub last_execution: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub last_execution: String,
@@ -447,10 +488,12 @@ pub struct ServiceDetails {
     pub id: String,
     pub version: ObjectVersion,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub created_at: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub created_at: String,
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub updated_at: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub updated_at: String,
@@ -529,3 +572,67 @@ pub struct ServiceCreateInfo {
     #[serde(rename = "Warning")]
     pub warning: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_types::Method;
+
+    #[test]
+    fn service_routes() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let opts: ServiceOptions = serde_json::from_str(r#"{"Name":"web"}"#).unwrap();
+
+        let request = docker.services().list(&Default::default()).unwrap();
+        assert_eq!(request.method(), Method::Get);
+        assert_eq!(request.url().path(), "/services");
+
+        let request = docker.services().create(&opts).unwrap();
+        assert_eq!(request.method(), Method::Post);
+        assert_eq!(request.url().path(), "/services/create");
+
+        let request = docker.services().get("web").inspect().unwrap();
+        assert_eq!(request.method(), Method::Get);
+        assert_eq!(request.url().path(), "/services/web");
+
+        let request = docker.services().get("web").update(12, &opts).unwrap();
+        assert_eq!(request.method(), Method::Post);
+        assert_eq!(request.url().path(), "/services/web/update");
+        assert_eq!(request.url().query(), Some("version=12"));
+
+        let request = docker.services().get("web").delete().unwrap();
+        assert_eq!(request.method(), Method::Delete);
+        assert_eq!(request.url().path(), "/services/web");
+    }
+
+    #[test]
+    fn service_logs_query() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let opts = LogsOptions::builder()
+            .follow(true)
+            .stdout(true)
+            .stderr(false)
+            .timestamps(true)
+            .tail("100")
+            .since_nanos("1619863200")
+            .build();
+        let request = docker.services().get("web").logs(&opts).unwrap();
+        assert_eq!(request.method(), Method::Get);
+        assert_eq!(request.url().path(), "/services/web/logs");
+
+        let mut pairs: Vec<(String, String)> = request.url().query_pairs().into_owned().collect();
+        pairs.sort();
+        let expected: Vec<(String, String)> = vec![
+            ("follow", "true"),
+            ("since", "1619863200"),
+            ("stderr", "false"),
+            ("stdout", "true"),
+            ("tail", "100"),
+            ("timestamps", "true"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect();
+        assert_eq!(pairs, expected);
+    }
+}
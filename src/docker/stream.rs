@@ -0,0 +1,718 @@
+//! Decode the multiplexed stdout/stderr framing of attach and logs.
+//!
+//! Without a TTY the daemon prefixes every chunk with an 8 byte header: the stream type,
+//! three zero bytes and the big endian length of the payload.
+//!
+//! API Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ContainerAttach>
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, FixedOffset};
+use futures::{stream::Stream, AsyncBufReadExt, AsyncReadExt, StreamExt, TryStreamExt};
+use http_types::Body;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::docker::docker::Event;
+
+const HEADER_LEN: usize = 8;
+const READ_CHUNK: usize = 8 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamType {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl StreamType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StreamType::Stdin => "stdin",
+            StreamType::Stdout => "stdout",
+            StreamType::Stderr => "stderr",
+        }
+    }
+
+    fn from_byte(b: u8) -> Option<StreamType> {
+        match b {
+            0 => Some(StreamType::Stdin),
+            1 => Some(StreamType::Stdout),
+            2 => Some(StreamType::Stderr),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub stream: StreamType,
+    pub data: Vec<u8>,
+}
+
+/// Incremental frame decoder. Output of TTY containers carries no headers, this is detected
+/// on the first bytes and everything is then passed through as stdout.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    raw: Option<bool>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        FrameDecoder::default()
+    }
+
+    pub fn push(
+        &mut self,
+        bytes: &[u8],
+    ) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// the next complete frame in the buffer, if any
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        if self.raw.is_none() && self.buf.len() >= HEADER_LEN {
+            let framed = StreamType::from_byte(self.buf[0]).is_some() && self.buf[1..4] == [0, 0, 0];
+            self.raw = Some(!framed);
+        }
+        match self.raw {
+            Some(true) if !self.buf.is_empty() => Some(Frame {
+                stream: StreamType::Stdout,
+                data: std::mem::take(&mut self.buf),
+            }),
+            Some(false) if self.buf.len() >= HEADER_LEN => {
+                let len = u32::from_be_bytes([self.buf[4], self.buf[5], self.buf[6], self.buf[7]])
+                    as usize;
+                if self.buf.len() < HEADER_LEN + len {
+                    return None;
+                }
+                let stream = StreamType::from_byte(self.buf[0]).unwrap_or(StreamType::Stdout);
+                let data = self.buf[HEADER_LEN..HEADER_LEN + len].to_vec();
+                self.buf.drain(..HEADER_LEN + len);
+                Some(Frame { stream, data })
+            }
+            _ => None,
+        }
+    }
+
+    /// called once the input ended, returns what is left over as a last frame
+    pub fn finish(&mut self) -> io::Result<Option<Frame>> {
+        if self.buf.is_empty() {
+            Ok(None)
+        } else if self.raw != Some(false) {
+            Ok(Some(Frame {
+                stream: StreamType::Stdout,
+                data: std::mem::take(&mut self.buf),
+            }))
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream ended inside a frame",
+            ))
+        }
+    }
+}
+
+impl Frame {
+    /// server-sent event named after the stream, the payload is decoded as lossy utf-8
+    pub fn to_sse(&self) -> Vec<u8> {
+        let mut event = format!("event: {}\n", self.stream.as_str());
+        for line in String::from_utf8_lossy(&self.data).split('\n') {
+            event.push_str("data: ");
+            event.push_str(line.trim_end_matches('\r'));
+            event.push('\n');
+        }
+        event.push('\n');
+        event.into_bytes()
+    }
+}
+
+/// Decode all frames of a complete buffer
+pub fn decode(bytes: &[u8]) -> io::Result<Vec<Frame>> {
+    let mut decoder = FrameDecoder::new();
+    decoder.push(bytes);
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.next_frame() {
+        frames.push(frame);
+    }
+    frames.extend(decoder.finish()?);
+    Ok(frames)
+}
+
+/// Decode the frames of a daemon body as they arrive
+pub fn frames(body: Body) -> impl Stream<Item = io::Result<Frame>> + Send + Sync + Unpin {
+    Box::pin(futures::stream::unfold(
+        Some((body, FrameDecoder::new())),
+        |state| async move {
+            let (mut body, mut decoder) = state?;
+            loop {
+                if let Some(frame) = decoder.next_frame() {
+                    return Some((Ok(frame), Some((body, decoder))));
+                }
+                let mut chunk = vec![0; READ_CHUNK];
+                match body.read(&mut chunk).await {
+                    Ok(0) => {
+                        return match decoder.finish() {
+                            Ok(Some(frame)) => Some((Ok(frame), None)),
+                            Ok(None) => None,
+                            Err(e) => Some((Err(e), None)),
+                        }
+                    }
+                    Ok(n) => decoder.push(&chunk[..n]),
+                    Err(e) => return Some((Err(e), None)),
+                }
+            }
+        },
+    ))
+}
+
+/// Timestamp prefix of a log line requested with `timestamps=true`
+pub fn line_timestamp(line: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let end = line.iter().position(|b| *b == b' ').unwrap_or(line.len());
+    let prefix = std::str::from_utf8(&line[..end]).ok()?;
+    DateTime::parse_from_rfc3339(prefix).ok()
+}
+
+/// A log line split from the timestamp it starts with when asked with `timestamps=true`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LogLine {
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub stream: StreamType,
+    pub line: String,
+}
+
+impl LogLine {
+    /// the lines of `frame`, decoded as lossy utf-8. The daemon sends a frame per line,
+    /// the output of TTY containers may hold several or end mid-line. Only with
+    /// `timestamps` the lines start with the daemon's timestamp, otherwise a leading
+    /// timestamp is the application's own and kept in the line.
+    pub fn split(frame: &Frame, timestamps: bool) -> Vec<LogLine> {
+        let text = String::from_utf8_lossy(&frame.data);
+        text.strip_suffix('\n')
+            .unwrap_or(&text)
+            .split('\n')
+            .map(|line| {
+                let line = line.trim_end_matches('\r');
+                let timestamp = if timestamps {
+                    line_timestamp(line.as_bytes())
+                } else {
+                    None
+                };
+                let line = match timestamp {
+                    Some(_) => line.split_once(' ').map_or("", |(_, rest)| rest),
+                    None => line,
+                };
+                LogLine {
+                    timestamp,
+                    stream: frame.stream,
+                    line: line.to_owned(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The lines of a stream of log frames, see [`LogLine::split`]
+pub fn log_lines<S>(
+    frames: S,
+    timestamps: bool,
+) -> impl Stream<Item = io::Result<LogLine>> + Send + Unpin
+where
+    S: Stream<Item = io::Result<Frame>> + Send + Unpin + 'static,
+{
+    Box::pin(
+        frames
+            .map_ok(move |frame| {
+                futures::stream::iter(LogLine::split(&frame, timestamps).into_iter().map(Ok))
+            })
+            .try_flatten(),
+    )
+}
+
+/// Follow a timestamped log stream across the ends caused by restarts or log rotation.
+///
+/// When `body` ends, `open` is called with the `since` of the last line seen, formatted
+/// as a unix timestamp with nanoseconds. `since` is inclusive, so the lines a reconnect
+/// replays that are not newer than the last one seen are dropped, until the first newer
+/// one. At most `max_reconnects` reconnects in a row are made without receiving a new line.
+pub fn follow<F, Fut>(
+    body: Body,
+    open: F,
+    max_reconnects: usize,
+) -> impl Stream<Item = io::Result<Frame>> + Send + Unpin
+where
+    F: FnMut(String) -> Fut + Send + 'static,
+    Fut: Future<Output = io::Result<Body>> + Send + 'static,
+{
+    struct Follow<F> {
+        open: F,
+        frames: Option<Pin<Box<dyn Stream<Item = io::Result<Frame>> + Send + Sync>>>,
+        last: Option<DateTime<FixedOffset>>,
+        reconnects: usize,
+        /// the stream was reopened and has not passed the last line seen yet
+        replaying: bool,
+    }
+
+    let state = Follow {
+        open,
+        frames: Some(Box::pin(frames(body))),
+        last: None,
+        reconnects: 0,
+        replaying: false,
+    };
+    Box::pin(futures::stream::unfold(Some(state), move |state| async move {
+        let mut state = state?;
+        loop {
+            if state.frames.is_none() {
+                let since = match state.last {
+                    Some(last) if state.reconnects < max_reconnects => format!(
+                        "{}.{:09}",
+                        last.timestamp(),
+                        last.timestamp_subsec_nanos()
+                    ),
+                    _ => return None,
+                };
+                state.reconnects += 1;
+                state.replaying = true;
+                log::debug!("log stream ended, reconnect since {}", since);
+                match (state.open)(since).await {
+                    Ok(body) => state.frames = Some(Box::pin(frames(body))),
+                    Err(e) => return Some((Err(e), None)),
+                }
+            }
+            let current = state.frames.as_mut().unwrap();
+            match current.next().await {
+                Some(Ok(frame)) => {
+                    let timestamp = line_timestamp(&frame.data);
+                    if let (true, Some(timestamp), Some(last)) =
+                        (state.replaying, timestamp, state.last)
+                    {
+                        if timestamp <= last {
+                            continue;
+                        }
+                    }
+                    if timestamp.is_some() {
+                        state.last = timestamp;
+                        state.reconnects = 0;
+                        state.replaying = false;
+                    }
+                    return Some((Ok(frame), Some(state)));
+                }
+                Some(Err(e)) => return Some((Err(e), None)),
+                None => state.frames = None,
+            }
+        }
+    }))
+}
+
+/// Raw chunks of a daemon body as they arrive
+pub fn chunks(body: Body) -> impl Stream<Item = io::Result<Vec<u8>>> + Send + Sync + Unpin {
+    Box::pin(futures::stream::unfold(Some(body), |body| async move {
+        let mut body = body?;
+        let mut chunk = vec![0; READ_CHUNK];
+        match body.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(n) => {
+                chunk.truncate(n);
+                Some((Ok(chunk), Some(body)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    }))
+}
+
+/// Newline delimited lines of `body`, as sent by pull, push and build progress
+pub fn lines(body: Body) -> impl Stream<Item = io::Result<String>> + Send + Sync + Unpin {
+    body.lines()
+}
+
+/// [`lines`] with their newline, for relaying a json lines body in chunks that never end
+/// in the middle of a line
+pub fn line_chunks(body: Body) -> impl Stream<Item = io::Result<Vec<u8>>> + Send + Sync + Unpin {
+    lines(body).map_ok(|line| {
+        let mut chunk = line.into_bytes();
+        chunk.push(b'\n');
+        chunk
+    })
+}
+
+/// Decode newline delimited json from raw `chunks`, a line may be split across any number
+/// of chunks. Blank lines are skipped, a line that is not a `T` ends the stream with an
+/// `InvalidData` error.
+pub fn json_lines<T, S>(chunks: S) -> impl Stream<Item = io::Result<T>> + Send + Unpin
+where
+    T: DeserializeOwned + Send + 'static,
+    S: Stream<Item = io::Result<Vec<u8>>> + Send + Unpin + 'static,
+{
+    fn decode<T: DeserializeOwned>(line: &[u8]) -> io::Result<T> {
+        serde_json::from_slice(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    Box::pin(futures::stream::unfold(
+        Some((chunks, Vec::new(), false)),
+        |state| async move {
+            let (mut chunks, mut pending, mut ended) = state?;
+            loop {
+                if let Some(end) = pending.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = pending.drain(..=end).collect();
+                    if line.iter().all(u8::is_ascii_whitespace) {
+                        continue;
+                    }
+                    return match decode(&line) {
+                        Ok(item) => Some((Ok(item), Some((chunks, pending, ended)))),
+                        Err(e) => Some((Err(e), None)),
+                    };
+                }
+                if ended {
+                    // a last line without its newline
+                    if pending.iter().all(u8::is_ascii_whitespace) {
+                        return None;
+                    }
+                    return Some((decode(&pending), None));
+                }
+                match chunks.next().await {
+                    Some(Ok(chunk)) => pending.extend_from_slice(&chunk),
+                    Some(Err(e)) => return Some((Err(e), None)),
+                    None => ended = true,
+                }
+            }
+        },
+    ))
+}
+
+/// The typed events of an `/events` body, see [`json_lines`]
+pub fn events(body: Body) -> impl Stream<Item = io::Result<Event>> + Send + Unpin {
+    json_lines(chunks(body))
+}
+
+/// [`events`] for clients following them: a line that is not an `Event`, e.g. of a newer
+/// daemon, is logged and skipped instead of ending the stream
+pub fn lenient_events(body: Body) -> impl Stream<Item = io::Result<Event>> + Send + Unpin {
+    Box::pin(lines(body).filter_map(|line| async move {
+        let decoded = line.and_then(|line| {
+            if line.trim().is_empty() {
+                return Ok(None);
+            }
+            serde_json::from_str(&line).map(Some).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {:?}", e, line))
+            })
+        });
+        match decoded {
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                log::warn!("skipped an event line of the daemon: {}", e);
+                None
+            }
+            decoded => decoded.transpose(),
+        }
+    }))
+}
+
+/// End `stream` once it ran for `max`, sending `terminal` as the last chunk so clients
+/// know the server closed it on purpose and can reconnect. The items of `stream` should be
+/// whole lines or events, e.g. [`line_chunks`] rather than [`chunks`], so `terminal`
+/// never lands in the middle of one.
+pub fn bounded<S>(
+    stream: S,
+    max: Duration,
+    terminal: Vec<u8>,
+) -> impl Stream<Item = io::Result<Vec<u8>>> + Send + Unpin
+where
+    S: Stream<Item = io::Result<Vec<u8>>> + Send + Unpin + 'static,
+{
+    let deadline = Instant::now() + max;
+    Box::pin(futures::stream::unfold(
+        Some((stream, terminal)),
+        move |state| async move {
+            let (mut stream, terminal) = state?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match async_std::future::timeout(remaining, stream.next()).await {
+                Ok(Some(chunk)) => Some((chunk, Some((stream, terminal)))),
+                Ok(None) => None,
+                Err(_) => {
+                    log::debug!("stream closed after {:?}", max);
+                    Some((Ok(terminal), None))
+                }
+            }
+        },
+    ))
+}
+
+/// Makes a `Send` stream `Sync` so it can back a response body. The stream is only ever
+/// polled through `&mut self`, so the mutex is never locked.
+pub struct SyncStream<S>(Mutex<S>);
+
+impl<S> SyncStream<S> {
+    pub fn new(stream: S) -> Self {
+        SyncStream(Mutex::new(stream))
+    }
+}
+
+impl<S> Stream for SyncStream<S>
+where
+    S: Stream + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let stream = self.get_mut().0.get_mut().unwrap();
+        Pin::new(stream).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(
+        stream: u8,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut bytes = vec![stream, 0, 0, 0];
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn decode_multiplexed_frames() {
+        let mut bytes = frame(1, b"hello\n");
+        bytes.extend(frame(2, b"oops\n"));
+        bytes.extend(frame(1, b""));
+        bytes.extend(frame(1, b"bye\n"));
+
+        // split in the middle of a header and a payload
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        for chunk in bytes.chunks(5) {
+            decoder.push(chunk);
+            while let Some(frame) = decoder.next_frame() {
+                frames.push(frame);
+            }
+        }
+        assert!(decoder.finish().unwrap().is_none());
+        assert_eq!(frames, decode(&bytes).unwrap());
+
+        let streams: Vec<_> = frames.iter().map(|f| f.stream).collect();
+        assert_eq!(
+            streams,
+            vec![
+                StreamType::Stdout,
+                StreamType::Stderr,
+                StreamType::Stdout,
+                StreamType::Stdout
+            ]
+        );
+        assert_eq!(frames[0].data, b"hello\n");
+        assert_eq!(frames[1].data, b"oops\n");
+        assert_eq!(frames[3].data, b"bye\n");
+    }
+
+    #[test]
+    fn frame_to_sse() {
+        let frame = Frame {
+            stream: StreamType::Stderr,
+            data: b"first\nsecond\n".to_vec(),
+        };
+        assert_eq!(
+            String::from_utf8(frame.to_sse()).unwrap(),
+            "event: stderr\ndata: first\ndata: second\ndata: \n\n"
+        );
+    }
+
+    /// Test timestamps being split off the lines that start with one
+    #[test]
+    fn log_line_timestamp_split() {
+        let frame = Frame {
+            stream: StreamType::Stderr,
+            data: b"2021-06-01T09:14:33.261913765Z connection refused: retrying\n".to_vec(),
+        };
+        let lines = LogLine::split(&frame, true);
+        assert_eq!(lines.len(), 1);
+        let timestamp = lines[0].timestamp.unwrap();
+        assert_eq!(timestamp.timestamp(), 1622538873);
+        assert_eq!(timestamp.timestamp_subsec_nanos(), 261913765);
+        assert_eq!(lines[0].stream, StreamType::Stderr);
+        assert_eq!(lines[0].line, "connection refused: retrying");
+        let json = serde_json::to_value(&lines[0]).unwrap();
+        assert_eq!(json["stream"], "stderr");
+        assert_eq!(json["timestamp"], "2021-06-01T09:14:33.261913765Z");
+
+        let frame = Frame {
+            stream: StreamType::Stdout,
+            data: b"listening on :8080\r\nready\n".to_vec(),
+        };
+        let lines = LogLine::split(&frame, true);
+        let text: Vec<_> = lines.iter().map(|line| line.line.as_str()).collect();
+        assert_eq!(text, vec!["listening on :8080", "ready"]);
+        assert!(lines.iter().all(|line| line.timestamp.is_none()));
+    }
+
+    /// Test an application's own leading timestamp staying in the line without `timestamps`
+    #[test]
+    fn log_line_own_timestamp_kept() {
+        let frame = Frame {
+            stream: StreamType::Stdout,
+            data: b"2021-06-01T09:14:33Z INFO worker started\n".to_vec(),
+        };
+        let lines = LogLine::split(&frame, false);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].timestamp, None);
+        assert_eq!(lines[0].line, "2021-06-01T09:14:33Z INFO worker started");
+    }
+
+    #[test]
+    fn decode_tty_passthrough() {
+        let frames = decode(b"plain tty output\n").unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].stream, StreamType::Stdout);
+        assert_eq!(frames[0].data, b"plain tty output\n");
+    }
+
+    #[test]
+    fn follow_reconnects_since_last_line() {
+        use std::sync::{Arc, Mutex};
+
+        let mut first = frame(1, b"2021-05-01T10:00:00.000000001Z one\n");
+        // lines of the first stream are never dropped, even with the same timestamp
+        first.extend(frame(2, b"2021-05-01T10:00:00.000000001Z one again\n"));
+        first.extend(frame(1, b"2021-05-01T10:00:01.500000000Z two\n"));
+        // the reconnect repeats the last line seen, `since` is inclusive
+        let mut second = frame(1, b"2021-05-01T10:00:01.500000000Z two\n");
+        second.extend(frame(2, b"2021-05-01T10:00:02.000000000Z three\n"));
+
+        let sinces = Arc::new(Mutex::new(Vec::new()));
+        let bodies = Arc::new(Mutex::new(vec![second]));
+        let open = {
+            let sinces = sinces.clone();
+            move |since: String| {
+                sinces.lock().unwrap().push(since);
+                let next = bodies.lock().unwrap().pop().unwrap_or_default();
+                async move { Ok(Body::from(next)) }
+            }
+        };
+
+        let lines: Vec<Vec<u8>> = futures::executor::block_on(
+            follow(Body::from(first), open, 2)
+                .map(|frame| frame.unwrap().data)
+                .collect(),
+        );
+        assert_eq!(
+            lines,
+            vec![
+                b"2021-05-01T10:00:00.000000001Z one\n".to_vec(),
+                b"2021-05-01T10:00:00.000000001Z one again\n".to_vec(),
+                b"2021-05-01T10:00:01.500000000Z two\n".to_vec(),
+                b"2021-05-01T10:00:02.000000000Z three\n".to_vec(),
+            ]
+        );
+        // one reconnect that returned a new line, then two empty ones
+        assert_eq!(
+            *sinces.lock().unwrap(),
+            vec![
+                "1619863201.500000000",
+                "1619863202.000000000",
+                "1619863202.000000000"
+            ]
+        );
+    }
+
+    #[test]
+    fn bounded_stream_ends_with_terminal() {
+        let endless = futures::stream::iter(vec![Ok(b"first\n".to_vec())])
+            .chain(futures::stream::pending());
+        let started = Instant::now();
+        let chunks: Vec<Vec<u8>> = futures::executor::block_on(
+            bounded(endless, Duration::from_millis(50), b"ended\n".to_vec())
+                .map(|chunk| chunk.unwrap())
+                .collect(),
+        );
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(chunks, vec![b"first\n".to_vec(), b"ended\n".to_vec()]);
+    }
+
+    /// Test a bounded body ending between lines, never after half a line
+    #[test]
+    fn bounded_line_chunks() {
+        let trickled = futures::stream::iter(vec![Ok(b"{\"a\":1}\n{\"b\"".to_vec())])
+            .chain(futures::stream::pending())
+            .into_async_read();
+        let body = Body::from_reader(trickled, None);
+        let chunks: Vec<Vec<u8>> = futures::executor::block_on(
+            bounded(line_chunks(body), Duration::from_millis(50), b"ended\n".to_vec())
+                .map(|chunk| chunk.unwrap())
+                .collect(),
+        );
+        assert_eq!(chunks, vec![b"{\"a\":1}\n".to_vec(), b"ended\n".to_vec()]);
+    }
+
+    /// Test events split mid-line across chunks being decoded whole
+    #[test]
+    fn json_lines_split_mid_line() {
+        let body = concat!(
+            r#"{"Type":"container","Action":"start","Actor":{"ID":"e90e34656806","#,
+            r#""Attributes":{"name":"web"}},"time":1619863201,"timeNano":1619863201000000000}"#,
+            "\n\n",
+            r#"{"Type":"network","Action":"connect","Actor":{"ID":"7d86d31b1478","#,
+            r#""Attributes":{}},"time":1619863202,"timeNano":1619863202000000000}"#,
+            "\n",
+        )
+        .as_bytes();
+        let split = body.iter().position(|b| *b == b'A').unwrap() + 3;
+        let chunks =
+            futures::stream::iter(vec![Ok(body[..split].to_vec()), Ok(body[split..].to_vec())]);
+        let events: Vec<Event> =
+            futures::executor::block_on(json_lines(chunks).try_collect()).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].typ, "container");
+        assert_eq!(events[0].action, "start");
+        assert_eq!(events[0].actor.attributes["name"], "web");
+        assert_eq!(events[1].actor.id, "7d86d31b1478");
+
+        let garbled = futures::stream::iter(vec![Ok(b"{\"Type\":\n".to_vec())]);
+        let decoded: Vec<io::Result<Event>> =
+            futures::executor::block_on(json_lines(garbled).collect());
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(
+            decoded[0].as_ref().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    /// Test an event line that does not decode being skipped, the events after it still
+    /// being relayed
+    #[test]
+    fn lenient_events_skip_invalid() {
+        let body = concat!(
+            r#"{"Type":"container","Action":"start","Actor":{"ID":"e90e34656806","#,
+            r#""Attributes":{"name":"web"}},"time":1619863201,"timeNano":1619863201000000000}"#,
+            "\n",
+            r#"{"Type":"container","Action":"start","time":"yesterday"}"#,
+            "\n\n",
+            r#"{"Type":"network","Action":"connect","Actor":{"ID":"7d86d31b1478","#,
+            r#""Attributes":{}},"time":1619863202,"timeNano":1619863202000000000}"#,
+            "\n",
+        );
+        let events: Vec<Event> =
+            futures::executor::block_on(lenient_events(Body::from(body)).try_collect()).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].actor.attributes["name"], "web");
+        assert_eq!(events[1].actor.id, "7d86d31b1478");
+    }
+
+    #[test]
+    fn decode_truncated_frame() {
+        let bytes = frame(1, b"hello\n");
+        assert!(decode(&bytes[..10]).is_err());
+    }
+}
@@ -1,20 +1,23 @@
-use flate2::{write::GzEncoder, Compression};
 use std::{
     fs::{self, File},
     io::{self, Write},
-    path::{Path, MAIN_SEPARATOR},
+    path::{Component, Path, MAIN_SEPARATOR},
 };
-use tar::Builder;
+use tar::{Builder, EntryType, Header};
+
+/// Files the daemon needs even when `.dockerignore` excludes them.
+const ALWAYS_INCLUDED: [&str; 2] = ["Dockerfile", ".dockerignore"];
 
 // todo: this is pretty involved. (re)factor this into its own crate
 pub fn dir<W>(
     buf: W,
     path: &str,
+    dockerignore: bool,
 ) -> io::Result<()>
 where
     W: Write,
 {
-    let mut archive = Builder::new(GzEncoder::new(buf, Compression::best()));
+    let mut archive = Builder::new(buf);
     fn bundle<F>(
         dir: &Path,
         f: &mut F,
@@ -29,7 +32,8 @@ where
             }
             for entry in fs::read_dir(dir)? {
                 let entry = entry?;
-                if fs::metadata(entry.path())?.is_dir() {
+                // links are packed as links, a link to a directory is not descended into
+                if entry.file_type()?.is_dir() {
                     bundle(&entry.path(), f, true)?;
                 } else {
                     f(&entry.path().as_path())?;
@@ -41,31 +45,313 @@ where
 
     {
         let base_path = Path::new(path).canonicalize()?;
-        // todo: don't unwrap
-        let mut base_path_str = base_path.to_str().unwrap().to_owned();
-        if let Some(last) = base_path_str.chars().last() {
-            if last != MAIN_SEPARATOR {
-                base_path_str.push(MAIN_SEPARATOR)
-            }
-        }
-
+        let ignore = if dockerignore {
+            DockerIgnore::load(&base_path)?
+        } else {
+            DockerIgnore::default()
+        };
+        let root = Path::new(path);
         let mut append = |path: &Path| {
-            let canonical = path.canonicalize()?;
-            // todo: don't unwrap
-            let relativized = canonical
+            // entries keep the name they have in the context
+            let relativized = path.strip_prefix(root).unwrap_or(path);
+            let relative = relativized
                 .to_str()
-                .unwrap()
-                .trim_start_matches(&base_path_str[..]);
-            if path.is_dir() {
-                archive.append_dir(Path::new(relativized), &canonical)?
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} is not valid utf-8", path.display()),
+                    )
+                })?
+                .replace(MAIN_SEPARATOR, "/");
+            // ignored paths are never packed, wherever they point to
+            if ignore.excludes(&relative) && !ALWAYS_INCLUDED.contains(&relative.as_str()) {
+                return Ok(());
+            }
+            let metadata = fs::symlink_metadata(path)?;
+            if metadata.file_type().is_symlink() {
+                let target = fs::read_link(path)?;
+                within(relativized, &target)?;
+                // a target through other links is where they resolve to
+                if let Ok(canonical) = path.canonicalize() {
+                    if !canonical.starts_with(&base_path) {
+                        return Err(escape(relativized, &target));
+                    }
+                }
+                let mut header = Header::new_gnu();
+                header.set_metadata(&metadata);
+                header.set_entry_type(EntryType::Symlink);
+                header.set_size(0);
+                archive.append_link(&mut header, relativized, &target)?
+            } else if metadata.is_dir() {
+                archive.append_dir(relativized, path)?
             } else {
-                archive.append_file(Path::new(relativized), &mut File::open(&canonical)?)?
+                archive.append_file(relativized, &mut File::open(path)?)?
             }
             Ok(())
         };
-        bundle(Path::new(path), &mut append, false)?;
+        bundle(root, &mut append, false)?;
     }
     archive.finish()?;
 
     Ok(())
 }
+
+/// Check that the `target` of the link at `path`, relative to the context root, stays
+/// within the context. An error for absolute targets and for targets leaving it through
+/// `../`, as the build would read host files otherwise.
+fn within(
+    path: &Path,
+    target: &Path,
+) -> io::Result<()> {
+    let escape = || escape(path, target);
+    let mut depth = path.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => depth = depth.checked_sub(1).ok_or_else(escape)?,
+            Component::RootDir | Component::Prefix(_) => return Err(escape()),
+        }
+    }
+    Ok(())
+}
+
+fn escape(
+    path: &Path,
+    target: &Path,
+) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "{} links to {}, outside of the build context",
+            path.display(),
+            target.display()
+        ),
+    )
+}
+
+/// Patterns of a `.dockerignore` file. Later patterns take precedence over earlier ones,
+/// `!` re-includes a path and `**` matches any number of directories.
+#[derive(Debug, Default)]
+pub struct DockerIgnore {
+    patterns: Vec<(bool, Vec<String>)>,
+}
+
+impl DockerIgnore {
+    pub fn parse(content: &str) -> DockerIgnore {
+        let patterns = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (exclude, pattern) = match line.strip_prefix('!') {
+                    Some(pattern) => (false, pattern.trim()),
+                    None => (true, line),
+                };
+                let parts = pattern
+                    .split('/')
+                    .filter(|part| !part.is_empty() && *part != ".")
+                    .map(str::to_owned)
+                    .collect();
+                (exclude, parts)
+            })
+            .collect();
+        DockerIgnore { patterns }
+    }
+
+    /// read the `.dockerignore` in the context root, if any
+    pub fn load(dir: &Path) -> io::Result<DockerIgnore> {
+        match fs::read_to_string(dir.join(".dockerignore")) {
+            Ok(content) => Ok(DockerIgnore::parse(&content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(DockerIgnore::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// whether `path`, relative to the context root and `/` separated, is excluded.
+    /// a pattern matching a parent directory also matches everything below it.
+    pub fn excludes(&self, path: &str) -> bool {
+        let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+        let mut excluded = false;
+        for (exclude, pattern) in &self.patterns {
+            if (1..=parts.len()).any(|n| match_parts(pattern, &parts[..n])) {
+                excluded = *exclude;
+            }
+        }
+        excluded
+    }
+}
+
+fn match_parts(
+    pattern: &[String],
+    path: &[&str],
+) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|i| match_parts(rest, &path[i..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => {
+                let pattern: Vec<char> = first.chars().collect();
+                let segment: Vec<char> = segment.chars().collect();
+                match_segment(&pattern, &segment) && match_parts(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_segment(
+    pattern: &[char],
+    name: &[char],
+) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|i| match_segment(rest, &name[i..])),
+        Some(('?', rest)) => !name.is_empty() && match_segment(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && match_segment(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(bytes: &[u8]) -> Vec<String> {
+        let mut archive = tar::Archive::new(bytes);
+        let mut names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap())
+            .filter(|e| e.header().entry_type().is_file())
+            .map(|e| e.path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn links(bytes: &[u8]) -> Vec<(String, String)> {
+        let mut archive = tar::Archive::new(bytes);
+        let mut links: Vec<(String, String)> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap())
+            .filter(|e| e.header().entry_type().is_symlink())
+            .map(|e| {
+                let path = e.path().unwrap().to_string_lossy().into_owned();
+                let target = e.link_name().unwrap().unwrap().to_string_lossy().into_owned();
+                (path, target)
+            })
+            .collect();
+        links.sort();
+        links
+    }
+
+    /// Test the entries of a context honoring `.dockerignore`
+    #[test]
+    fn dir_dockerignore() {
+        let root = std::env::temp_dir().join("beekeeper-dockerignore");
+        let _ = fs::remove_dir_all(&root);
+        for dir in &["app", "docs", "target"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in &[
+            "Dockerfile",
+            "README.md",
+            "app/main.rs",
+            "app/debug.log",
+            "docs/readme.md",
+            "docs/notes.md",
+            "target/build.bin",
+        ] {
+            fs::write(root.join(file), file).unwrap();
+        }
+        fs::write(
+            root.join(".dockerignore"),
+            "# build output\ntarget\n**/*.log\ndocs\n!docs/readme.md\n*.md\n",
+        )
+        .unwrap();
+
+        let mut bytes = Vec::default();
+        dir(&mut bytes, root.to_str().unwrap(), true).unwrap();
+        assert_eq!(
+            files(&bytes),
+            vec![".dockerignore", "Dockerfile", "app/main.rs", "docs/readme.md"]
+        );
+
+        let mut bytes = Vec::default();
+        dir(&mut bytes, root.to_str().unwrap(), false).unwrap();
+        assert_eq!(files(&bytes).len(), 8);
+    }
+
+    /// Test symlinks packed as links and those resolving outside of the context failing
+    /// the archive instead of packing host files
+    #[cfg(unix)]
+    #[test]
+    fn dir_symlink_escape() {
+        use std::os::unix::fs::symlink;
+
+        let root = std::env::temp_dir().join("beekeeper-escape");
+        let _ = fs::remove_dir_all(&root);
+        let context = root.join("context");
+        fs::create_dir_all(context.join("app")).unwrap();
+        fs::write(context.join("Dockerfile"), "FROM scratch\n").unwrap();
+        fs::write(root.join("secret"), "host only").unwrap();
+        // links inside the context are packed as links, directories are not descended into
+        symlink("../Dockerfile", context.join("app/Dockerfile")).unwrap();
+        symlink("app", context.join("src")).unwrap();
+        symlink("missing", context.join("app/dangling")).unwrap();
+        let mut bytes = Vec::default();
+        dir(&mut bytes, context.to_str().unwrap(), false).unwrap();
+        assert_eq!(files(&bytes), vec!["Dockerfile"]);
+        assert_eq!(
+            links(&bytes),
+            vec![
+                ("app/Dockerfile".to_owned(), "../Dockerfile".to_owned()),
+                ("app/dangling".to_owned(), "missing".to_owned()),
+                ("src".to_owned(), "app".to_owned()),
+            ]
+        );
+
+        // a `../` through a link to a directory leaving the context
+        symlink("..", context.join("app/root")).unwrap();
+        symlink("root/../secret", context.join("app/out")).unwrap();
+        let err = dir(Vec::default(), context.to_str().unwrap(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        fs::remove_file(context.join("app/out")).unwrap();
+        fs::remove_file(context.join("app/root")).unwrap();
+
+        // a `../` entry leaving the context
+        symlink("../../secret", context.join("app/up")).unwrap();
+        let err = dir(Vec::default(), context.to_str().unwrap(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        fs::remove_file(context.join("app/up")).unwrap();
+
+        // an absolute link to a directory of the host
+        symlink(&root, context.join("host")).unwrap();
+        let err = dir(Vec::default(), context.to_str().unwrap(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        fs::remove_file(context.join("host")).unwrap();
+
+        // links in ignored paths are never packed and can point anywhere
+        fs::create_dir_all(context.join(".venv/bin")).unwrap();
+        symlink("/usr/bin/python3", context.join(".venv/bin/python")).unwrap();
+        fs::write(context.join(".dockerignore"), ".venv\n").unwrap();
+        let mut bytes = Vec::default();
+        dir(&mut bytes, context.to_str().unwrap(), true).unwrap();
+        assert_eq!(files(&bytes), vec![".dockerignore", "Dockerfile"]);
+    }
+
+    #[test]
+    fn dockerignore_patterns() {
+        let ignore = DockerIgnore::parse("/build\n**/tmp/**\n?.txt\n");
+        assert!(ignore.excludes("build"));
+        assert!(ignore.excludes("build/out/a.o"));
+        assert!(!ignore.excludes("src/build"));
+        assert!(ignore.excludes("tmp/a"));
+        assert!(ignore.excludes("src/tmp/a/b"));
+        assert!(ignore.excludes("a.txt"));
+        assert!(!ignore.excludes("ab.txt"));
+    }
+}
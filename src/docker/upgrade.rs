@@ -0,0 +1,117 @@
+//! Raw connections to the daemon for the endpoints that hijack the http connection,
+//! e.g. an attach with stdin. The http client has no way to hand out the connection
+//! once the daemon switched protocols, so the request is written by hand.
+//!
+//! API Reference: <https://docs.docker.com/engine/api/v1.41/#operation/ContainerAttach>
+
+use std::io;
+
+use async_std::{
+    io::{prelude::*, BufReader},
+    net::TcpStream,
+};
+use http_types::Request;
+use url::Host;
+
+/// Send `request` on a new connection asking the daemon to upgrade it. Returns the
+/// reading side, buffered past the response head, and the writing side of the now raw
+/// connection. Only plain http daemons can be upgraded.
+pub async fn upgrade(request: Request) -> io::Result<(BufReader<TcpStream>, TcpStream)> {
+    let url = request.url();
+    if url.scheme() != "http" {
+        return Err(io::Error::other(format!(
+            "{} daemons can not be attached to",
+            url.scheme()
+        )));
+    }
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let mut target = url.path().to_owned();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}:{}\r\nConnection: Upgrade\r\nUpgrade: tcp\r\n",
+        request.method(),
+        target,
+        host,
+        port
+    );
+    for (name, values) in request.iter() {
+        for value in values.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    head.push_str("\r\n");
+
+    // an ipv6 host is bracketed in the url and the `Host` header, but not to connect
+    let stream = match url.host() {
+        Some(Host::Ipv6(ip)) => TcpStream::connect((ip, port)).await?,
+        _ => TcpStream::connect((host, port)).await?,
+    };
+    let mut writer = stream.clone();
+    writer.write_all(head.as_bytes()).await?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).await?;
+    let status: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not an http response: {:?}", status_line.trim_end()),
+            )
+        })?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+    // daemons before the upgrade support answer 200 and hijack the connection all the same
+    if status != 101 && status != 200 {
+        return Err(io::Error::other(format!(
+            "daemon refused the upgrade with {}",
+            status_line.trim_end()
+        )));
+    }
+    Ok((reader, writer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::net::TcpListener;
+
+    /// Test an attach to a daemon on an ipv6 address, bracketed in its url
+    #[async_std::test]
+    async fn upgrade_ipv6_daemon() {
+        let listener = TcpListener::bind("[::1]:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        async_std::task::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream.clone());
+            let mut head = String::new();
+            while reader.read_line(&mut head).await.unwrap() > 2 {}
+            assert!(head.starts_with("POST /containers/web/attach?stdin=1 HTTP/1.1\r\n"));
+            assert!(head.contains(&format!("Host: [::1]:{}\r\n", port)));
+            let mut writer = stream;
+            writer
+                .write_all(b"HTTP/1.1 101 UPGRADED\r\nUpgrade: tcp\r\n\r\nhello")
+                .await
+                .unwrap();
+        });
+
+        let url = format!("http://[::1]:{}/containers/web/attach?stdin=1", port);
+        let request = Request::post(url.as_str());
+        let (mut reader, _writer) = upgrade(request).await.unwrap();
+        let mut output = String::new();
+        reader.read_to_string(&mut output).await.unwrap();
+        assert_eq!(output, "hello");
+    }
+}
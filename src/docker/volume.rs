@@ -12,12 +12,15 @@ use serde_json::{json, Value};
 
 use crate::docker::{
     docker::Docker,
+    json,
 };
 
+#[cfg(feature = "chrono")]
+use crate::docker::datetime::datetime_from_rfc3339;
 #[cfg(feature = "chrono")]
 use chrono::{DateTime, Utc};
 
-use http_types::{Mime, Request, Body, Error};
+use http_types::{Request, Body, Error};
 
 /// Interface for docker volumes
 ///
@@ -39,11 +42,17 @@ impl<'docker> Volumes<'docker> {
         &self,
         opts: &VolumeCreateOptions,
     ) -> Result<Request, Error> {
+        if opts.params.contains_key("DriverOpts") && !opts.params.contains_key("Driver") {
+            return Err(Error::from_str(
+                http_types::StatusCode::BadRequest,
+                "driver options are given without the driver they are for",
+            ));
+        }
+        // the daemon requires an object, options without any parameter are sent as `{}`
         let body: Body = opts.serialize()?.into();
         let path = vec!["/volumes/create".to_owned()];
 
-        self.docker
-            .post(&path.join("?"),  Some((body, Mime::from("application/json"))))
+        self.docker.post(&path.join("?"), Some((body, json())))
     }
 
     /// Lists the docker volumes on the current docker host
@@ -134,12 +143,12 @@ impl VolumeCreateOptions {
 #[derive(Default)]
 pub struct VolumeCreateOptionsBuilder {
     params: HashMap<&'static str, Value>,
+    labels: BTreeMap<String, String>,
 }
 
 impl VolumeCreateOptionsBuilder {
     pub(crate) fn new() -> Self {
-        let params = HashMap::new();
-        VolumeCreateOptionsBuilder { params }
+        VolumeCreateOptionsBuilder::default()
     }
 
     pub fn name(
@@ -150,18 +159,52 @@ impl VolumeCreateOptionsBuilder {
         self
     }
 
+    /// add all of `labels`, replacing earlier values of the same keys
     pub fn labels(
         &mut self,
         labels: &HashMap<&str, &str>,
     ) -> &mut Self {
-        self.params.insert("Labels", json!(labels));
+        for (key, value) in labels {
+            self.label(key, value);
+        }
+        self
+    }
+
+    /// add a single label, replacing an earlier value of the same key
+    pub fn label(
+        &mut self,
+        key: &str,
+        value: &str,
+    ) -> &mut Self {
+        self.labels.insert(key.to_owned(), value.to_owned());
+        self
+    }
+
+    /// name of the volume driver, `local` when not set
+    pub fn driver(
+        &mut self,
+        name: &str,
+    ) -> &mut Self {
+        self.params.insert("Driver", json!(name));
+        self
+    }
+
+    /// driver specific options, e.g. the `type`, `o` and `device` of an nfs mount for
+    /// the `local` driver. The driver has to be set as well.
+    pub fn driver_opts(
+        &mut self,
+        opts: &HashMap<&str, &str>,
+    ) -> &mut Self {
+        self.params.insert("DriverOpts", json!(opts));
         self
     }
 
     pub fn build(&self) -> VolumeCreateOptions {
-        VolumeCreateOptions {
-            params: self.params.clone(),
+        let mut params = self.params.clone();
+        if !self.labels.is_empty() {
+            params.insert("Labels", json!(self.labels));
         }
+        VolumeCreateOptions { params }
     }
 }
 
@@ -182,6 +225,7 @@ pub struct VolumesInfo {
 #[serde(rename_all = "PascalCase")]
 pub struct VolumeInfo {
     #[cfg(feature = "chrono")]
+    #[serde(deserialize_with = "datetime_from_rfc3339")]
     pub created_at: DateTime<Utc>,
     #[cfg(not(feature = "chrono"))]
     pub created_at: String,
@@ -192,3 +236,83 @@ pub struct VolumeInfo {
     pub options: Option<HashMap<String, String>>,
     pub scope: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test a create without options sending an empty json object
+    #[test]
+    fn volume_create_empty_body() {
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let mut request = docker
+            .volumes()
+            .create(&VolumeCreateOptions::builder().build())
+            .unwrap();
+        assert_eq!(request.url().path(), "/volumes/create");
+        assert_eq!(request.content_type(), Some(http_types::mime::JSON));
+        let body = futures::executor::block_on(request.body_string()).unwrap();
+        assert_eq!(body, "{}");
+    }
+
+    /// Test the body of an nfs volume and driver options without a driver being refused
+    #[test]
+    fn volume_create_nfs() {
+        let mut opts = HashMap::new();
+        opts.insert("type", "nfs");
+        opts.insert("o", "addr=10.0.0.2,rw,nfsvers=4");
+        opts.insert("device", ":/exports/plots");
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let options = VolumeCreateOptions::builder()
+            .name("plots")
+            .driver("local")
+            .driver_opts(&opts)
+            .build();
+        let mut request = docker.volumes().create(&options).unwrap();
+        let body = futures::executor::block_on(request.body_string()).unwrap();
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "Name": "plots",
+                "Driver": "local",
+                "DriverOpts": {
+                    "type": "nfs",
+                    "o": "addr=10.0.0.2,rw,nfsvers=4",
+                    "device": ":/exports/plots",
+                },
+            })
+        );
+
+        let options = VolumeCreateOptions::builder()
+            .name("plots")
+            .driver_opts(&opts)
+            .build();
+        let err = docker.volumes().create(&options).unwrap_err();
+        assert_eq!(err.status(), 400);
+    }
+
+    /// Test a volume with a driver, driver options and labels added one at a time
+    #[test]
+    fn volume_create_driver_and_labels() {
+        let mut opts = HashMap::new();
+        opts.insert("type", "nfs");
+        let options = VolumeCreateOptions::builder()
+            .name("data")
+            .driver("local")
+            .driver_opts(&opts)
+            .label("env", "prod")
+            .label("tier", "db")
+            .build();
+        let body: Value = serde_json::from_str(&options.serialize().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "Name": "data",
+                "Driver": "local",
+                "DriverOpts": { "type": "nfs" },
+                "Labels": { "env": "prod", "tier": "db" },
+            })
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! A json body for every error response that comes without one, so clients can rely on
+//! `{"error": message}` whatever rejected the request, like the bodies of the handlers.
+
+use serde_json::json;
+use tide::{http::Method, Middleware, Next, Request, StatusCode};
+
+use crate::errors;
+
+/// Fills the empty body of any 4xx or 5xx response with `{"error": message}`. The message
+/// is the error the handler failed with, except for a 500 where that may be an internal
+/// detail and the reason phrase is used instead, unless it is one of the crate's errors.
+#[derive(Debug, Default, Clone)]
+pub struct ErrorBodies;
+
+#[tide::utils::async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for ErrorBodies {
+    async fn handle(&self, request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let head = request.method() == Method::Head;
+        let mut response = next.run(request).await;
+        let status = response.status();
+        if head || !(status.is_client_error() || status.is_server_error()) {
+            return Ok(response);
+        }
+        if response.len() != Some(0) {
+            return Ok(response);
+        }
+        let message = match response.error() {
+            Some(error)
+                if status != StatusCode::InternalServerError
+                    || error.downcast_ref::<errors::Error>().is_some() =>
+            {
+                error.to_string()
+            }
+            _ => status.canonical_reason().to_owned(),
+        };
+        response.set_body(json!({ "error": message }));
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tide::Response;
+
+    /// Test bare and failed responses getting the shape of the handler error bodies, and
+    /// those bodies being kept
+    #[async_std::test]
+    async fn error_envelope() {
+        let mut app = tide::new();
+        app.with(ErrorBodies);
+        app.at("/bare").get(|_| async { Ok(Response::new(StatusCode::BadRequest)) });
+        app.at("/invalid").get(|_| async {
+            Err::<Response, _>(tide::Error::from_str(StatusCode::BadRequest, "tail must be `all`"))
+        });
+        app.at("/failed").get(|_| async {
+            Err::<Response, _>(tide::Error::from_str(500, "pool timed out on mysql://db"))
+        });
+        app.at("/garbled").get(|_| async {
+            Err::<Response, _>(errors::Error::InvalidResponse("empty".to_owned()).into())
+        });
+        app.at("/explained").get(|_| async {
+            Ok(Response::builder(409).body(json!({ "error": "name in use" })).build())
+        });
+
+        let get = |path: &str| {
+            let url = format!("http://localhost{}", path);
+            app.respond::<_, http_types::Response>(http_types::Request::get(url.as_str()))
+        };
+        for (path, status, message) in &[
+            ("/bare", 400, "Bad Request"),
+            ("/invalid", 400, "tail must be `all`"),
+            ("/failed", 500, "Internal Server Error"),
+            ("/none", 404, "Not Found"),
+            (
+                "/garbled",
+                500,
+                "Docker Error: Response doesn't have the expected format: empty",
+            ),
+        ] {
+            let mut response = get(path).await.unwrap();
+            assert_eq!(response.status(), *status, "{}", path);
+            let body: Value = response.body_json().await.unwrap();
+            assert_eq!(body, json!({ "error": message }), "{}", path);
+        }
+
+        let mut response = get("/explained").await.unwrap();
+        let body: Value = response.body_json().await.unwrap();
+        assert_eq!(body, json!({ "error": "name in use" }));
+    }
+}
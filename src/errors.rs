@@ -2,7 +2,7 @@
 
 use hyper::{self, http, StatusCode};
 use serde_json::Error as SerdeError;
-use std::{error::Error as StdError, fmt, string::FromUtf8Error};
+use std::{fmt, string::FromUtf8Error};
 
 use futures_util::io::Error as IoError;
 
@@ -23,6 +23,81 @@ pub enum Error {
         message: String,
     },
     ConnectionNotUpgraded,
+    /// no daemon is registered for the requested docker id
+    DaemonNotFound,
+    /// the daemon could not be reached
+    DaemonUnreachable,
+    /// the url of a daemon can not be used to reach it
+    InvalidEndpoint(String),
+    /// the image a container should be created from does not exist on the daemon
+    ImageNotFound {
+        reference: String,
+    },
+}
+
+impl Error {
+    /// http status reported to the client for this error
+    pub fn status(&self) -> tide::StatusCode {
+        match self {
+            Error::DaemonNotFound => tide::StatusCode::NotFound,
+            Error::ImageNotFound { .. } => tide::StatusCode::NotFound,
+            Error::DaemonUnreachable => tide::StatusCode::BadGateway,
+            _ => tide::StatusCode::InternalServerError,
+        }
+    }
+
+    /// typed error for a daemon error response, if it is one this crate reacts to
+    pub fn from_daemon(
+        status: u16,
+        body: &str,
+    ) -> Option<Error> {
+        let message = serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|v| v.get("message").and_then(|m| m.as_str()).map(str::to_owned))
+            .unwrap_or_else(|| body.trim().to_owned());
+        match status {
+            404 => message
+                .strip_prefix("No such image: ")
+                .map(|reference| Error::ImageNotFound {
+                    reference: reference.trim().to_owned(),
+                }),
+            _ => None,
+        }
+    }
+
+    /// classify a failure to get any response from the daemon. An io error is the
+    /// connection failing (refused, reset, timed out), unless it is one for invalid data.
+    /// http-client reports connections it could not open as 400 errors, while async-h1
+    /// converts anything wrong with the response head with the default 500. So every other
+    /// 500 is a daemon answering something that is not http.
+    pub fn from_send(error: &http_types::Error) -> Error {
+        let unparsable = match error.downcast_ref::<std::io::Error>() {
+            Some(e) => e.kind() == std::io::ErrorKind::InvalidData,
+            None => {
+                error.downcast_ref::<async_std::future::TimeoutError>().is_none()
+                    && error.status() == http_types::StatusCode::InternalServerError
+            }
+        };
+        if unparsable {
+            Error::InvalidResponse(error.to_string())
+        } else {
+            Error::DaemonUnreachable
+        }
+    }
+
+    /// tide converts any other error into a 500, this keeps the status of the variant
+    pub fn into_tide(self) -> tide::Error {
+        self.into()
+    }
+}
+
+/// `?` in a handler answers the status of the variant. The error is kept as is, so
+/// `ErrorBodies` can tell it is one of these and answer its message. `Error` is no
+/// `std::error::Error` for this, tide converts any of those into a 500 on its own.
+impl From<Error> for tide::Error {
+    fn from(error: Error) -> tide::Error {
+        tide::Error::from_str(error.status(), error)
+    }
 }
 
 impl From<SerdeError> for Error {
@@ -82,18 +157,93 @@ impl fmt::Display for Error {
                 f,
                 "expected the docker host to upgrade the HTTP connection but it did not"
             ),
+            Error::DaemonNotFound => write!(f, "daemon not registered"),
+            Error::DaemonUnreachable => write!(f, "daemon unreachable"),
+            Error::ImageNotFound { reference } => write!(f, "no such image: {}", reference),
+            Error::InvalidEndpoint(reason) => reason.fmt(f),
         }
     }
 }
 
-impl StdError for Error {
-    fn source(&self) -> Option<&(dyn StdError + 'static)> {
-        match self {
-            Error::SerdeJsonError(ref err) => Some(err),
-            Error::Http(ref err) => Some(err),
-            Error::IO(ref err) => Some(err),
-            Error::Encoding(e) => Some(e),
-            _ => None,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daemon_error_status() {
+        assert_eq!(Error::DaemonNotFound.into_tide().status(), 404);
+        assert_eq!(Error::DaemonUnreachable.into_tide().status(), 502);
+        let error: tide::Error = Error::ImageNotFound { reference: "redis".to_owned() }.into();
+        assert_eq!(error.status(), 404);
+        assert!(error.downcast_ref::<Error>().is_some());
+        assert_eq!(
+            Error::InvalidResponse("empty".to_owned()).into_tide().status(),
+            500
+        );
+    }
+
+    /// Test connection failures being told apart from daemons answering garbage, on real
+    /// connections of a keep-alive client
+    #[async_std::test]
+    async fn daemon_send_error_classification() {
+        use async_std::{io::prelude::*, net::TcpListener};
+        use http_client::{h1::H1Client, HttpClient};
+        use std::{
+            io::{Error as IoError, ErrorKind},
+            time::Duration,
+        };
+
+        let client = H1Client::new();
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/_ping", closed.local_addr().unwrap());
+        drop(closed);
+        let error = client.send(http_types::Request::get(url.as_str())).await.unwrap_err();
+        let error = Error::from_send(&error);
+        assert!(matches!(error, Error::DaemonUnreachable), "{:?}", error);
+        assert_eq!(error.to_string(), "Docker Error: daemon unreachable");
+        assert_eq!(error.into_tide().status(), 502);
+
+        let pending = futures::future::pending::<()>();
+        let timeout = async_std::future::timeout(Duration::from_millis(1), pending)
+            .await
+            .unwrap_err();
+        let error = Error::from_send(&http_types::Error::new(500, timeout));
+        assert!(matches!(error, Error::DaemonUnreachable));
+
+        let garbage = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/_ping", garbage.local_addr().unwrap());
+        async_std::task::spawn(async move {
+            let (mut stream, _) = garbage.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let _ = stream.read(&mut request).await.unwrap();
+            stream.write_all(b"SSH-2.0-OpenSSH_8.4\r\n\r\n").await.unwrap();
+        });
+        let error = client.send(http_types::Request::get(url.as_str())).await.unwrap_err();
+        let error = Error::from_send(&error);
+        assert!(matches!(error, Error::InvalidResponse(_)), "{:?}", error);
+
+        let garbage = IoError::new(ErrorKind::InvalidData, "invalid HTTP version");
+        let error = Error::from_send(&http_types::Error::new(500, garbage));
+        assert!(matches!(error, Error::InvalidResponse(_)));
+        let reset = IoError::new(ErrorKind::ConnectionReset, "connection reset by peer");
+        let error = Error::from_send(&http_types::Error::new(500, reset));
+        assert!(matches!(error, Error::DaemonUnreachable));
+        let error = Error::from_send(&http_types::Error::from_str(500, "Malformed HTTP head"));
+        assert!(matches!(error, Error::InvalidResponse(_)));
+
+        let error = Error::DaemonNotFound;
+        assert_eq!(error.to_string(), "Docker Error: daemon not registered");
+        assert_eq!(error.into_tide().status(), 404);
+    }
+
+    #[test]
+    fn daemon_image_not_found() {
+        let error = Error::from_daemon(404, r#"{"message":"No such image: redis:6-alpine"}"#);
+        match error {
+            Some(Error::ImageNotFound { reference }) => assert_eq!(reference, "redis:6-alpine"),
+            other => panic!("expected image not found, got {:?}", other),
         }
+        assert!(Error::from_daemon(404, r#"{"message":"No such container: web"}"#).is_none());
+        assert!(Error::from_daemon(409, r#"{"message":"No such image: redis"}"#).is_none());
     }
 }
@@ -0,0 +1,190 @@
+//! Results of recent requests made with an `Idempotency-Key` header, so a retried
+//! request answers the first result instead of doing the work again.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    env,
+    hash::{Hash, Hasher},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+/// Once this many keys are kept, expired results are dropped on the next insert.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// A key, the fingerprint of the request that first used it and its result once done
+#[derive(Debug)]
+struct Entry<T> {
+    stored: Instant,
+    fingerprint: u64,
+    result: Option<T>,
+}
+
+#[derive(Debug)]
+pub struct IdempotencyCache<T> {
+    ttl: Duration,
+    results: RwLock<HashMap<String, Entry<T>>>,
+}
+
+/// What a request learns about its key from [`IdempotencyCache::claim`]
+#[derive(Debug)]
+pub enum Claim<T: Clone> {
+    /// the key is new, the request does the work and completes the claim
+    Claimed(Pending<T>),
+    /// a request with the same key and body is still being worked on
+    InFlight,
+    /// the key was first used with a different request
+    Mismatch,
+    /// the result of the first request with the key
+    Done(T),
+}
+
+/// A claimed key. Dropped without being completed, e.g. when the work failed or the client
+/// went away, the key is released so a retry does the work again.
+#[derive(Debug)]
+pub struct Pending<T: Clone> {
+    cache: Arc<IdempotencyCache<T>>,
+    key: String,
+    completed: bool,
+}
+
+impl<T: Clone> Pending<T> {
+    /// store the result of the work, answered to every later request with the key
+    pub fn complete(mut self, result: T) {
+        self.cache.insert(&self.key, result);
+        self.completed = true;
+    }
+}
+
+impl<T: Clone> Drop for Pending<T> {
+    fn drop(&mut self) {
+        if !self.completed {
+            let mut results = self.cache.results.write().unwrap();
+            if matches!(results.get(&self.key), Some(entry) if entry.result.is_none()) {
+                results.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// fingerprint of what makes a request, to tell a retry from another request reusing its key
+pub fn fingerprint(parts: &[&[u8]]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    parts.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<T: Clone> IdempotencyCache<T> {
+    pub fn new(ttl: Duration) -> Self {
+        IdempotencyCache {
+            ttl,
+            results: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// `IDEMPOTENCY_SECONDS` overrides the default of an hour
+    pub fn from_env() -> Self {
+        let secs = env::var("IDEMPOTENCY_SECONDS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(3600);
+        IdempotencyCache::new(Duration::from_secs(secs))
+    }
+
+    /// the result stored for `key` if it has not expired yet
+    pub fn get(&self, key: &str) -> Option<T> {
+        let results = self.results.read().unwrap();
+        results
+            .get(key)
+            .filter(|entry| entry.stored.elapsed() < self.ttl)
+            .and_then(|entry| entry.result.clone())
+    }
+
+    /// Claim `key` for the request with `fingerprint` before doing its work. The key is
+    /// marked pending at once, so a retry racing with the first request does not do the
+    /// work a second time.
+    pub fn claim(self: &Arc<Self>, key: &str, fingerprint: u64) -> Claim<T> {
+        let mut results = self.results.write().unwrap();
+        if let Some(entry) = results.get(key).filter(|entry| entry.stored.elapsed() < self.ttl) {
+            return match &entry.result {
+                _ if entry.fingerprint != fingerprint => Claim::Mismatch,
+                Some(result) => Claim::Done(result.clone()),
+                None => Claim::InFlight,
+            };
+        }
+        self.prune(&mut results);
+        let entry = Entry {
+            stored: Instant::now(),
+            fingerprint,
+            result: None,
+        };
+        results.insert(key.to_owned(), entry);
+        Claim::Claimed(Pending {
+            cache: self.clone(),
+            key: key.to_owned(),
+            completed: false,
+        })
+    }
+
+    /// store the result of the request made with `key`, keeping the fingerprint it was
+    /// claimed with
+    pub fn insert(&self, key: &str, result: T) {
+        let mut results = self.results.write().unwrap();
+        self.prune(&mut results);
+        let fingerprint = results.get(key).map_or(0, |entry| entry.fingerprint);
+        let entry = Entry {
+            stored: Instant::now(),
+            fingerprint,
+            result: Some(result),
+        };
+        results.insert(key.to_owned(), entry);
+    }
+
+    fn prune(&self, results: &mut HashMap<String, Entry<T>>) {
+        if results.len() >= PRUNE_THRESHOLD {
+            let ttl = self.ttl;
+            results.retain(|_, entry| entry.stored.elapsed() < ttl);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test results being answered within the ttl only
+    #[test]
+    fn idempotency_ttl() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60));
+        assert_eq!(cache.get("k-1"), None);
+        cache.insert("k-1", "e90e34656806");
+        assert_eq!(cache.get("k-1"), Some("e90e34656806"));
+        assert_eq!(cache.get("k-2"), None);
+
+        let expired = IdempotencyCache::new(Duration::from_secs(0));
+        expired.insert("k-1", "e90e34656806");
+        assert_eq!(expired.get("k-1"), None);
+    }
+
+    /// Test a claimed key holding off retries until done, and refusing other requests
+    #[test]
+    fn idempotency_claim() {
+        let cache = Arc::new(IdempotencyCache::new(Duration::from_secs(60)));
+        let body = fingerprint(&[b"name=web", br#"{"Image":"alpine"}"#]);
+        let other = fingerprint(&[b"name=web", br#"{"Image":"redis"}"#]);
+
+        let pending = match cache.claim("k-1", body) {
+            Claim::Claimed(pending) => pending,
+            claim => panic!("expected a claim, got {:?}", claim),
+        };
+        assert!(matches!(cache.claim("k-1", body), Claim::InFlight));
+        assert!(matches!(cache.claim("k-1", other), Claim::Mismatch));
+        pending.complete("e90e34656806");
+        assert!(matches!(cache.claim("k-1", body), Claim::Done("e90e34656806")));
+        assert!(matches!(cache.claim("k-1", other), Claim::Mismatch));
+
+        // a claim dropped unfinished leaves the key to the retry
+        drop(cache.claim("k-2", body));
+        assert!(matches!(cache.claim("k-2", body), Claim::Claimed(_)));
+    }
+}
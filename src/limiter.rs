@@ -0,0 +1,199 @@
+//! Rate limiting keyed by an arbitrary string (source ip, docker id, ...): fixed windows
+//! and token buckets.
+
+use std::{
+    collections::HashMap,
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tide::{http::headers, Middleware, Next, Request, Response, StatusCode};
+
+use crate::State;
+
+/// Once this many keys are tracked, expired windows are dropped on the next check.
+const PRUNE_THRESHOLD: usize = 1024;
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    hits: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    /// allow at most `limit` requests per `window` for every key
+    pub fn new(limit: u32, window: Duration) -> Self {
+        RateLimiter {
+            limit,
+            window,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// record a request for `key`, returns false if the key is over its limit
+    pub fn check(&self, key: &str) -> bool {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> bool {
+        let mut hits = self.hits.lock().unwrap();
+        if hits.len() >= PRUNE_THRESHOLD {
+            let window = self.window;
+            hits.retain(|_, (start, _)| now.duration_since(*start) < window);
+        }
+
+        let entry = hits.entry(key.to_owned()).or_insert((now, 0));
+        if now.duration_since(entry.0) >= self.window {
+            *entry = (now, 0);
+        }
+        if entry.1 >= self.limit {
+            return false;
+        }
+        entry.1 += 1;
+        true
+    }
+}
+
+/// Token buckets keyed by an arbitrary string: every key may burst up to a second worth
+/// of requests, refilled continuously at `per_sec`.
+#[derive(Debug)]
+pub struct TokenBuckets {
+    per_sec: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, (Instant, f64)>>,
+}
+
+impl TokenBuckets {
+    pub fn new(per_sec: f64) -> Self {
+        TokenBuckets {
+            per_sec,
+            burst: per_sec.max(1.0),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// `RATE_LIMIT_PER_SEC` requests per second, unlimited if unset or not positive
+    pub fn from_env() -> Option<Self> {
+        env::var("RATE_LIMIT_PER_SEC")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|per_sec| *per_sec > 0.0)
+            .map(TokenBuckets::new)
+    }
+
+    /// take a token for `key`, or tell how long until the next one is available
+    pub fn take(&self, key: &str) -> Result<(), Duration> {
+        self.take_at(key, Instant::now())
+    }
+
+    fn take_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            // a bucket refilled to the brim is the same as no bucket
+            let (per_sec, burst) = (self.per_sec, self.burst);
+            buckets.retain(|_, (at, tokens)| {
+                *tokens + now.duration_since(*at).as_secs_f64() * per_sec < burst
+            });
+        }
+
+        let (at, tokens) = buckets.entry(key.to_owned()).or_insert((now, self.burst));
+        *tokens = (*tokens + now.duration_since(*at).as_secs_f64() * self.per_sec).min(self.burst);
+        *at = now;
+        if *tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - *tokens) / self.per_sec));
+        }
+        *tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Answers requests to a daemon over the `RATE_LIMIT_PER_SEC` of its docker id with a
+/// 429 and a `Retry-After` in whole seconds
+#[derive(Debug, Default, Clone)]
+pub struct DockerRateLimit;
+
+#[tide::utils::async_trait]
+impl Middleware<State> for DockerRateLimit {
+    async fn handle(&self, request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        if let Some(buckets) = &request.state().docker_limiter {
+            let docker_id = request.param("docker").unwrap_or_default();
+            if let Err(wait) = buckets.take(docker_id) {
+                log::warn!("requests to docker {} throttled", docker_id);
+                let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+                return Ok(Response::builder(StatusCode::TooManyRequests)
+                    .header(headers::RETRY_AFTER, retry_after.to_string())
+                    .build());
+            }
+        }
+        Ok(next.run(request).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test one key getting throttled while another key is unaffected
+    #[test]
+    fn throttle_per_key() {
+        let limiter = RateLimiter::new(3, Duration::from_secs(60));
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check_at("10.0.0.1", now));
+        }
+        assert!(!limiter.check_at("10.0.0.1", now));
+        assert!(limiter.check_at("10.0.0.2", now));
+    }
+
+    /// Test the window resetting after it expires
+    #[test]
+    fn throttle_window_reset() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        let now = Instant::now();
+        assert!(limiter.check_at("10.0.0.1", now));
+        assert!(!limiter.check_at("10.0.0.1", now + Duration::from_secs(59)));
+        assert!(limiter.check_at("10.0.0.1", now + Duration::from_secs(60)));
+    }
+
+    /// Test the bucket refilling over time and its wait being told
+    #[test]
+    fn token_bucket_refill() {
+        let buckets = TokenBuckets::new(2.0);
+        let now = Instant::now();
+        assert!(buckets.take_at("7", now).is_ok());
+        assert!(buckets.take_at("7", now).is_ok());
+        assert_eq!(buckets.take_at("7", now), Err(Duration::from_millis(500)));
+        assert!(buckets.take_at("8", now).is_ok());
+        assert!(buckets.take_at("7", now + Duration::from_millis(500)).is_ok());
+        assert!(buckets.take_at("7", now + Duration::from_millis(500)).is_err());
+    }
+
+    /// Test a burst past the limit of one docker id getting 429s, another id unaffected
+    #[async_std::test]
+    async fn docker_rate_limit_burst() {
+        use std::sync::Arc;
+
+        let state = State {
+            docker_limiter: Some(Arc::new(TokenBuckets::new(3.0))),
+            ..State::for_test()
+        };
+        let mut app = tide::with_state(state);
+        app.at("/docker/:docker/info")
+            .with(DockerRateLimit)
+            .get(|_| async { Ok("info") });
+        let get = |docker: &str| {
+            let url = format!("http://localhost/docker/{}/info", docker);
+            app.respond::<_, http_types::Response>(http_types::Request::get(url.as_str()))
+        };
+
+        for _ in 0..3 {
+            assert_eq!(get("7").await.unwrap().status(), 200);
+        }
+        let response = get("7").await.unwrap();
+        assert_eq!(response.status(), 429);
+        assert_eq!(response.header(headers::RETRY_AFTER).unwrap(), "1");
+        assert_eq!(get("8").await.unwrap().status(), 200);
+    }
+}
@@ -0,0 +1,243 @@
+//! Per daemon limit on the number of requests in flight.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Matches the per host connection limit of the default `H1Client`.
+const DEFAULT_MAX_ACTIVE: usize = 50;
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    max_active: usize,
+    per_host: HashMap<String, usize>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_active: DEFAULT_MAX_ACTIVE,
+            per_host: HashMap::new(),
+        }
+    }
+}
+
+impl PoolConfig {
+    /// `DOCKER_POOL_MAX` sets the limit for every daemon, `DOCKER_POOL_MAX_HOSTS` overrides it
+    /// for single daemons as a list like `10.0.0.5:2375=200,10.0.0.6:2375=100`
+    pub fn from_env() -> Self {
+        PoolConfig::from_vars(
+            env::var("DOCKER_POOL_MAX").ok().as_deref(),
+            env::var("DOCKER_POOL_MAX_HOSTS").ok().as_deref(),
+        )
+    }
+
+    /// A limit of 0 would block every request to the daemon for good, it is ignored like
+    /// any other invalid limit.
+    fn from_vars(max: Option<&str>, hosts: Option<&str>) -> Self {
+        fn limit(value: &str) -> Option<usize> {
+            value.trim().parse().ok().filter(|max| *max > 0)
+        }
+
+        let mut config = PoolConfig::default();
+        if let Some(max) = max {
+            match limit(max) {
+                Some(max) => config.max_active = max,
+                None => log::warn!("ignore invalid DOCKER_POOL_MAX: {}", max),
+            }
+        }
+        for entry in hosts.into_iter().flat_map(|hosts| hosts.split(',')) {
+            let mut pair = entry.splitn(2, '=');
+            match (pair.next(), pair.next().and_then(limit)) {
+                (Some(host), Some(max)) => {
+                    config.host(host.trim(), max);
+                }
+                _ => log::warn!("ignore invalid DOCKER_POOL_MAX_HOSTS entry: {}", entry),
+            }
+        }
+        config
+    }
+
+    pub fn max_active(
+        &mut self,
+        max: usize,
+    ) -> &mut Self {
+        self.max_active = max;
+        self
+    }
+
+    pub fn host(
+        &mut self,
+        host: &str,
+        max: usize,
+    ) -> &mut Self {
+        self.per_host.insert(host.to_owned(), max);
+        self
+    }
+
+    fn max_for(&self, host: &str) -> usize {
+        self.per_host.get(host).copied().unwrap_or(self.max_active)
+    }
+}
+
+#[derive(Debug)]
+struct HostPool {
+    max: usize,
+    semaphore: Arc<Semaphore>,
+    saturated: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+pub struct HostPools {
+    config: PoolConfig,
+    hosts: Mutex<HashMap<String, Arc<HostPool>>>,
+}
+
+/// Snapshot of a single daemon pool
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoolStats {
+    pub max: usize,
+    pub active: usize,
+    pub saturated: u64,
+}
+
+/// name, type, help and value of a pool metric
+type MetricFamily = (&'static str, &'static str, &'static str, fn(&PoolStats) -> u64);
+
+impl HostPools {
+    pub fn new(config: PoolConfig) -> Self {
+        HostPools {
+            config,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn pool(&self, host: &str) -> Arc<HostPool> {
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts
+            .entry(host.to_owned())
+            .or_insert_with(|| {
+                let max = self.config.max_for(host);
+                Arc::new(HostPool {
+                    max,
+                    semaphore: Arc::new(Semaphore::new(max)),
+                    saturated: AtomicU64::new(0),
+                })
+            })
+            .clone()
+    }
+
+    /// wait for a free slot on `host`, the slot is released when the permit is dropped
+    pub async fn acquire(&self, host: &str) -> OwnedSemaphorePermit {
+        let pool = self.pool(host);
+        if pool.semaphore.available_permits() == 0 {
+            let saturated = pool.saturated.fetch_add(1, Ordering::Relaxed) + 1;
+            log::warn!(
+                "pool for {} saturated ({} active), waited {} times",
+                host,
+                pool.max,
+                saturated
+            );
+        }
+        pool.semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("pool semaphore closed")
+    }
+
+    pub fn stats(&self, host: &str) -> PoolStats {
+        HostPools::pool_stats(&self.pool(host))
+    }
+
+    fn pool_stats(pool: &HostPool) -> PoolStats {
+        PoolStats {
+            max: pool.max,
+            active: pool.max - pool.semaphore.available_permits(),
+            saturated: pool.saturated.load(Ordering::Relaxed),
+        }
+    }
+
+    /// the stats of every daemon requested so far in the Prometheus text format
+    pub fn metrics(&self) -> String {
+        let hosts = self.hosts.lock().unwrap();
+        let stats: BTreeMap<&str, PoolStats> = hosts
+            .iter()
+            .map(|(host, pool)| (host.as_str(), HostPools::pool_stats(pool)))
+            .collect();
+        let mut metrics = String::new();
+        let families: [MetricFamily; 3] = [
+            ("docker_pool_max", "gauge", "requests allowed in flight", |s| s.max as u64),
+            ("docker_pool_active", "gauge", "requests in flight", |s| s.active as u64),
+            ("docker_pool_saturated_total", "counter", "requests that waited", |s| s.saturated),
+        ];
+        for (name, kind, help, value) in &families {
+            metrics.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+            for (host, stats) in &stats {
+                metrics.push_str(&format!("{}{{host=\"{}\"}} {}\n", name, host, value(stats)));
+            }
+        }
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::AtomicUsize, time::Duration};
+
+    /// Test the active count never exceeding the configured max under load
+    #[tokio::test]
+    async fn pool_limits_active() {
+        let mut config = PoolConfig::default();
+        config.max_active(10).host("10.0.0.5:2375", 3);
+        let pools = Arc::new(HostPools::new(config));
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..20)
+            .map(|_| {
+                let (pools, active, peak) = (pools.clone(), active.clone(), peak.clone());
+                tokio::spawn(async move {
+                    let _permit = pools.acquire("10.0.0.5:2375").await;
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        let stats = pools.stats("10.0.0.5:2375");
+        assert_eq!(stats.max, 3);
+        assert_eq!(stats.active, 0);
+        assert!(stats.saturated > 0);
+        assert_eq!(pools.stats("10.0.0.6:2375").max, 10);
+
+        let metrics = pools.metrics();
+        assert!(metrics.contains("# TYPE docker_pool_saturated_total counter\n"));
+        assert!(metrics.contains("docker_pool_max{host=\"10.0.0.5:2375\"} 3\n"));
+        assert!(metrics.contains("docker_pool_active{host=\"10.0.0.6:2375\"} 0\n"));
+    }
+
+    /// Test limits of 0, which would never let a request through, being ignored
+    #[test]
+    fn pool_config_rejects_zero() {
+        let config = PoolConfig::from_vars(Some("0"), Some("10.0.0.5:2375=0,10.0.0.6:2375=7"));
+        assert_eq!(config.max_for("10.0.0.5:2375"), DEFAULT_MAX_ACTIVE);
+        assert_eq!(config.max_for("10.0.0.6:2375"), 7);
+        let config = PoolConfig::from_vars(Some(" 20 "), None);
+        assert_eq!(config.max_for("10.0.0.5:2375"), 20);
+    }
+}
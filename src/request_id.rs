@@ -0,0 +1,79 @@
+//! Correlation ids linking the log lines of a request to the daemon calls made for it.
+
+use std::fmt;
+
+use tide::{Middleware, Next, Request};
+use uuid::Uuid;
+
+/// response header carrying the id of the request
+pub const HEADER: &str = "X-Request-Id";
+
+/// Id of the request being handled, set as a request ext by [`RequestIds`]
+#[derive(Debug, Clone)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn new() -> Self {
+        RequestId(Uuid::new_v4().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// the correlation id of `req`, `-` outside of the [`RequestIds`] middleware
+pub fn request_id<S>(req: &Request<S>) -> &str {
+    req.ext::<RequestId>().map_or("-", RequestId::as_str)
+}
+
+/// Gives every request a fresh id, logs it with the request and its response and
+/// returns it in the `X-Request-Id` header
+#[derive(Debug, Default, Clone)]
+pub struct RequestIds;
+
+#[tide::utils::async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for RequestIds {
+    async fn handle(&self, mut request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let id = RequestId::new();
+        log::info!("[{}] {} {}", id, request.method(), request.url().path());
+        request.set_ext(id.clone());
+        let mut response = next.run(request).await;
+        log::info!("[{}] responded {}", id, response.status());
+        response.insert_header(HEADER, id.as_str());
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tide::http::{Method, Request as HttpRequest, Response as HttpResponse, Url};
+
+    /// Test every response carrying the id the handler saw
+    #[async_std::test]
+    async fn request_id_header() {
+        let mut app = tide::new();
+        app.with(RequestIds);
+        app.at("/id")
+            .get(|req: Request<()>| async move { Ok(request_id(&req).to_owned()) });
+
+        let request = HttpRequest::new(Method::Get, Url::parse("http://localhost/id").unwrap());
+        let mut response: HttpResponse = app.respond(request).await.unwrap();
+        let header = response.header(HEADER).unwrap().as_str().to_owned();
+        assert!(Uuid::parse_str(&header).is_ok());
+        assert_eq!(response.body_string().await.unwrap(), header);
+
+        // errors and unknown routes are tagged too
+        let request = HttpRequest::new(Method::Get, Url::parse("http://localhost/none").unwrap());
+        let response: HttpResponse = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 404);
+        assert!(response.header(HEADER).is_some());
+    }
+}
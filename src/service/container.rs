@@ -1,16 +1,42 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 use std::default::Default;
-use std::time::Duration;
+use std::pin::Pin;
+use std::{fmt, io};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use url::Url;
 
-use crate::docker::container::LogsOptions;
+use crate::docker::container::{
+    generation_since, valid_container_name, ContainerFilter, ContainerListOptions,
+    ContainerLogs, ContainerSummary, HealthStatus, LogsOptions, LogsOptionsBuilder, PathStat,
+    TopResult, WaitCondition, WaitResponse, PATH_STAT_HEADER,
+};
 use crate::docker::container::RmContainerOptions;
-use crate::docker::{container::ContainerOptions, docker::Docker};
-use crate::service::docker_not_found_error;
+use crate::docker::{
+    container::{ContainerCreateInfo, ContainerDetails, ContainerOptions, Stats, StatsSummary},
+    docker::Docker,
+    stream::{self, Frame, StreamType},
+    upgrade::upgrade,
+};
+use crate::errors::Error;
+use crate::idempotency::{self, Claim};
+use crate::docker::docker::Info;
+use crate::service::{
+    daemon, decode_json, forward, image::check_platform, limits::HostLimits, proxy, proxy_json,
+    proxy_stream, proxy_tar, send, wants_sse,
+};
+use crate::request_id::request_id;
 use crate::State;
 
-use tide::{Request, Result};
+use futures::{Stream, StreamExt, TryStreamExt};
+use tide::{
+    http::{headers, mime},
+    Body, Request, Response, Result, StatusCode,
+};
 
 use serde::Deserialize;
+use serde_json::{json, Value};
+use tide_websockets::{Message, WebSocketConnection};
 
 #[derive(Deserialize)]
 pub struct ContainerProcessOptions {
@@ -18,18 +44,218 @@ pub struct ContainerProcessOptions {
 }
 
 #[derive(Deserialize)]
+pub struct ContainerAttachOptions {
+    #[serde(rename = "detachKeys")]
+    pub detach_keys: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
 pub struct ContainerLogsOptions {
     pub follow: Option<bool>,
     pub stdout: Option<bool>,
     pub stderr: Option<bool>,
+    #[serde(default, deserialize_with = "log_time")]
     pub since: Option<i64>,
+    #[serde(default, deserialize_with = "log_time")]
     pub until: Option<i64>,
     pub timestamps: Option<bool>,
-    pub tail: Option<String>,
+    #[serde(default)]
+    pub tail: LogTail,
+    /// logs of every run instead of only the current one
+    #[serde(rename = "all")]
+    pub all_time: Option<bool>,
+    /// json lines of `{timestamp, stream, line}` instead of the raw output, the
+    /// timestamp is only set with `timestamps`
+    pub json: Option<bool>,
+    /// start of the current run, looked up from the container events
+    #[serde(skip)]
+    pub since_nanos: Option<String>,
+}
+
+/// `since`/`until` of the logs query in unix seconds, given as unix seconds, an RFC3339
+/// timestamp or a duration before now like `10m` or `1h30m`
+fn log_time<'de, D>(deserializer: D) -> std::result::Result<Option<i64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Secs(i64),
+        Text(String),
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    match Option::<Raw>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Raw::Secs(secs)) => Ok(Some(secs)),
+        Some(Raw::Text(text)) => parse_log_time(&text, now)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+/// unix seconds of `value` relative to `now`, see [`log_time`]
+fn parse_log_time(value: &str, now: i64) -> std::result::Result<i64, String> {
+    let invalid = || format!("{:?} is neither unix seconds, RFC3339 nor a duration", value);
+    if let Ok(secs) = value.parse::<i64>() {
+        return Ok(secs);
+    }
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(time.timestamp());
+    }
+    // a duration of amounts with a unit, e.g. `1h30m`
+    let mut ago = 0;
+    let mut amount = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            amount.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let amount: i64 = std::mem::take(&mut amount).parse().map_err(|_| invalid())?;
+        ago = amount
+            .checked_mul(unit)
+            .and_then(|secs| secs.checked_add(ago))
+            .ok_or_else(invalid)?;
+    }
+    if value.is_empty() || !amount.is_empty() {
+        return Err(invalid());
+    }
+    Ok(now - ago)
+}
+
+/// unix seconds as the datetime the builders take with `chrono`, clamped to its range
+#[cfg(feature = "chrono")]
+fn unix_time(secs: i64) -> chrono::DateTime<chrono::Utc> {
+    use chrono::{DateTime, TimeZone, Utc};
+    Utc.timestamp_opt(secs, 0).single().unwrap_or(if secs < 0 {
+        DateTime::<Utc>::MIN_UTC
+    } else {
+        DateTime::<Utc>::MAX_UTC
+    })
+}
+
+/// Lines the logs end with, `all` of them unless a number is given
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(try_from = "String")]
+pub enum LogTail {
+    #[default]
+    All,
+    Lines(u64),
 }
 
-impl Into<LogsOptions> for ContainerLogsOptions {
-    fn into(self) -> LogsOptions {
+impl TryFrom<String> for LogTail {
+    type Error = String;
+
+    fn try_from(tail: String) -> std::result::Result<Self, String> {
+        match tail.as_str() {
+            "all" => Ok(LogTail::All),
+            lines => lines.parse().map(LogTail::Lines).map_err(|_| {
+                format!("tail must be `all` or a number of lines, not {:?}", tail)
+            }),
+        }
+    }
+}
+
+impl fmt::Display for LogTail {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogTail::All => write!(f, "all"),
+            LogTail::Lines(lines) => write!(f, "{}", lines),
+        }
+    }
+}
+
+/// Query of the container list, `filters` is the JSON object of the docker API
+/// restricted to `status`, `label`, `name` and `ancestor`
+#[derive(Deserialize, Default)]
+pub struct ContainerListQuery {
+    pub all: Option<bool>,
+    pub limit: Option<u64>,
+    pub since: Option<String>,
+    pub before: Option<String>,
+    pub size: Option<bool>,
+    pub filters: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ContainerListFilters {
+    #[serde(default)]
+    status: Vec<String>,
+    #[serde(default)]
+    label: Vec<String>,
+    #[serde(default)]
+    name: Vec<String>,
+    #[serde(default)]
+    ancestor: Vec<String>,
+    #[serde(default)]
+    health: Vec<HealthStatus>,
+}
+
+impl ContainerListQuery {
+    pub fn options(&self) -> serde_json::Result<ContainerListOptions> {
+        let mut builder = ContainerListOptions::builder();
+        if self.all.unwrap_or(false) {
+            builder.all();
+        }
+        if let Some(limit) = self.limit {
+            builder.limit(limit);
+        }
+        if let Some(since) = &self.since {
+            builder.since(since);
+        }
+        if let Some(before) = &self.before {
+            builder.before(before);
+        }
+        if self.size.unwrap_or(false) {
+            builder.sized();
+        }
+        if let Some(filters) = &self.filters {
+            let filters: ContainerListFilters = serde_json::from_str(filters)?;
+            let mut list = Vec::new();
+            list.extend(filters.status.into_iter().map(ContainerFilter::Status));
+            list.extend(
+                filters
+                    .label
+                    .into_iter()
+                    .map(|label| match label.split_once('=') {
+                        Some((name, value)) => {
+                            ContainerFilter::Label(name.to_owned(), value.to_owned())
+                        }
+                        None => ContainerFilter::LabelName(label),
+                    }),
+            );
+            list.extend(filters.name.into_iter().map(ContainerFilter::Name));
+            list.extend(filters.ancestor.into_iter().map(ContainerFilter::Ancestor));
+            list.extend(filters.health.into_iter().map(ContainerFilter::Health));
+            if !list.is_empty() {
+                builder.filter(list);
+            }
+        }
+        Ok(builder.build())
+    }
+}
+
+/// reconnects of a followed log stream without new lines before giving up
+const MAX_LOG_RECONNECTS: usize = 3;
+
+impl From<ContainerLogsOptions> for LogsOptions {
+    fn from(options: ContainerLogsOptions) -> LogsOptions {
+        options.builder().build()
+    }
+}
+
+impl ContainerLogsOptions {
+    fn builder(self) -> LogsOptionsBuilder {
         let mut builder = LogsOptions::builder();
         if let Some(b) = self.follow {
             builder.follow(b);
@@ -41,15 +267,25 @@ impl Into<LogsOptions> for ContainerLogsOptions {
             builder.stderr(b);
         }
         if let Some(b) = self.since {
+            #[cfg(feature = "chrono")]
+            builder.since(&unix_time(b));
+            #[cfg(not(feature = "chrono"))]
             builder.since(b);
         }
+        if let Some(since) = &self.since_nanos {
+            builder.since_nanos(since);
+        }
+        if let Some(b) = self.until {
+            #[cfg(feature = "chrono")]
+            builder.until(&unix_time(b));
+            #[cfg(not(feature = "chrono"))]
+            builder.until(b);
+        }
         if let Some(b) = self.timestamps {
             builder.timestamps(b);
         }
-        if let Some(b) = self.tail {
-            builder.tail(b.as_str());
-        }
-        builder.build()
+        builder.tail(&self.tail.to_string());
+        builder
     }
 }
 
@@ -60,7 +296,9 @@ pub struct ContainerStopOptions {
 
 #[derive(Deserialize)]
 pub struct ContainerKillOptions {
-    pub singal: Option<String>,
+    /// `singal` is the old misspelling, still accepted until the next release
+    #[serde(alias = "singal")]
+    pub signal: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -75,232 +313,1285 @@ pub struct ContainerRemoveOptions {
     pub link: Option<bool>,
 }
 
-impl Into<RmContainerOptions> for ContainerRemoveOptions {
-    fn into(self) -> RmContainerOptions {
+impl From<ContainerRemoveOptions> for RmContainerOptions {
+    fn from(options: ContainerRemoveOptions) -> RmContainerOptions {
         let mut builder = RmContainerOptions::builder();
-        if let Some(v) = self.v {
+        if let Some(v) = options.v {
             builder.volumes(v);
         }
-        if let Some(f) = self.force {
+        if let Some(f) = options.force {
             builder.force(f);
         }
+        if let Some(l) = options.link {
+            builder.link(l);
+        }
         builder.build()
     }
 }
 
 pub async fn list(req: Request<State>) -> Result {
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().list(&Default::default())?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    let options = req
+        .query::<ContainerListQuery>()?
+        .options()
+        .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e.to_string()))?;
+    proxy_json::<Vec<ContainerSummary>, _>(&req, |docker| docker.containers().list(&options)).await
 }
 
+#[derive(Deserialize)]
+pub struct ContainerCreateQuery {
+    pub name: Option<String>,
+    /// `os/arch[/variant]` of the image to create the container from
+    pub platform: Option<String>,
+}
+
+/// header naming a create, a retry with the same key answers the container created first
+const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+/// Fingerprint of a create for its idempotency key: the name and platform sent in the query
+/// and the body in its canonical form, so the order the options are kept in does not matter
+fn create_fingerprint(image: &ContainerOptions) -> crate::errors::Result<u64> {
+    Ok(idempotency::fingerprint(&[
+        image.name.as_deref().unwrap_or_default().as_bytes(),
+        image.platform.as_deref().unwrap_or_default().as_bytes(),
+        image.serialize()?.as_bytes(),
+    ]))
+}
+
+/// Create a container, named by the `name` query param if given. A name that is taken
+/// is answered with a 409 naming it. A repeated `Idempotency-Key` answers the container
+/// created for it, with an `Idempotent-Replayed` header, instead of creating another. While
+/// the first create is in progress a retry is answered with a 409, and a key reused for a
+/// create with other options with a 422.
 pub async fn create(mut req: Request<State>) -> Result {
-    let image: ContainerOptions = req.body_json().await?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
+    let query = req.query::<ContainerCreateQuery>()?;
+    let mut image: ContainerOptions = req.body_json().await?;
+    image
+        .restart_policy()
+        .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e))?;
+    if let Some(name) = query.name {
+        image.name = Some(name);
+    }
+    if let Some(platform) = query.platform {
+        image.platform = Some(platform);
+    }
+    if let Some(name) = image.name.as_deref() {
+        check_container_name(name)?;
+    }
+    if let Some(platform) = image.platform.as_deref() {
+        check_platform(platform)?;
+    }
+    let url = req.ext::<Url>().ok_or(Error::DaemonNotFound)?;
+    let idempotency_key = req
+        .header(IDEMPOTENCY_KEY)
+        .map(|key| format!("{} {}", url, key.last().as_str()));
+    let mut pending = None;
+    if let Some(key) = idempotency_key.as_deref() {
+        match req.state().created.claim(key, create_fingerprint(&image)?) {
+            Claim::Claimed(claim) => pending = Some(claim),
+            Claim::Done(created) => {
+                log::info!("create of {} replayed for its idempotency key", created.id);
+                let response = Response::builder(StatusCode::Created)
+                    .header("Idempotent-Replayed", "true")
+                    .body(Body::from_json(&created)?)
+                    .build();
+                return Ok(with_warnings(response, &created.warnings));
+            }
+            Claim::InFlight => {
+                return Ok(Response::builder(StatusCode::Conflict)
+                    .body(json!({ "error": "a create with this idempotency key is in progress" }))
+                    .build());
+            }
+            Claim::Mismatch => {
+                return Ok(Response::builder(StatusCode::UnprocessableEntity)
+                    .body(json!({ "error": "idempotency key was used for a different create" }))
+                    .build());
+            }
+        }
+    }
 
-    let docker = Docker::host(url.clone());
-    let response = req
+    let docker = daemon(&req)?;
+    if let Some(cache) = &req.state().host_limits {
+        let limits = match cache.get(url.as_str()) {
+            Some(limits) => limits,
+            None => {
+                let mut response =
+                    req.state().send(request_id(&req), docker.info()?).await?;
+                if !response.status().is_success() {
+                    return forward(response).await;
+                }
+                let info: Info = response.body_json().await?;
+                let limits = HostLimits::from(&info);
+                cache.insert(url.as_str(), limits.clone());
+                limits
+            }
+        };
+        if let Err(reason) = limits.check(&image.resources()) {
+            return Ok(Response::builder(StatusCode::UnprocessableEntity)
+                .body(json!({ "error": reason, "limits": limits }))
+                .build());
+        }
+    }
+    let mut response = req
         .state()
-        .send(docker.containers().create(&image)?)
+        .send(request_id(&req), docker.containers().create(&image)?)
         .await?;
-    Ok(tide::Response::from_res(response))
+    if response.status() == 404 {
+        let body = response.body_string().await?;
+        if let Some(e) = Error::from_daemon(404, &body) {
+            return Err(e.into_tide());
+        }
+        response.set_body(body);
+    }
+    if response.status() == StatusCode::Conflict {
+        if let Some(name) = image.name.as_deref() {
+            log::warn!("create of {} conflicts", name);
+            return Ok(name_in_use(name));
+        }
+    }
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+    let created: ContainerCreateInfo = decode_json(&req, &mut response).await?;
+    for warning in &created.warnings {
+        log::warn!("create of {} warns: {}", created.id, warning);
+    }
+    if let Some(pending) = pending {
+        pending.complete(created.clone());
+    }
+    let response = Response::builder(StatusCode::Created)
+        .body(Body::from_json(&created)?)
+        .build();
+    Ok(with_warnings(response, &created.warnings))
+}
+
+/// `warning` as the value of a `Warning` header: the miscellaneous warning code 199, no
+/// agent and the text as a quoted string (RFC 7234, section 5.5). Header values are ascii,
+/// other characters are replaced by `?`.
+fn warning_header(warning: &str) -> String {
+    let text: String = warning
+        .chars()
+        .filter(|c| !c.is_control())
+        .flat_map(|c| match c {
+            '"' | '\\' => vec!['\\', c],
+            c if c.is_ascii() => vec![c],
+            _ => vec!['?'],
+        })
+        .collect();
+    format!("199 - \"{}\"", text)
+}
+
+/// add a `Warning` header per daemon warning, clients reading headers only see them too
+fn with_warnings(mut response: Response, warnings: &[String]) -> Response {
+    for warning in warnings {
+        response.append_header(headers::WARNING, warning_header(warning));
+    }
+    response
+}
+
+#[derive(Deserialize)]
+pub struct ContainerInspectOptions {
+    pub size: Option<bool>,
 }
 
 pub async fn inspect(req: Request<State>) -> Result {
     let id = req.param("id")?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).inspect()?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    let args = req.query::<ContainerInspectOptions>()?;
+    let size = args.size.unwrap_or(false);
+    proxy(&req, |docker| docker.containers().get(id).inspect_with_size(size)).await
+}
+
+/// Inspect decoded into `ContainerDetails`, for clients that want the stable subset
+/// this crate knows rather than everything the daemon reports
+pub async fn details(req: Request<State>) -> Result {
+    let id = req.param("id")?;
+    let args = req.query::<ContainerInspectOptions>()?;
+    let size = args.size.unwrap_or(false);
+    proxy_json::<ContainerDetails, _>(&req, |docker| {
+        docker.containers().get(id).inspect_with_size(size)
+    })
+    .await
 }
 
 pub async fn top(req: Request<State>) -> Result {
     let id = req.param("id")?;
     let args = req.query::<ContainerProcessOptions>()?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
+    proxy_json::<TopResult, _>(&req, |docker| docker.containers().get(id).top(args.ps_args)).await
+}
+
+/// Scope logs without an explicit `since` to the current run of the container,
+/// unless all-time logs are asked for
+async fn scope_to_generation(
+    req: &Request<State>,
+    docker: &Docker,
+    id: &str,
+    args: &mut ContainerLogsOptions,
+) -> Result<()> {
+    if args.since.is_some() || args.all_time.unwrap_or(false) {
+        return Ok(());
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let mut response = req
         .state()
-        .send(docker.containers().get(id).top(args.ps_args)?)
+        .send(request_id(req), docker.containers().get(id).start_events(now)?)
         .await?;
-    Ok(tide::Response::from_res(response))
+    if response.status().is_success() {
+        args.since_nanos = generation_since(&response.body_bytes().await?);
+    }
+    Ok(())
 }
 
 pub async fn logs(req: Request<State>) -> Result {
     let id = req.param("id")?;
-    let args = req.query::<ContainerLogsOptions>()?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
+    let mut args = req.query::<ContainerLogsOptions>()?;
+    let follow = args.follow.unwrap_or(false);
+    let timestamps = args.timestamps.unwrap_or(false);
+    let json = args.json.unwrap_or(false);
+    let docker = daemon(&req)?;
+    scope_to_generation(&req, &docker, id, &mut args).await?;
+    let mut response = req
         .state()
-        .send(docker.containers().get(id).logs(&args.into())?)
+        .send(
+            request_id(&req),
+            docker.containers().get(id).logs(&args.clone().into())?,
+        )
         .await?;
-    Ok(tide::Response::from_res(response))
+    if !response.status().is_success() || !(follow || json) {
+        return forward(response).await;
+    }
+    if !follow || !timestamps {
+        let frames = stream::frames(response.take_body());
+        return Ok(relay_logs(&req, frames, json, timestamps));
+    }
+
+    // the timestamps tell where to resume when the daemon ends the stream
+    let state = req.state().clone();
+    let (docker, id) = (docker.clone(), id.to_owned());
+    let req_id = request_id(&req).to_owned();
+    let open = move |since: String| {
+        let (state, docker, id) = (state.clone(), docker.clone(), id.clone());
+        let req_id = req_id.clone();
+        // the lines since the last one seen, a tail would skip some of them
+        let options: LogsOptions = ContainerLogsOptions {
+            tail: LogTail::All,
+            since_nanos: Some(since),
+            ..args.clone()
+        }
+        .into();
+        async move {
+            let request = docker
+                .containers()
+                .get(id)
+                .logs(&options)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            let mut response = state
+                .send(&req_id, request)
+                .await
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            if !response.status().is_success() {
+                return Err(io::Error::other(format!(
+                    "docker responded {}",
+                    response.status()
+                )));
+            }
+            Ok(response.take_body())
+        }
+    };
+    let frames = stream::follow(response.take_body(), open, MAX_LOG_RECONNECTS);
+    Ok(relay_logs(&req, stream::SyncStream::new(frames), json, timestamps))
 }
 
-pub async fn changes(req: Request<State>) -> Result {
+/// [`relay_frames`], or with `json` the lines of the frames as json lines of `LogLine`,
+/// split from their timestamps if the logs were requested with `timestamps`
+fn relay_logs<S>(req: &Request<State>, frames: S, json: bool, timestamps: bool) -> Response
+where
+    S: Stream<Item = io::Result<Frame>> + Send + Sync + Unpin + 'static,
+{
+    if !json {
+        return relay_frames(req, frames);
+    }
+    let lines = stream::log_lines(frames, timestamps).map_ok(|line| {
+        let mut chunk = serde_json::to_vec(&line).unwrap_or_default();
+        chunk.push(b'\n');
+        chunk
+    });
+    let terminal = format!("{}\n", json!({ "status": "stream-ended" })).into_bytes();
+    let chunks = stream::bounded(lines, req.state().max_stream, terminal);
+    let reader = stream::SyncStream::new(chunks).into_async_read();
+    Response::builder(200)
+        .content_type(mime::JSON)
+        .body(Body::from_reader(reader, None))
+        .build()
+}
+
+/// Complete logs as json `{stdout, stderr}`, meant for scripts rather than following
+pub async fn logs_text(req: Request<State>) -> Result {
     let id = req.param("id")?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
+    let mut args = req.query::<ContainerLogsOptions>()?;
+    args.follow = Some(false);
+    let docker = daemon(&req)?;
+    scope_to_generation(&req, &docker, id, &mut args).await?;
+    let mut response = req
         .state()
-        .send(docker.containers().get(id).changes()?)
+        .send(request_id(&req), docker.containers().get(id).logs(&args.into())?)
         .await?;
-    Ok(tide::Response::from_res(response))
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+    let logs = ContainerLogs::from_bytes(&response.body_bytes().await?)?;
+    Ok(Response::builder(200).body(Body::from_json(&logs)?).build())
 }
 
+/// Relay the framed output of logs and attach as it arrives. Clients accepting
+/// `text/event-stream` get one event per frame named after its stream, others get
+/// the payload with the framing removed.
+pub(crate) fn relay_frames<S>(req: &Request<State>, frames: S) -> Response
+where
+    S: Stream<Item = io::Result<Frame>> + Send + Sync + Unpin + 'static,
+{
+    let max = req.state().max_stream;
+    if wants_sse(req) {
+        let terminal = b"event: stream-ended\ndata: \n\n".to_vec();
+        let chunks = stream::bounded(frames.map_ok(|frame| frame.to_sse()), max, terminal);
+        let reader = stream::SyncStream::new(chunks).into_async_read();
+        Response::builder(200)
+            .content_type(mime::SSE)
+            .header(headers::CACHE_CONTROL, "no-cache")
+            .body(Body::from_reader(reader, None))
+            .build()
+    } else {
+        // plain text has no room for a terminal frame, the connection is just closed
+        let chunks = stream::bounded(frames.map_ok(|frame| frame.data), max, Vec::new());
+        let reader = stream::SyncStream::new(chunks).into_async_read();
+        Response::builder(200)
+            .content_type(mime::PLAIN)
+            .body(Body::from_reader(reader, None))
+            .build()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ArchivePathQuery {
+    pub path: String,
+}
+
+/// The daemon's stat header of the queried path and its decoded form, or the status the
+/// daemon refused the stat with
+async fn fetch_path_stat(
+    req: &Request<State>,
+) -> Result<std::result::Result<(String, PathStat), StatusCode>> {
+    let id = req.param("id")?;
+    let query = req.query::<ArchivePathQuery>()?;
+    let response = send(req, |docker| docker.containers().get(id).stat_path(&query.path)).await?;
+    if !response.status().is_success() {
+        return Ok(Err(response.status()));
+    }
+    let header = response
+        .header(PATH_STAT_HEADER)
+        .map(|value| value.last().as_str())
+        .unwrap_or_default();
+    let stat = PathStat::from_header(header).map_err(|e| {
+        log::warn!("stat of {} in {} not decoded: {}", query.path, id, e);
+        tide::Error::from_str(StatusCode::BadGateway, e)
+    })?;
+    Ok(Ok((header.to_owned(), stat)))
+}
+
+/// Stat a path in the container without downloading it. Answered with the daemon's
+/// `X-Docker-Container-Path-Stat` header, see [`stat_path_json`] for the decoded stat.
+pub async fn stat_path(req: Request<State>) -> Result {
+    match fetch_path_stat(&req).await? {
+        Ok((header, _)) => Ok(Response::builder(200).header(PATH_STAT_HEADER, header).build()),
+        // answers to HEAD have no body to explain the status in
+        Err(status) => Ok(Response::new(status)),
+    }
+}
+
+/// The stat of a path in the container as a json `PathStat`. Names are not ascii-only,
+/// so unlike the raw stat they can't be sent in a header.
+pub async fn stat_path_json(req: Request<State>) -> Result {
+    match fetch_path_stat(&req).await? {
+        Ok((_, stat)) => Ok(Response::builder(200).body(Body::from_json(&stat)?).build()),
+        Err(status) => Ok(Response::new(status)),
+    }
+}
+
+pub async fn changes(req: Request<State>) -> Result {
+    let id = req.param("id")?;
+    proxy(&req, |docker| docker.containers().get(id).changes()).await
+}
+
+/// The filesystem of the container as a tarball, streamed through
 pub async fn export(req: Request<State>) -> Result {
     let id = req.param("id")?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).export()?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    proxy_tar(&req, |docker| docker.containers().get(id).export()).await
+}
+
+#[derive(Deserialize)]
+pub struct ContainerStatsOptions {
+    pub stream: Option<bool>,
 }
 
+/// Stats as a stream of samples, or a single decoded `Stats` sample with `stream=false`
 pub async fn stats(req: Request<State>) -> Result {
     let id = req.param("id")?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).stats()?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    let args = req.query::<ContainerStatsOptions>()?;
+    if args.stream.unwrap_or(true) {
+        return proxy_stream(&req, |docker| docker.containers().get(id).stats()).await;
+    }
+
+    proxy_json::<Stats, _>(&req, |docker| docker.containers().get(id).stats_once()).await
+}
+
+/// Stream the usage of a container as `stats` server sent events of `StatsSummary`,
+/// with the cpu percent computed from consecutive samples
+pub async fn stats_summary(req: Request<State>) -> Result {
+    let id = req.param("id")?;
+    let mut response = send(&req, |docker| docker.containers().get(id).stats()).await?;
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+    let summaries = stream::lines(response.take_body()).scan(None, |previous, line| {
+        let chunk = line.and_then(|line| {
+            let stats: Stats = serde_json::from_str(&line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let summary = StatsSummary::new(&stats, previous.as_ref());
+            *previous = Some(stats.cpu_stats);
+            let data = serde_json::to_string(&summary).unwrap_or_default();
+            Ok(format!("event: stats\ndata: {}\n\n", data).into_bytes())
+        });
+        futures::future::ready(Some(chunk))
+    });
+    let terminal = b"event: stream-ended\ndata: \n\n".to_vec();
+    let chunks = stream::bounded(summaries, req.state().max_stream, terminal);
+    let reader = stream::SyncStream::new(chunks).into_async_read();
+    Ok(Response::builder(200)
+        .content_type(mime::SSE)
+        .header(headers::CACHE_CONTROL, "no-cache")
+        .body(Body::from_reader(reader, None))
+        .build())
 }
 
 // resize not impl
 
 pub async fn start(req: Request<State>) -> Result {
     let id = req.param("id")?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).start()?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    proxy(&req, |docker| docker.containers().get(id).start()).await
+}
+
+/// containers a batch start starts at the same time
+const BATCH_START_CONCURRENCY: usize = 8;
+
+/// Start every container of a json array of ids, answering `{id: {"ok": true}}` or
+/// `{id: {"error": message}}` per container even if some fail
+pub async fn start_batch(mut req: Request<State>) -> Result {
+    let ids: BTreeSet<String> = req.body_json::<Vec<String>>().await?.into_iter().collect();
+    let docker = daemon(&req)?;
+    let (req, docker) = (&req, &docker);
+    let results: BTreeMap<String, Value> = futures::stream::iter(ids)
+        .map(|id| async move {
+            let result = start_one(req, docker, &id).await;
+            (id, result)
+        })
+        .buffer_unordered(BATCH_START_CONCURRENCY)
+        .collect()
+        .await;
+    Ok(Response::builder(200).body(Body::from_json(&results)?).build())
+}
+
+async fn start_one(req: &Request<State>, docker: &Docker, id: &str) -> Value {
+    let request = match docker.containers().get(id).start() {
+        Ok(request) => request,
+        Err(e) => return json!({ "error": e.to_string() }),
+    };
+    let mut response = match req.state().send(request_id(req), request).await {
+        Ok(response) => response,
+        Err(e) => return json!({ "error": e.to_string() }),
+    };
+    // 304 is a container that was running already
+    if response.status().is_success() || response.status() == StatusCode::NotModified {
+        return json!({ "ok": true });
+    }
+    let message = response
+        .body_json::<Value>()
+        .await
+        .ok()
+        .and_then(|body| body["message"].as_str().map(str::to_owned))
+        .unwrap_or_else(|| response.status().to_string());
+    json!({ "error": message })
 }
 
 pub async fn stop(req: Request<State>) -> Result {
     let id = req.param("id")?;
     let time = req.query::<ContainerStopOptions>()?;
-    let time = if let Some(t) = time.wait {
-        Some(Duration::from_secs(t))
-    } else {
-        None
-    };
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).stop(time)?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    let time = time.wait.map(Duration::from_secs);
+    proxy(&req, |docker| docker.containers().get(id).stop(time)).await
 }
 
 pub async fn restart(req: Request<State>) -> Result {
     let id = req.param("id")?;
     let time = req.query::<ContainerStopOptions>()?;
-    let time = if let Some(t) = time.wait {
-        Some(Duration::from_secs(t))
-    } else {
-        None
-    };
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).restart(time)?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    let time = time.wait.map(Duration::from_secs);
+    proxy(&req, |docker| docker.containers().get(id).restart(time)).await
 }
 
 pub async fn kill(req: Request<State>) -> Result {
     let id = req.param("id")?;
     let options = req.query::<ContainerKillOptions>()?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).kill(options.singal)?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    proxy(&req, |docker| docker.containers().get(id).kill(options.signal)).await
 }
 
+/// Rename a container, rejecting names the daemon would refuse and explaining a
+/// name that is taken instead of relaying the bare conflict
 pub async fn rename(req: Request<State>) -> Result {
     let id = req.param("id")?;
     let options = req.query::<ContainerRenameOptions>()?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).rename(options.name.as_str())?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    let name = options.name.as_str();
+    check_container_name(name)?;
+    let response = send(&req, |docker| docker.containers().get(id).rename(name)).await?;
+    if response.status() == StatusCode::Conflict {
+        log::warn!("rename of {} to {} conflicts", id, name);
+        return Ok(name_in_use(name));
+    }
+    forward(response).await
+}
+
+/// 400 for a name the daemon would refuse
+fn check_container_name(name: &str) -> std::result::Result<(), tide::Error> {
+    if valid_container_name(name) {
+        return Ok(());
+    }
+    Err(tide::Error::from_str(
+        StatusCode::BadRequest,
+        format!("invalid container name {:?}, allowed is [a-zA-Z0-9][a-zA-Z0-9_.-]+", name),
+    ))
+}
+
+/// 409 explaining that `name` is taken by another container
+fn name_in_use(name: &str) -> Response {
+    Response::builder(StatusCode::Conflict)
+        .body(json!({
+            "error": format!("container name {:?} is already in use", name),
+            "name": name,
+        }))
+        .build()
 }
 
 pub async fn pause(req: Request<State>) -> Result {
     let id = req.param("id")?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).pause()?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    proxy(&req, |docker| docker.containers().get(id).pause()).await
 }
 
 pub async fn unpause(req: Request<State>) -> Result {
     let id = req.param("id")?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).unpause()?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    proxy(&req, |docker| docker.containers().get(id).unpause()).await
 }
 
 pub async fn attach(req: Request<State>) -> Result {
     let id = req.param("id")?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
+    let args = req.query::<ContainerAttachOptions>()?;
+    let docker = daemon(&req)?;
+    let mut response = req
         .state()
-        .send(docker.containers().get(id).attach()?)
+        .send(
+            request_id(&req),
+            docker
+                .containers()
+                .get(id)
+                .attach(args.detach_keys.as_deref())?,
+        )
         .await?;
-    Ok(tide::Response::from_res(response))
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+    Ok(relay_frames(&req, stream::frames(response.take_body())))
 }
 
+/// Relay an interactive attach: every message of `stdin` is written to the container and
+/// every frame of the daemon `output` is passed to `send`. The output of a container with
+/// a `tty` has no frames, it is passed on as stdout as it arrives. Ends when the client
+/// stops sending, i.e. `stdin` ends, or when the container closes its output.
+pub async fn relay_attach<I, R, W, F, Fut>(
+    stdin: I,
+    output: R,
+    tty: bool,
+    mut input: W,
+    mut send: F,
+) -> io::Result<()>
+where
+    I: Stream<Item = Vec<u8>> + Unpin,
+    R: futures::AsyncBufRead + Send + Sync + Unpin + 'static,
+    W: futures::AsyncWrite + Unpin,
+    F: FnMut(Frame) -> Fut,
+    Fut: std::future::Future<Output = io::Result<()>>,
+{
+    use futures::AsyncWriteExt;
+
+    let upstream = async move {
+        let mut stdin = stdin;
+        while let Some(bytes) = stdin.next().await {
+            input.write_all(&bytes).await?;
+            input.flush().await?;
+        }
+        input.close().await
+    };
+    let downstream = async move {
+        let output = Body::from_reader(output, None);
+        let mut frames: Pin<Box<dyn Stream<Item = io::Result<Frame>> + Send>> = if tty {
+            Box::pin(stream::chunks(output).map_ok(|data| Frame {
+                stream: StreamType::Stdout,
+                data,
+            }))
+        } else {
+            Box::pin(stream::frames(output))
+        };
+        while let Some(frame) = frames.next().await {
+            send(frame?).await?;
+        }
+        Ok(())
+    };
+    futures::pin_mut!(upstream, downstream);
+    match futures::future::select(upstream, downstream).await {
+        futures::future::Either::Left((done, _)) | futures::future::Either::Right((done, _)) => {
+            done
+        }
+    }
+}
+
+/// whether the container was created with a TTY, its output then carries no frame headers
+async fn has_tty(req: &Request<State>, docker: &Docker, id: &str) -> Result<bool> {
+    #[derive(Deserialize)]
+    struct Inspect {
+        #[serde(rename = "Config")]
+        config: TtyConfig,
+    }
+    #[derive(Deserialize)]
+    struct TtyConfig {
+        #[serde(rename = "Tty", default)]
+        tty: bool,
+    }
+
+    let request = docker.containers().get(id).inspect()?;
+    let mut response = req.state().send(request_id(req), request).await?;
+    if !response.status().is_success() {
+        let reason = format!("inspect of {} failed with {}", id, response.status());
+        return Err(tide::Error::from_str(response.status(), reason));
+    }
+    let inspect: Inspect = decode_json(req, &mut response).await?;
+    Ok(inspect.config.tty)
+}
+
+/// Attach to a container over a WebSocket for an interactive terminal. Text and binary
+/// messages of the client are the stdin of the container, its output is sent as binary
+/// messages of the stream type byte (1 stdout, 2 stderr) followed by the data. The output
+/// of a container created with `Tty` is all stdout.
+///
+/// The connection to the daemon is upgraded by hand rather than made by the client of
+/// `State`, so only plain http daemons can be attached to this way. TLS daemons are told
+/// so on the socket, `POST attach` works for them.
+pub async fn attach_ws(req: Request<State>, conn: WebSocketConnection) -> Result<()> {
+    let id = req.param("id")?;
+    let args = req.query::<ContainerAttachOptions>()?;
+    let url = req.ext::<Url>().ok_or(Error::DaemonNotFound)?;
+    // the websocket is already open, failures can only be reported on it
+    if url.scheme() != "http" {
+        let error = format!(
+            "{} daemons can not be attached to over a websocket, use POST attach",
+            url.scheme()
+        );
+        conn.send_json(&json!({ "error": error })).await?;
+        return Ok(());
+    }
+    let docker = daemon(&req)?;
+    let tty = match has_tty(&req, &docker, id).await {
+        Ok(tty) => tty,
+        Err(e) => {
+            log::warn!("attach to {} failed: {}", id, e);
+            conn.send_json(&json!({ "error": e.to_string() })).await?;
+            return Ok(());
+        }
+    };
+    let request = docker
+        .containers()
+        .get(id)
+        .attach(args.detach_keys.as_deref())?;
+    log::debug!("[{}] attach to {} over a websocket", request_id(&req), id);
+    let (output, input) = match upgrade(request).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            log::warn!("attach to {} failed: {}", id, e);
+            conn.send_json(&json!({ "error": e.to_string() })).await?;
+            return Ok(());
+        }
+    };
+
+    let stdin = conn
+        .clone()
+        .take_while(|message| {
+            futures::future::ready(matches!(message, Ok(message) if !message.is_close()))
+        })
+        .filter_map(|message| {
+            futures::future::ready(match message {
+                Ok(Message::Binary(bytes)) => Some(bytes),
+                Ok(Message::Text(text)) => Some(text.into_bytes()),
+                _ => None,
+            })
+        });
+    let send = |frame: Frame| {
+        let conn = conn.clone();
+        async move {
+            let stream = match frame.stream {
+                StreamType::Stdin => 0,
+                StreamType::Stdout => 1,
+                StreamType::Stderr => 2,
+            };
+            let mut message = Vec::with_capacity(frame.data.len() + 1);
+            message.push(stream);
+            message.extend_from_slice(&frame.data);
+            conn.send_bytes(message)
+                .await
+                .map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e.to_string()))
+        }
+    };
+    if let Err(e) = relay_attach(Box::pin(stdin), output, tty, input, send).await {
+        log::warn!("attach to {} ended: {}", id, e);
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct ContainerWaitOptions {
+    pub condition: Option<WaitCondition>,
+}
+
+/// Wait for a container, answering the decoded `WaitResponse` with its exit code
 pub async fn wait(req: Request<State>) -> Result {
     let id = req.param("id")?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).wait()?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    let args = req.query::<ContainerWaitOptions>()?;
+    proxy_json::<WaitResponse, _>(&req, |docker| {
+        docker.containers().get(id).wait_for(args.condition)
+    })
+    .await
 }
 
 pub async fn remove(req: Request<State>) -> Result {
     let id = req.param("id")?;
     let options = req.query::<ContainerRemoveOptions>()?;
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req
-        .state()
-        .send(docker.containers().get(id).remove(options.into())?)
-        .await?;
-    Ok(tide::Response::from_res(response))
+    proxy(&req, |docker| docker.containers().get(id).remove(options.into())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use url::form_urlencoded;
+    use crate::service::mock;
+    use http_types::Method;
+
+    /// Test a batch start reporting every container, with failures next to successes
+    #[async_std::test]
+    async fn start_batch_partial_failure() {
+        let daemon = mock::daemon(|method, target| {
+            assert_eq!(method, "POST");
+            match target {
+                "/containers/web/start" => (204, String::new()),
+                "/containers/db/start" => (304, String::new()),
+                _ => (404, r#"{"message":"No such container: gone"}"#.to_owned()),
+            }
+        })
+        .await;
+        let mut app = tide::with_state(State::for_test());
+        app.at("/containers/start").post(start_batch);
+
+        let mut request = mock::request(Method::Post, "/containers/start", &daemon);
+        request.set_body(Body::from_json(&json!(["web", "db", "gone", "web"])).unwrap());
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let results: Value = response.body_json().await.unwrap();
+        assert_eq!(
+            results,
+            json!({
+                "web": { "ok": true },
+                "db": { "ok": true },
+                "gone": { "error": "No such container: gone" },
+            })
+        );
+    }
+
+    /// Test an export being relayed as a stream of unknown length rather than a buffer
+    #[async_std::test]
+    async fn export_streamed() {
+        const SIZE: usize = 8 * 1024 * 1024;
+        let daemon = mock::daemon(|method, target| {
+            assert_eq!(method, "GET");
+            assert_eq!(target, "/containers/web/export");
+            (200, "x".repeat(SIZE))
+        })
+        .await;
+        let mut app = tide::with_state(State::for_test());
+        app.at("/containers/:id/export").get(export);
+
+        let request = mock::request(Method::Get, "/containers/web/export", &daemon);
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.len(), None);
+        // the daemon's content type is kept
+        assert_eq!(response.content_type(), Some(mime::JSON));
+        assert_eq!(response.body_bytes().await.unwrap().len(), SIZE);
+    }
+
+    /// Test an invalid new name never reaching the daemon and a taken name being explained
+    #[async_std::test]
+    async fn rename_invalid_and_conflict() {
+        let daemon = mock::daemon(|_, target| {
+            assert_eq!(target, "/containers/web/rename?name=db");
+            let message = "Conflict. The container name \"/db\" is already in use";
+            (409, json!({ "message": message }).to_string())
+        })
+        .await;
+        let mut app = tide::with_state(State::for_test());
+        app.at("/containers/:id/rename").post(rename);
+
+        for name in &["-db", "d", "db%2Fmain", "db%20main"] {
+            let url = format!("/containers/web/rename?name={}", name);
+            let request = mock::request(Method::Post, &url, &daemon);
+            let response: http_types::Response = app.respond(request).await.unwrap();
+            assert_eq!(response.status(), 400, "{}", name);
+        }
+
+        let url = "/containers/web/rename?name=db";
+        let request = mock::request(Method::Post, url, &daemon);
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 409);
+        let body: Value = response.body_json().await.unwrap();
+        assert_eq!(body["error"], "container name \"db\" is already in use");
+        assert_eq!(body["name"], "db");
+    }
+
+    /// Test the create name reaching the daemon as the `name` query param, and a taken
+    /// name answered with a conflict naming it
+    #[async_std::test]
+    async fn create_with_name() {
+        let daemon = mock::daemon(|method, target| {
+            assert_eq!(method, "POST");
+            match target {
+                "/containers/create?name=web" => {
+                    (201, json!({ "Id": "4fa6e0f0c678", "Warnings": [] }).to_string())
+                }
+                "/containers/create?name=db" => {
+                    let message = "Conflict. The container name \"/db\" is already in use";
+                    (409, json!({ "message": message }).to_string())
+                }
+                _ => panic!("unexpected create {}", target),
+            }
+        })
+        .await;
+        let mut app = tide::with_state(State::for_test());
+        app.at("/containers/create").post(create);
+        let body = serde_json::to_value(ContainerOptions::builder("alpine").build()).unwrap();
+
+        for (name, status) in &[("web", 201), ("db", 409), ("-web", 400)] {
+            let url = format!("/containers/create?name={}", name);
+            let mut request = mock::request(Method::Post, &url, &daemon);
+            request.set_body(Body::from_json(&body).unwrap());
+            let mut response: http_types::Response = app.respond(request).await.unwrap();
+            assert_eq!(response.status(), *status, "{}", name);
+            if *status == 201 {
+                let created: ContainerCreateInfo = response.body_json().await.unwrap();
+                assert_eq!(created.id, "4fa6e0f0c678");
+            }
+            if *status == 409 {
+                let conflict: Value = response.body_json().await.unwrap();
+                assert_eq!(conflict["name"], "db");
+            }
+        }
+    }
+
+    /// Test the fingerprint of a create not depending on the order of its options
+    #[test]
+    fn create_fingerprint_canonical() {
+        let labels: HashMap<&str, &str> = vec![("app", "web"), ("tier", "front"), ("team", "ops")]
+            .into_iter()
+            .collect();
+        let options = |memory| {
+            ContainerOptions::builder("redis")
+                .name("cache")
+                .env(vec!["A=1", "B=2"])
+                .cmd(vec!["redis-server", "--appendonly", "yes"])
+                .memory(memory)
+                .cpu_shares(512)
+                .labels(&labels)
+                .restart_policy("on-failure", 3)
+                .build()
+        };
+        let first = create_fingerprint(&options(314_572_800)).unwrap();
+        for _ in 0..32 {
+            // each build and each decode of the body keeps its options in a new map
+            let image = options(314_572_800);
+            assert_eq!(create_fingerprint(&image).unwrap(), first);
+            let decoded: ContainerOptions =
+                serde_json::from_value(serde_json::to_value(&image).unwrap()).unwrap();
+            assert_eq!(create_fingerprint(&decoded).unwrap(), first);
+        }
+        assert_ne!(create_fingerprint(&options(1_048_576)).unwrap(), first);
+        let mut renamed = options(314_572_800);
+        renamed.name = Some("store".to_owned());
+        assert_ne!(create_fingerprint(&renamed).unwrap(), first);
+    }
+
+    /// Test a retried create with the same idempotency key reaching the daemon once, and a
+    /// reused key with other options being refused
+    #[async_std::test]
+    async fn create_idempotency_key() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let creates = Arc::new(AtomicUsize::new(0));
+        let counted = creates.clone();
+        let daemon = mock::daemon(move |_, target| {
+            assert_eq!(target, "/containers/create");
+            let id = format!("4fa6e0f0c67{}", counted.fetch_add(1, Ordering::SeqCst));
+            (201, json!({ "Id": id, "Warnings": [] }).to_string())
+        })
+        .await;
+        let mut app = tide::with_state(State::for_test());
+        app.at("/containers/create").post(create);
+        let body = serde_json::to_value(ContainerOptions::builder("alpine").build()).unwrap();
+
+        let mut ids = vec![];
+        for key in &["k-1", "k-1", "k-2"] {
+            let mut request = mock::request(Method::Post, "/containers/create", &daemon);
+            request.insert_header(IDEMPOTENCY_KEY, *key);
+            request.set_body(Body::from_json(&body).unwrap());
+            let mut response: http_types::Response = app.respond(request).await.unwrap();
+            assert_eq!(response.status(), 201, "{}", key);
+            let created: ContainerCreateInfo = response.body_json().await.unwrap();
+            ids.push(created.id);
+        }
+        assert_eq!(creates.load(Ordering::SeqCst), 2);
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[0], ids[2]);
+
+        // a used key is refused for another image
+        let other = serde_json::to_value(ContainerOptions::builder("redis").build()).unwrap();
+        let mut request = mock::request(Method::Post, "/containers/create", &daemon);
+        request.insert_header(IDEMPOTENCY_KEY, "k-1");
+        request.set_body(Body::from_json(&other).unwrap());
+        let response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 422);
+        assert_eq!(creates.load(Ordering::SeqCst), 2);
+    }
+
+    /// Test every create warning being answered as its own `Warning` header
+    #[async_std::test]
+    async fn create_warning_headers() {
+        let daemon = mock::daemon(|_, _| {
+            let warnings = [
+                "Your kernel does not support swap limit capabilities",
+                "Published ports are discarded when using \"host\" network mode",
+            ];
+            let body = json!({ "Id": "4fa6e0f0c678", "Warnings": warnings });
+            (201, body.to_string())
+        })
+        .await;
+        let mut app = tide::with_state(State::for_test());
+        app.at("/containers/create").post(create);
+        let body = serde_json::to_value(ContainerOptions::builder("alpine").build()).unwrap();
+
+        let mut request = mock::request(Method::Post, "/containers/create", &daemon);
+        request.set_body(Body::from_json(&body).unwrap());
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 201);
+        let values: Vec<&str> = response[headers::WARNING]
+            .iter()
+            .map(|v| v.as_str())
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                r#"199 - "Your kernel does not support swap limit capabilities""#,
+                r#"199 - "Published ports are discarded when using \"host\" network mode""#,
+            ]
+        );
+        let created: ContainerCreateInfo = response.body_json().await.unwrap();
+        assert_eq!(created.warnings.len(), 2);
+    }
+
+    /// Test the create platform reaching the daemon as the `platform` query param, and
+    /// malformed platforms being rejected before it
+    #[async_std::test]
+    async fn create_with_platform() {
+        let daemon = mock::daemon(|_, target| {
+            assert_eq!(target, "/containers/create?name=web&platform=linux%2Farm64");
+            (201, json!({ "Id": "4fa6e0f0c678", "Warnings": [] }).to_string())
+        })
+        .await;
+        let mut app = tide::with_state(State::for_test());
+        app.at("/containers/create").post(create);
+        let body = serde_json::to_value(ContainerOptions::builder("alpine").build()).unwrap();
+
+        for (platform, status) in &[("linux/arm64", 201), ("linux", 400), ("linux/arm/v7/x", 400)] {
+            let url = format!("/containers/create?name=web&platform={}", platform);
+            let mut request = mock::request(Method::Post, &url, &daemon);
+            request.set_body(Body::from_json(&body).unwrap());
+            let response: http_types::Response = app.respond(request).await.unwrap();
+            assert_eq!(response.status(), *status, "{}", platform);
+        }
+    }
+
+    /// Test stdin reaching the container while its framed output reaches the client
+    #[async_std::test]
+    async fn relay_attach_both_ways() {
+        let mut output = vec![1, 0, 0, 0, 0, 0, 0, 3];
+        output.extend_from_slice(b"$ \n");
+        output.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 9]);
+        output.extend_from_slice(b"not found");
+        let output = futures::io::Cursor::new(output);
+        let mut input = futures::io::Cursor::new(Vec::new());
+        // the client keeps the socket open, the relay ends with the container output
+        let stdin = futures::stream::iter(vec![b"ls\n".to_vec(), b"exit\n".to_vec()])
+            .chain(futures::stream::pending());
+        let sent = std::sync::Mutex::new(Vec::new());
+        relay_attach(stdin, output, false, &mut input, |frame| {
+            sent.lock().unwrap().push(frame);
+            futures::future::ready(Ok(()))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(input.into_inner(), b"ls\nexit\n");
+        let sent = sent.into_inner().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].stream, StreamType::Stdout);
+        assert_eq!(sent[0].data, b"$ \n");
+        assert_eq!(sent[1].stream, StreamType::Stderr);
+        assert_eq!(sent[1].data, b"not found");
+
+        // a client going away ends the relay even while the container is quiet
+        let mut input = futures::io::Cursor::new(Vec::new());
+        let quiet = futures::io::BufReader::new(futures::io::Cursor::new(Vec::new()));
+        let stdin = futures::stream::iter(vec![b"top\n".to_vec()]);
+        relay_attach(stdin, quiet, false, &mut input, |_| futures::future::ready(Ok(())))
+            .await
+            .unwrap();
+        assert_eq!(input.into_inner(), b"top\n");
+    }
+
+    /// Test the output of a TTY container being relayed unframed, even when it starts like
+    /// a frame header
+    #[async_std::test]
+    async fn relay_attach_tty_raw() {
+        let raw = b"\x01\x00\x00\x00\x00\x00\x00\x03$ ls\r\n".to_vec();
+        let output = futures::io::Cursor::new(raw.clone());
+        let mut input = futures::io::Cursor::new(Vec::new());
+        let stdin = futures::stream::pending();
+        let sent = std::sync::Mutex::new(Vec::new());
+        relay_attach(stdin, output, true, &mut input, |frame| {
+            sent.lock().unwrap().push(frame);
+            futures::future::ready(Ok(()))
+        })
+        .await
+        .unwrap();
+
+        let sent = sent.into_inner().unwrap();
+        assert!(sent.iter().all(|frame| frame.stream == StreamType::Stdout));
+        let data: Vec<u8> = sent.into_iter().flat_map(|frame| frame.data).collect();
+        assert_eq!(data, raw);
+    }
+
+    /// Test the remove query carrying the link option
+    #[test]
+    fn remove_options_link() {
+        let args: ContainerRemoveOptions =
+            serde_json::from_value(json!({ "link": true, "force": false })).unwrap();
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let request = docker.containers().get("web").remove(args.into()).unwrap();
+        let params: HashMap<String, String> = request.url().query_pairs().into_owned().collect();
+        assert_eq!(params["link"], "true");
+        assert_eq!(params["force"], "false");
+        assert!(!params.contains_key("v"));
+    }
+
+    /// Test the kill signal under its name and its old misspelling
+    #[test]
+    fn kill_signal_alias() {
+        let signal: ContainerKillOptions =
+            serde_json::from_value(json!({ "signal": "SIGHUP" })).unwrap();
+        let singal: ContainerKillOptions =
+            serde_json::from_value(json!({ "singal": "SIGHUP" })).unwrap();
+        assert_eq!(signal.signal.as_deref(), Some("SIGHUP"));
+        assert_eq!(signal.signal, singal.signal);
+
+        let docker = Docker::host("http://127.0.0.1:2375".parse().unwrap());
+        let request = docker.containers().get("web").kill(singal.signal).unwrap();
+        assert_eq!(request.url().query(), Some("signal=SIGHUP"));
+    }
+
+    /// Test a time bounded logs request keeping its upper bound
+    #[test]
+    fn logs_options_until() {
+        let args: ContainerLogsOptions = serde_json::from_value(json!({
+            "stdout": true,
+            "since": 1620000000,
+            "until": 1620003600,
+        }))
+        .unwrap();
+        let options: LogsOptions = args.into();
+        let serialized = options.serialize().unwrap();
+        let params: HashMap<String, String> =
+            form_urlencoded::parse(serialized.as_bytes()).into_owned().collect();
+        assert_eq!(params["since"], "1620000000");
+        assert_eq!(params["until"], "1620003600");
+        assert_eq!(params["stdout"], "true");
+    }
+
+    /// Test `tail` reaching the daemon as `all` or a number of lines, and anything else
+    /// being refused before it
+    #[async_std::test]
+    async fn logs_tail() {
+        let daemon = mock::daemon(|_, target| {
+            let (path, query) = target.split_once('?').unwrap();
+            assert_eq!(path, "/containers/web/logs");
+            let params: HashMap<String, String> =
+                form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+            (200, params["tail"].clone())
+        })
+        .await;
+        let mut app = tide::with_state(State::for_test());
+        app.at("/containers/:id/logs").get(logs);
+
+        let cases = [
+            ("&tail=all", 200, "all"),
+            ("&tail=10", 200, "10"),
+            ("", 200, "all"),
+            ("&tail=-1", 400, ""),
+            ("&tail=ten", 400, ""),
+            ("&tail=", 400, ""),
+        ];
+        for (tail, status, sent) in &cases {
+            let url = format!("/containers/web/logs?stdout=true&all=true{}", tail);
+            let request = mock::request(Method::Get, &url, &daemon);
+            let mut response: http_types::Response = app.respond(request).await.unwrap();
+            assert_eq!(response.status(), *status, "{}", tail);
+            if *status == 200 {
+                assert_eq!(response.body_string().await.unwrap(), *sent);
+            }
+        }
+    }
+
+    /// Test a followed log stream reopened since its last line without the tail, which
+    /// would skip lines written while it was away
+    #[async_std::test]
+    async fn logs_follow_reconnect_without_tail() {
+        use std::sync::{Arc, Mutex};
+
+        let reconnects = Arc::new(Mutex::new(Vec::new()));
+        let daemon = mock::daemon({
+            let reconnects = reconnects.clone();
+            move |_, target| {
+                let (_, query) = target.split_once('?').unwrap();
+                let params: HashMap<String, String> =
+                    form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+                if params.contains_key("since") {
+                    reconnects.lock().unwrap().push(params);
+                    return (200, String::new());
+                }
+                assert_eq!(params["tail"], "2");
+                let line = b"2021-05-01T10:00:01.500000000Z two\n";
+                let mut frame = vec![1, 0, 0, 0];
+                frame.extend_from_slice(&(line.len() as u32).to_be_bytes());
+                frame.extend_from_slice(line);
+                (200, String::from_utf8(frame).unwrap())
+            }
+        })
+        .await;
+        let mut app = tide::with_state(State::for_test());
+        app.at("/containers/:id/logs").get(logs);
+
+        let url = "/containers/web/logs?stdout=true&all=true&follow=true&timestamps=true&tail=2";
+        let request = mock::request(Method::Get, url, &daemon);
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        response.body_bytes().await.unwrap();
+
+        let reconnects = reconnects.lock().unwrap();
+        assert_eq!(reconnects.len(), MAX_LOG_RECONNECTS);
+        for params in reconnects.iter() {
+            assert_eq!(params["since"], "1619863201.500000000");
+            assert_eq!(params["tail"], "all");
+        }
+    }
+
+    /// Test logs times given as unix seconds, RFC3339 timestamps and durations before now
+    #[test]
+    fn logs_options_time_formats() {
+        let now = 1620003600;
+        assert_eq!(parse_log_time("1620000000", now), Ok(1620000000));
+        assert_eq!(parse_log_time("2021-05-03T00:00:00Z", now), Ok(1620000000));
+        assert_eq!(parse_log_time("2021-05-03T02:00:00+02:00", now), Ok(1620000000));
+        assert_eq!(parse_log_time("10m", now), Ok(1620003000));
+        assert_eq!(parse_log_time("1h30s", now), Ok(1620000000 - 30));
+        for invalid in &["", "yesterday", "10d", "m", "5m10", "2021-05-03", "9999999999999999h"] {
+            assert!(parse_log_time(invalid, now).is_err(), "{}", invalid);
+        }
+
+        let args: ContainerLogsOptions = serde_json::from_value(json!({
+            "since": "2021-05-03T00:00:00Z",
+            "until": 1620003600,
+        }))
+        .unwrap();
+        assert_eq!(args.since, Some(1620000000));
+        assert_eq!(args.until, Some(1620003600));
+        let args: ContainerLogsOptions = serde_json::from_value(json!({ "since": "10m" })).unwrap();
+        assert!(args.since.unwrap() > 1620003600);
+        let invalid = serde_json::from_value::<ContainerLogsOptions>(json!({ "since": "soon" }));
+        assert!(invalid.is_err());
+    }
+
+    /// Test the list query turning into the docker query string with several filters
+    #[test]
+    fn container_list_query_filters() {
+        let query = ContainerListQuery {
+            all: Some(true),
+            limit: Some(5),
+            filters: Some(
+                json!({
+                    "status": ["running", "paused"],
+                    "label": ["env=prod", "tier"],
+                    "name": ["web"],
+                    "ancestor": ["alpine"],
+                    "health": ["healthy", "starting"],
+                })
+                .to_string(),
+            ),
+            ..Default::default()
+        };
+        let serialized = query.options().unwrap().serialize().unwrap();
+        let params: HashMap<String, String> =
+            form_urlencoded::parse(serialized.as_bytes()).into_owned().collect();
+        assert_eq!(params["all"], "true");
+        assert_eq!(params["limit"], "5");
+        let filters: Value = serde_json::from_str(&params["filters"]).unwrap();
+        assert_eq!(filters["status"], json!(["running", "paused"]));
+        assert_eq!(filters["label"], json!(["env=prod", "tier"]));
+        assert_eq!(filters["name"], json!(["web"]));
+        assert_eq!(filters["ancestor"], json!(["alpine"]));
+        assert_eq!(filters["health"], json!(["healthy", "starting"]));
+
+        let query = ContainerListQuery {
+            filters: Some(r#"{"health":["sick"]}"#.to_owned()),
+            ..Default::default()
+        };
+        assert!(query.options().is_err());
+
+        let query = ContainerListQuery {
+            filters: Some(r#"{"volume":["data"]}"#.to_owned()),
+            ..Default::default()
+        };
+        assert!(query.options().is_err());
+    }
 }
@@ -0,0 +1,838 @@
+use url::Url;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    sync::{Arc, Mutex},
+};
+
+use crate::docker::{
+    docker::Docker,
+    image::{
+        BuildOptions, BuildStep, History, ImageDetails, ImageFilter, ImageLayers,
+        ImageListOptions, ImageRemoveOptions, ImageSearchOptions, PullError, PullEvent,
+        PullOptions, RegistryAuth, SearchFilter, SearchResult, Status, TagOptions,
+        TarballEncoding, valid_platform,
+    },
+    stream,
+};
+use crate::errors::Error;
+use crate::service::{daemon, forward, proxy, proxy_json, proxy_tar, send, wants_sse};
+use crate::request_id::request_id;
+use crate::State;
+
+use futures::{AsyncReadExt, Stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tide::{
+    http::{headers, mime},
+    Body, Request, Response, Result, StatusCode,
+};
+
+#[derive(Deserialize)]
+pub struct PullQuery {
+    #[serde(rename = "fromImage")]
+    pub from_image: String,
+    pub tag: Option<String>,
+    /// `os/arch[/variant]` of a multi-arch image
+    pub platform: Option<String>,
+}
+
+/// Build the image list options from the query pairs of the request. `label` and
+/// `dangling` may be repeated, contradicting filters are rejected.
+pub fn list_options<'a, I>(pairs: I) -> std::result::Result<ImageListOptions, String>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut builder = ImageListOptions::builder();
+    let mut filters = Vec::new();
+    let mut dangling = None;
+    let mut reference = None;
+    for (key, value) in pairs {
+        match key {
+            "all" if parse_bool(key, value)? => {
+                builder.all();
+            }
+            "digests" => {
+                builder.digests(parse_bool(key, value)?);
+            }
+            "filter" => reference = Some(value),
+            "label" => filters.push(match value.split_once('=') {
+                Some((name, value)) => ImageFilter::Label(name.to_owned(), value.to_owned()),
+                None => ImageFilter::LabelName(value.to_owned()),
+            }),
+            "dangling" => {
+                let value = parse_bool(key, value)?;
+                if dangling.replace(value).is_some_and(|previous| previous != value) {
+                    return Err("dangling can not be both true and false".to_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(reference) = reference {
+        if dangling == Some(true) {
+            return Err("dangling images have no reference to filter on".to_owned());
+        }
+        builder.filter_name(reference);
+    }
+    match dangling {
+        Some(true) => filters.push(ImageFilter::Dangling),
+        Some(false) => filters.push(ImageFilter::NotDangling),
+        None => {}
+    }
+    if !filters.is_empty() {
+        builder.filter(filters);
+    }
+    Ok(builder.build())
+}
+
+fn parse_bool(key: &str, value: &str) -> std::result::Result<bool, String> {
+    match value {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err(format!("{} must be a boolean, got {:?}", key, value)),
+    }
+}
+
+pub async fn list(req: Request<State>) -> Result {
+    let pairs: Vec<(String, String)> = req.url().query_pairs().into_owned().collect();
+    let options = list_options(pairs.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+        .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e))?;
+    proxy(&req, |docker| docker.images().list(&options)).await
+}
+
+/// Query of an image search, `filters` is the docker filters json restricted to
+/// `is-official`, `is-automated` and `stars`
+#[derive(Deserialize)]
+pub struct ImageSearchQuery {
+    pub term: String,
+    pub limit: Option<u64>,
+    pub filters: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ImageSearchFilters {
+    #[serde(default, rename = "is-official")]
+    is_official: Vec<String>,
+    #[serde(default, rename = "is-automated")]
+    is_automated: Vec<String>,
+    #[serde(default)]
+    stars: Vec<String>,
+}
+
+impl ImageSearchQuery {
+    pub fn options(&self) -> std::result::Result<ImageSearchOptions, String> {
+        let mut builder = ImageSearchOptions::builder(&self.term);
+        if let Some(limit) = self.limit {
+            builder.limit(limit);
+        }
+        if let Some(filters) = &self.filters {
+            let filters: ImageSearchFilters =
+                serde_json::from_str(filters).map_err(|e| e.to_string())?;
+            let mut list = Vec::new();
+            for value in &filters.is_official {
+                list.push(SearchFilter::IsOfficial(parse_bool("is-official", value)?));
+            }
+            for value in &filters.is_automated {
+                list.push(SearchFilter::IsAutomated(parse_bool("is-automated", value)?));
+            }
+            for value in &filters.stars {
+                let stars = value
+                    .parse()
+                    .map_err(|_| format!("stars must be a number, got {:?}", value))?;
+                list.push(SearchFilter::Stars(stars));
+            }
+            if !list.is_empty() {
+                builder.filter(list);
+            }
+        }
+        Ok(builder.build())
+    }
+}
+
+pub async fn search(req: Request<State>) -> Result {
+    let options = req
+        .query::<ImageSearchQuery>()?
+        .options()
+        .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e))?;
+    proxy_json::<Vec<SearchResult>, _>(&req, |docker| docker.images().search_with(&options)).await
+}
+
+/// one event as an sse event named `name` or a json line
+fn event_chunk<T: Serialize>(event: &T, name: &str, sse: bool) -> Vec<u8> {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    if !sse {
+        return format!("{}\n", data).into_bytes();
+    }
+    format!("event: {}\ndata: {}\n\n", name, data).into_bytes()
+}
+
+fn pull_chunk(event: &PullEvent, sse: bool) -> Vec<u8> {
+    let name = match event {
+        PullEvent::Progress(_) => "progress",
+        PullEvent::Error(_) => "error",
+    };
+    event_chunk(event, name, sse)
+}
+
+/// a response relaying `chunks` as sse events or json lines
+fn event_response<S>(chunks: S, sse: bool) -> Response
+where
+    S: Stream<Item = io::Result<Vec<u8>>> + Send + 'static,
+{
+    let reader = stream::SyncStream::new(Box::pin(chunks)).into_async_read();
+    let builder = if sse {
+        Response::builder(200)
+            .content_type(mime::SSE)
+            .header(headers::CACHE_CONTROL, "no-cache")
+    } else {
+        Response::builder(200).content_type(mime::JSON)
+    };
+    builder.body(Body::from_reader(reader, None)).build()
+}
+
+/// 400 for a platform not of the form `os/arch[/variant]`
+pub(crate) fn check_platform(platform: &str) -> std::result::Result<(), tide::Error> {
+    if valid_platform(platform) {
+        return Ok(());
+    }
+    Err(tide::Error::from_str(
+        StatusCode::BadRequest,
+        format!("invalid platform {:?}, expected os/arch[/variant]", platform),
+    ))
+}
+
+/// Pull an image, relaying the progress of every layer as it arrives. A pull failing
+/// before any progress is answered with a matching status instead of a stream.
+pub async fn pull(req: Request<State>) -> Result {
+    let query = req.query::<PullQuery>()?;
+    let url = req.ext::<Url>().ok_or(Error::DaemonNotFound)?;
+    let docker = daemon(&req)?;
+    let mut options = PullOptions::builder();
+    options.image(query.from_image);
+    if let Some(tag) = query.tag {
+        options.tag(tag);
+    }
+    if let Some(platform) = query.platform {
+        check_platform(&platform)?;
+        options.platform(platform);
+    }
+    let mut response = req
+        .state()
+        .send(request_id(&req), docker.images().pull(&options.build())?)
+        .await?;
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+
+    let mut events = stream::lines(response.take_body()).map(|line| {
+        line.and_then(|line| {
+            PullEvent::parse(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    });
+    let first = match events.next().await {
+        Some(event) => event?,
+        None => return Ok(Response::new(StatusCode::Ok)),
+    };
+    if let PullEvent::Error(error) = &first {
+        log::warn!("pull of {} failed: {}", url, error.error);
+        return Ok(Response::builder(error.status())
+            .body(json!({ "error": error.error }))
+            .build());
+    }
+
+    let sse = wants_sse(&req);
+    let chunks = futures::stream::once(futures::future::ready(Ok(first)))
+        .chain(events)
+        .map_ok(move |event| pull_chunk(&event, sse));
+    Ok(event_response(chunks, sse))
+}
+
+/// One image of a batch pull
+#[derive(Deserialize, Debug)]
+pub struct PullBatchEntry {
+    pub image: String,
+    pub tag: Option<String>,
+    pub auth: Option<RegistryAuth>,
+}
+
+impl PullBatchEntry {
+    /// `image:tag`, the image events and the summary are reported for
+    pub fn reference(&self) -> String {
+        match &self.tag {
+            Some(tag) => format!("{}:{}", self.image, tag),
+            None => self.image.clone(),
+        }
+    }
+
+    fn options(&self) -> PullOptions {
+        let mut options = PullOptions::builder();
+        options.image(self.image.as_str());
+        if let Some(tag) = &self.tag {
+            options.tag(tag.as_str());
+        }
+        if let Some(auth) = &self.auth {
+            options.auth(auth.clone());
+        }
+        options.build()
+    }
+}
+
+/// images a batch pull pulls at the same time
+const BATCH_PULL_CONCURRENCY: usize = 3;
+
+/// A pull event of a batch, tagged with the image it belongs to
+#[derive(Serialize)]
+struct BatchPullEvent<'a> {
+    image: &'a str,
+    #[serde(flatten)]
+    event: &'a PullEvent,
+}
+
+fn pull_failed(error: String) -> PullEvent {
+    PullEvent::Error(PullError {
+        error,
+        error_detail: None,
+    })
+}
+
+/// The events of one pull of a batch. A request the daemon refuses ends in a single
+/// error event, so that it is reported like a failure during the pull.
+async fn daemon_pull(
+    state: State,
+    id: String,
+    docker: Docker,
+    options: PullOptions,
+) -> futures::stream::BoxStream<'static, PullEvent> {
+    let single = |event| futures::stream::once(futures::future::ready(event)).boxed();
+    let request = match docker.images().pull(&options) {
+        Ok(request) => request,
+        Err(e) => return single(pull_failed(e.to_string())),
+    };
+    let mut response = match state.send(&id, request).await {
+        Ok(response) => response,
+        Err(e) => return single(pull_failed(e.to_string())),
+    };
+    if !response.status().is_success() {
+        let body = response.body_string().await.unwrap_or_default();
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| body.get("message").and_then(|m| m.as_str()).map(str::to_owned))
+            .unwrap_or_else(|| response.status().canonical_reason().to_owned());
+        return single(pull_failed(message));
+    }
+    stream::lines(response.take_body())
+        .map(|line| match line {
+            Ok(line) => PullEvent::parse(&line).unwrap_or_else(|e| pull_failed(e.to_string())),
+            Err(e) => pull_failed(e.to_string()),
+        })
+        .boxed()
+}
+
+/// Run the pulls `pull` makes for `entries`, at most `limit` at a time, as one stream of
+/// events tagged with their image. It ends with a `summary` event of `{image: {"ok": true}}`
+/// or `{image: {"error": message}}` per image.
+fn pull_all<F, S>(
+    entries: Vec<PullBatchEntry>,
+    limit: usize,
+    sse: bool,
+    pull: F,
+) -> impl Stream<Item = io::Result<Vec<u8>>> + Send + 'static
+where
+    F: Fn(&PullBatchEntry) -> S,
+    S: Stream<Item = PullEvent> + Send + 'static,
+{
+    let mut seen = BTreeSet::new();
+    let entries: Vec<PullBatchEntry> = entries
+        .into_iter()
+        .filter(|entry| seen.insert(entry.reference()))
+        .collect();
+    let results: BTreeMap<String, serde_json::Value> = entries
+        .iter()
+        .map(|entry| (entry.reference(), json!({ "ok": true })))
+        .collect();
+    let results = Arc::new(Mutex::new(results));
+
+    let pulls: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let image = entry.reference();
+            pull(entry).map(move |event| (image.clone(), event)).boxed()
+        })
+        .collect();
+    let recorded = results.clone();
+    let events = futures::stream::iter(pulls)
+        .flatten_unordered(limit)
+        .map(move |(image, event)| {
+            let name = match &event {
+                PullEvent::Progress(_) => "progress",
+                PullEvent::Error(error) => {
+                    log::warn!("pull of {} failed: {}", image, error.error);
+                    let mut results = recorded.lock().unwrap();
+                    results.insert(image.clone(), json!({ "error": error.error }));
+                    "error"
+                }
+            };
+            let tagged = BatchPullEvent {
+                image: &image,
+                event: &event,
+            };
+            Ok(event_chunk(&tagged, name, sse))
+        });
+    let summary = futures::stream::once(async move {
+        let results = results.lock().unwrap();
+        Ok::<_, io::Error>(event_chunk(&*results, "summary", sse))
+    });
+    events.chain(summary)
+}
+
+/// Pull a json array of `{image, tag, auth}` with a bounded number of pulls at a time,
+/// relaying the progress of all of them tagged with their `image` and ending with a
+/// summary of which pulls succeeded. A failing pull does not stop the others.
+pub async fn pull_batch(mut req: Request<State>) -> Result {
+    let entries: Vec<PullBatchEntry> = req.body_json().await?;
+    let docker = daemon(&req)?;
+    let state = req.state().clone();
+    let id = request_id(&req).to_owned();
+    let sse = wants_sse(&req);
+    let chunks = pull_all(entries, BATCH_PULL_CONCURRENCY, sse, move |entry| {
+        let pull = daemon_pull(state.clone(), id.clone(), docker.clone(), entry.options());
+        futures::stream::once(pull).flatten()
+    });
+    Ok(event_response(chunks, sse))
+}
+
+/// Target of an image tag, from a json body or the query
+#[derive(Deserialize, Default)]
+pub struct TagQuery {
+    pub repo: Option<String>,
+    pub tag: Option<String>,
+}
+
+impl TagQuery {
+    /// the daemon answers a missing repo with an unrelated error, so it is required here
+    pub fn options(&self) -> std::result::Result<TagOptions, String> {
+        let repo = self
+            .repo
+            .as_deref()
+            .filter(|repo| !repo.is_empty())
+            .ok_or_else(|| "repo is required".to_owned())?;
+        let mut builder = TagOptions::builder();
+        builder.repo(repo);
+        if let Some(tag) = self.tag.as_deref().filter(|tag| !tag.is_empty()) {
+            builder.tag(tag);
+        }
+        Ok(builder.build())
+    }
+}
+
+pub async fn tag(mut req: Request<State>) -> Result {
+    let is_json = req
+        .content_type()
+        .is_some_and(|ct| ct.essence() == mime::JSON.essence());
+    let query: TagQuery = if is_json {
+        req.body_json().await?
+    } else {
+        req.query()?
+    };
+    let options = query
+        .options()
+        .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e))?;
+    let name = req.param("name")?;
+    proxy(&req, |docker| docker.images().get(name).tag(&options)).await
+}
+
+#[derive(Deserialize)]
+pub struct ImageRemoveQuery {
+    pub force: Option<bool>,
+    pub noprune: Option<bool>,
+}
+
+/// Remove an image, answering with the decoded list of untagged and deleted references
+pub async fn remove(req: Request<State>) -> Result {
+    let name = req.param("name")?;
+    let query = req.query::<ImageRemoveQuery>()?;
+    let mut options = ImageRemoveOptions::builder();
+    if let Some(force) = query.force {
+        options.force(force);
+    }
+    if let Some(noprune) = query.noprune {
+        options.noprune(noprune);
+    }
+    proxy_json::<Vec<Status>, _>(&req, |docker| {
+        docker.images().get(name).remove(&options.build())
+    })
+    .await
+}
+
+/// Build parameters of an uploaded context, named like the docker query
+#[derive(Deserialize, Default)]
+pub struct BuildQuery {
+    pub dockerfile: Option<String>,
+    pub t: Option<String>,
+    pub nocache: Option<bool>,
+    pub rm: Option<bool>,
+    pub forcerm: Option<bool>,
+    pub networkmode: Option<String>,
+    pub memory: Option<u64>,
+    pub cpushares: Option<u32>,
+    /// `false` answers the steps at once when the build is done, with a matching status
+    pub stream: Option<bool>,
+}
+
+impl From<BuildQuery> for BuildOptions {
+    fn from(query: BuildQuery) -> BuildOptions {
+        // the context is the request body, there is no directory to read
+        let mut builder = BuildOptions::builder("");
+        if let Some(dockerfile) = query.dockerfile {
+            builder.dockerfile(dockerfile);
+        }
+        if let Some(t) = query.t {
+            builder.tag(t);
+        }
+        if let Some(nocache) = query.nocache {
+            builder.nocache(nocache);
+        }
+        if let Some(rm) = query.rm {
+            builder.rm(rm);
+        }
+        if let Some(forcerm) = query.forcerm {
+            builder.forcerm(forcerm);
+        }
+        if let Some(networkmode) = query.networkmode {
+            builder.network_mode(networkmode);
+        }
+        if let Some(memory) = query.memory {
+            builder.memory(memory);
+        }
+        if let Some(cpushares) = query.cpushares {
+            builder.cpu_shares(cpushares);
+        }
+        builder.build()
+    }
+}
+
+/// Load images from a tarball relayed to the daemon as it arrives. The compression is
+/// told from the first bytes, the daemon decompresses.
+pub async fn load(mut req: Request<State>) -> Result {
+    let (encoding, tarball) = tarball_encoding(req.take_body()).await?;
+    proxy(&req, |docker| docker.images().import_stream(tarball, encoding)).await
+}
+
+/// The compression of `tarball` and the tarball again, the bytes read to tell it in front.
+/// A single read may return fewer bytes than the magic of the compression is long.
+async fn tarball_encoding(mut tarball: Body) -> io::Result<(Option<TarballEncoding>, Body)> {
+    let mut prefix = vec![0; TarballEncoding::MAGIC_LEN];
+    let mut read = 0;
+    while read < prefix.len() {
+        match tarball.read(&mut prefix[read..]).await? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    prefix.truncate(read);
+    let encoding = TarballEncoding::detect(&prefix);
+    let reader = futures::io::Cursor::new(prefix).chain(tarball);
+    Ok((encoding, Body::from_reader(reader, None)))
+}
+
+/// Build an image from the tar context in the request body, relaying every step of
+/// the build output as it arrives. Once the daemon accepted the build it is answered with
+/// a 200, the status is sent before the first step runs. A failed build ends the stream
+/// with an `error` event instead, clients have to look for it. With `stream=false` the
+/// steps are answered as one array when the build is done, a failed build with the status
+/// of its error, the message and the steps up to it.
+pub async fn build(mut req: Request<State>) -> Result {
+    let is_tar = req.content_type().is_some_and(|ct| {
+        ct.essence() == "application/x-tar" || ct.essence() == "application/tar"
+    });
+    if !is_tar {
+        return Err(tide::Error::from_str(
+            StatusCode::UnsupportedMediaType,
+            "the build context must be an application/x-tar body",
+        ));
+    }
+    let query = req.query::<BuildQuery>()?;
+    let streamed = query.stream.unwrap_or(true);
+    let options: BuildOptions = query.into();
+    let context = req.take_body();
+    let mut response =
+        send(&req, |docker| docker.images().build_from(&options, context)).await?;
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+
+    let steps = stream::lines(response.take_body()).map(|line| {
+        line.and_then(|line| {
+            BuildStep::parse(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    });
+    if !streamed {
+        let steps: Vec<BuildStep> = steps.try_collect().await?;
+        return Ok(match steps.iter().rev().find(|step| step.is_error()) {
+            Some(failed) => {
+                log::warn!("build failed: {}", failed.error.as_deref().unwrap_or_default());
+                Response::builder(failed.status())
+                    .body(json!({ "error": failed.error, "steps": steps }))
+                    .build()
+            }
+            None => Response::builder(200).body(Body::from_json(&steps)?).build(),
+        });
+    }
+
+    let sse = wants_sse(&req);
+    let chunks = steps.map(move |step| {
+        let step = step?;
+        if let Some(error) = &step.error {
+            log::warn!("build failed: {}", error);
+        }
+        let name = if step.is_error() { "error" } else { "step" };
+        Ok(event_chunk(&step, name, sse))
+    });
+    Ok(event_response(chunks, sse))
+}
+
+/// The image and its history as a tarball for `docker load`, streamed through
+pub async fn export(req: Request<State>) -> Result {
+    let name = req.param("name")?;
+    proxy_tar(&req, |docker| docker.images().get(name).export()).await
+}
+
+/// The layers of an image as the decoded `Vec<History>`, newest first
+pub async fn history(req: Request<State>) -> Result {
+    let name = req.param("name")?;
+    proxy_json::<Vec<History>, _>(&req, |docker| docker.images().get(name).history()).await
+}
+
+pub async fn layers(req: Request<State>) -> Result {
+    let name = req.param("name")?;
+    let docker = daemon(&req)?;
+    let image = docker.images().get(name);
+
+    let mut inspect = req.state().send(request_id(&req), image.inspect()?).await?;
+    if !inspect.status().is_success() {
+        return forward(inspect).await;
+    }
+    let mut history = req.state().send(request_id(&req), image.history()?).await?;
+    if !history.status().is_success() {
+        return forward(history).await;
+    }
+
+    let details: ImageDetails = inspect.body_json().await?;
+    let history: Vec<History> = history.body_json().await?;
+    let layers = ImageLayers::new(&details, &history);
+    Ok(tide::Response::builder(200)
+        .body(Body::from_json(&layers)?)
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use url::form_urlencoded;
+
+    /// Test a batch pull never running more pulls at a time than its limit, and a
+    /// failing pull being reported next to the others
+    #[async_std::test]
+    async fn pull_batch_concurrency_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        let entries: Vec<PullBatchEntry> = serde_json::from_value(json!([
+            { "image": "alpine", "tag": "3.13" },
+            { "image": "nginx" },
+            { "image": "redis", "tag": "6" },
+            { "image": "nope" },
+            { "image": "postgres" },
+            { "image": "nginx" },
+        ]))
+        .unwrap();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (counting, highest) = (in_flight.clone(), most.clone());
+        let chunks: Vec<Vec<u8>> = pull_all(entries, 2, false, move |entry| {
+            let (in_flight, most) = (counting.clone(), highest.clone());
+            let image = entry.image.clone();
+            futures::stream::once(async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                async_std::task::sleep(Duration::from_millis(20)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                if image == "nope" {
+                    pull_failed("manifest for nope:latest not found".to_owned())
+                } else {
+                    PullEvent::parse(r#"{"status":"Download complete","id":"a3ed95caeb02"}"#)
+                        .unwrap()
+                }
+            })
+        })
+        .try_collect()
+        .await
+        .unwrap();
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+
+        let lines: Vec<Value> = chunks
+            .iter()
+            .map(|chunk| serde_json::from_slice(chunk).unwrap())
+            .collect();
+        // one event per distinct image and the summary
+        assert_eq!(lines.len(), 6);
+        assert!(lines[..5].iter().all(|line| line["image"].is_string()));
+        assert_eq!(
+            lines[5],
+            json!({
+                "alpine:3.13": { "ok": true },
+                "nginx": { "ok": true },
+                "nope": { "error": "manifest for nope:latest not found" },
+                "postgres": { "ok": true },
+                "redis:6": { "ok": true },
+            })
+        );
+    }
+
+    /// Test repeated labels combined with the dangling filter, and contradicting filters
+    #[test]
+    fn image_list_dangling_and_labels() {
+        let options = list_options(vec![
+            ("dangling", "true"),
+            ("label", "maintainer"),
+            ("label", "env=prod"),
+            ("all", "1"),
+        ])
+        .unwrap();
+        let serialized = options.serialize().unwrap();
+        let params: HashMap<String, String> =
+            form_urlencoded::parse(serialized.as_bytes()).into_owned().collect();
+        assert_eq!(params["all"], "true");
+        let filters: Value = serde_json::from_str(&params["filters"]).unwrap();
+        assert_eq!(filters["dangling"], json!(["true"]));
+        assert_eq!(filters["label"], json!(["maintainer", "env=prod"]));
+
+        assert!(list_options(vec![("dangling", "true"), ("dangling", "false")]).is_err());
+        assert!(list_options(vec![("dangling", "true"), ("filter", "alpine")]).is_err());
+        assert!(list_options(vec![("dangling", "maybe")]).is_err());
+    }
+
+    /// Test the tag query, and a missing repo being rejected before reaching the daemon
+    #[async_std::test]
+    async fn image_tag_options() {
+        let query = TagQuery {
+            repo: Some("registry.local/chia".to_owned()),
+            tag: Some("1.2.0".to_owned()),
+        };
+        let serialized = query.options().unwrap().serialize().unwrap();
+        let params: HashMap<String, String> =
+            form_urlencoded::parse(serialized.as_bytes()).into_owned().collect();
+        assert_eq!(params["repo"], "registry.local/chia");
+        assert_eq!(params["tag"], "1.2.0");
+
+        let query = TagQuery {
+            repo: Some(String::new()),
+            tag: Some("1.2.0".to_owned()),
+        };
+        assert!(query.options().is_err());
+
+        let mut app = tide::with_state(State::for_test());
+        app.at("/images/:name/tag").post(tag);
+        let mut request = http_types::Request::post("http://localhost/images/alpine/tag");
+        request.set_body(Body::from_json(&json!({ "tag": "latest" })).unwrap());
+        let response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 400);
+    }
+
+    /// Test the forwarded search query with a limit and official/stars filters
+    #[test]
+    fn image_search_query() {
+        let query = ImageSearchQuery {
+            term: "chia".to_owned(),
+            limit: Some(10),
+            filters: Some(r#"{"is-official":["true"],"stars":["25"]}"#.to_owned()),
+        };
+        assert_eq!(
+            query.options().unwrap().serialize(),
+            "filters=%7B%22is-official%22%3A%5B%22true%22%5D%2C%22stars%22%3A%5B%2225%22%5D%7D\
+             &limit=10&term=chia"
+        );
+
+        let query = ImageSearchQuery {
+            term: "chia".to_owned(),
+            limit: None,
+            filters: Some(r#"{"stars":["many"]}"#.to_owned()),
+        };
+        assert!(query.options().is_err());
+        let query = ImageSearchQuery {
+            term: "chia".to_owned(),
+            limit: None,
+            filters: Some(r#"{"label":["x"]}"#.to_owned()),
+        };
+        assert!(query.options().is_err());
+    }
+
+    /// Test the compression of an upload arriving a byte at a time being told all the same,
+    /// and the whole tarball being relayed
+    #[async_std::test]
+    async fn load_tarball_encoding_trickled() {
+        let xz = vec![0xfd, b'7', b'z', b'X', b'Z', 0x00, 0x00, 0x04, 0xe6];
+        let chunks: Vec<io::Result<Vec<u8>>> = xz.iter().map(|byte| Ok(vec![*byte])).collect();
+        let reader = futures::io::BufReader::new(futures::stream::iter(chunks).into_async_read());
+        let (encoding, body) = tarball_encoding(Body::from_reader(reader, None)).await.unwrap();
+        assert_eq!(encoding, Some(TarballEncoding::Xz));
+        assert_eq!(body.into_bytes().await.unwrap(), xz);
+
+        let (encoding, body) = tarball_encoding(Body::from(vec![0x1f])).await.unwrap();
+        assert_eq!(encoding, None);
+        assert_eq!(body.into_bytes().await.unwrap(), vec![0x1f]);
+    }
+
+    /// Test a build with `stream=false` answering its steps at once, and the status of the
+    /// error a failed build ended with
+    #[async_std::test]
+    async fn build_buffered() {
+        use crate::service::mock;
+        use http_types::Method;
+
+        let daemon = mock::daemon(|method, target| {
+            assert_eq!(method, "POST");
+            let log = if target.contains("t=broken") {
+                concat!(
+                    r#"{"stream":"Step 1/2 : FROM alpine"}"#, "\n",
+                    r#"{"stream":"Step 2/2 : RUN exit 3"}"#, "\n",
+                    r#"{"errorDetail":{"code":3,"message":"returned a non-zero code: 3"},"#,
+                    r#""error":"returned a non-zero code: 3"}"#, "\n",
+                )
+            } else {
+                concat!(
+                    r#"{"stream":"Step 1/1 : FROM alpine"}"#, "\n",
+                    r#"{"aux":{"ID":"sha256:6dbb9cc54074"}}"#, "\n",
+                )
+            };
+            (200, log.to_owned())
+        })
+        .await;
+        let mut app = tide::with_state(State::for_test());
+        app.at("/images/build").post(build);
+
+        let mut request = mock::request(Method::Post, "/images/build?t=web&stream=false", &daemon);
+        request.set_body(Body::from_bytes(vec![0; 1024]));
+        request.set_content_type("application/x-tar".into());
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let steps: Vec<BuildStep> = response.body_json().await.unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].aux, Some(json!({ "ID": "sha256:6dbb9cc54074" })));
+
+        let path = "/images/build?t=broken&stream=false";
+        let mut request = mock::request(Method::Post, path, &daemon);
+        request.set_body(Body::from_bytes(vec![0; 1024]));
+        request.set_content_type("application/x-tar".into());
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 422);
+        let body: Value = response.body_json().await.unwrap();
+        assert_eq!(body["error"], "returned a non-zero code: 3");
+        assert_eq!(body["steps"].as_array().unwrap().len(), 3);
+    }
+}
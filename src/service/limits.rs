@@ -0,0 +1,154 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::docker::{container::Resources, docker::Info};
+
+/// how long the `/info` of a daemon is reused before asking again
+const INFO_TTL: Duration = Duration::from_secs(300);
+
+/// cpu shares the daemon accepts besides 0, which means the default weight
+const MIN_CPU_SHARES: i64 = 2;
+const MAX_CPU_SHARES: i64 = 262_144;
+
+/// Capacity of a docker host, from its `/info`. A capacity the daemon did not report is
+/// not checked.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HostLimits {
+    pub mem_total: Option<u64>,
+    pub n_cpu: Option<u64>,
+    pub runtimes: Vec<String>,
+}
+
+impl From<&Info> for HostLimits {
+    fn from(info: &Info) -> Self {
+        let mut runtimes: Vec<String> = info.runtimes.keys().cloned().collect();
+        runtimes.sort();
+        HostLimits {
+            mem_total: info.mem_total,
+            n_cpu: info.n_cpu,
+            runtimes,
+        }
+    }
+}
+
+impl HostLimits {
+    /// reason the requested resources can never be satisfied by this host, if any
+    pub fn check(&self, resources: &Resources) -> Result<(), String> {
+        if let (Some(memory), Some(mem_total)) = (resources.memory, self.mem_total) {
+            if memory > mem_total {
+                return Err(format!(
+                    "memory {} exceeds the host total of {}",
+                    memory, mem_total
+                ));
+            }
+        }
+        if let Some(shares) = resources.cpu_shares {
+            if shares != 0 && !(MIN_CPU_SHARES..=MAX_CPU_SHARES).contains(&shares) {
+                return Err(format!(
+                    "cpu shares {} outside of {}..={}",
+                    shares, MIN_CPU_SHARES, MAX_CPU_SHARES
+                ));
+            }
+        }
+        if let (Some(nano_cpus), Some(n_cpu)) = (resources.nano_cpus, self.n_cpu) {
+            if nano_cpus > n_cpu * 1_000_000_000 {
+                return Err(format!(
+                    "{} nano cpus exceed the {} cpus of the host",
+                    nano_cpus, n_cpu
+                ));
+            }
+        }
+        if let Some(runtime) = &resources.runtime {
+            // older daemons do not report their runtimes
+            if !self.runtimes.is_empty() && !self.runtimes.contains(runtime) {
+                return Err(format!(
+                    "runtime {} is not available, the host has {}",
+                    runtime,
+                    self.runtimes.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `/info` limits per daemon url, refreshed after `INFO_TTL`
+#[derive(Debug, Default)]
+pub struct HostLimitsCache {
+    hosts: Mutex<HashMap<String, (Instant, HostLimits)>>,
+}
+
+impl HostLimitsCache {
+    pub fn get(&self, host: &str) -> Option<HostLimits> {
+        let hosts = self.hosts.lock().unwrap();
+        hosts
+            .get(host)
+            .filter(|(fetched, _)| fetched.elapsed() < INFO_TTL)
+            .map(|(_, limits)| limits.clone())
+    }
+
+    pub fn insert(&self, host: &str, limits: HostLimits) {
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts.insert(host.to_owned(), (Instant::now(), limits));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docker::container::ContainerOptions;
+
+    #[test]
+    fn reject_memory_over_host_total() {
+        let host = HostLimits {
+            mem_total: Some(2 * 1024 * 1024 * 1024),
+            n_cpu: Some(2),
+            runtimes: vec![],
+        };
+        let options = ContainerOptions::builder("alpine")
+            .memory(4 * 1024 * 1024 * 1024)
+            .build();
+        assert!(host.check(&options.resources()).is_err());
+
+        let options = ContainerOptions::builder("alpine")
+            .memory(512 * 1024 * 1024)
+            .cpu_shares(1024)
+            .build();
+        assert!(host.check(&options.resources()).is_ok());
+
+        let options = ContainerOptions::builder("alpine").cpu_shares(1).build();
+        assert!(host.check(&options.resources()).is_err());
+        let options = ContainerOptions::builder("alpine").cpus(4.0).build();
+        assert!(host.check(&options.resources()).is_err());
+    }
+
+    /// Test a host that did not report its capacity accepting any memory and cpus
+    #[test]
+    fn accept_unreported_capacity() {
+        let info: Info = serde_json::from_value(serde_json::json!({})).unwrap();
+        let host = HostLimits::from(&info);
+        let options = ContainerOptions::builder("alpine")
+            .memory(4 * 1024 * 1024 * 1024)
+            .cpus(4.0)
+            .build();
+        assert!(host.check(&options.resources()).is_ok());
+    }
+
+    #[test]
+    fn reject_unavailable_runtime() {
+        let host = HostLimits {
+            mem_total: Some(2 * 1024 * 1024 * 1024),
+            n_cpu: Some(2),
+            runtimes: vec!["runc".to_owned()],
+        };
+        let options = ContainerOptions::builder("alpine").runtime("runc").build();
+        assert!(host.check(&options.resources()).is_ok());
+        let options = ContainerOptions::builder("alpine").runtime("nvidia").build();
+        assert!(host.check(&options.resources()).is_err());
+    }
+}
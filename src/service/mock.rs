@@ -0,0 +1,92 @@
+//! A daemon answering every request from a closure, for handler tests.
+
+use std::sync::Arc;
+
+use async_std::{
+    io::{prelude::*, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use http_types::Method;
+use url::Url;
+
+/// Serve `respond(method, path and query)` as `(status, json body)` on a local port,
+/// returns the url of the daemon. Keep-alive connections are served until closed.
+pub async fn daemon<F>(respond: F) -> Url
+where
+    F: Fn(&str, &str) -> (u16, String) + Send + Sync + 'static,
+{
+    daemon_with_body(move |method, target, _| respond(method, target)).await
+}
+
+/// [`daemon`] for tests of what is sent, `respond` also gets the request body. Only bodies
+/// with a `Content-Length` are read.
+pub async fn daemon_with_body<F>(respond: F) -> Url
+where
+    F: Fn(&str, &str, &[u8]) -> (u16, String) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+    let respond = Arc::new(respond);
+    async_std::task::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let respond = respond.clone();
+            async_std::task::spawn(async move {
+                let _ = serve(stream, respond.as_ref()).await;
+            });
+        }
+    });
+    url
+}
+
+/// A request to `path` of the proxy, routed to `daemon` the way `docker_id` resolves it
+pub fn request(method: Method, path: &str, daemon: &Url) -> http_types::Request {
+    let url = format!("http://localhost{}", path);
+    let mut request = http_types::Request::new(method, url.as_str());
+    request.ext_mut().insert(daemon.clone());
+    request
+}
+
+async fn serve<F>(stream: TcpStream, respond: &F) -> std::io::Result<()>
+where
+    F: Fn(&str, &str, &[u8]) -> (u16, String),
+{
+    let mut reader = BufReader::new(stream.clone());
+    let mut writer = stream;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_owned();
+        let target = parts.next().unwrap_or_default().to_owned();
+
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).await? == 0 {
+                return Ok(());
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await?;
+
+        let (status, body) = respond(&method, &target, &body);
+        let response = format!(
+            "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        writer.write_all(response.as_bytes()).await?;
+    }
+}
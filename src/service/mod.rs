@@ -1,81 +1,489 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
 use url::Url;
 
 use crate::{
-    docker::docker::{Docker, EventsOptions},
+    daemons::ApiVersion,
+    docker::{
+        docker::{AuthResponse, Docker, EventsOptions, Info, PingInfo},
+        image::RegistryAuth,
+        stream, tar,
+    },
+    errors::Error,
+    request_id::request_id,
     State,
 };
-use tide::{Request, Response, Result, StatusCode};
+use chrono::{Local, NaiveDateTime};
+use futures::TryStreamExt;
+use tide::{
+    http::{headers, mime},
+    Body, Request, Response, Result, StatusCode,
+};
 
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
 
 pub mod container;
+pub mod image;
+pub mod limits;
+#[cfg(test)]
+pub(crate) mod mock;
+pub mod network;
+pub mod proxy;
+pub mod swarm;
+
+/// the most plots a single completion may report
+const MAX_PLOT_COUNT: i32 = 100_000;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PlotCount {
     count: i32,
 }
 
-pub async fn plot_complete(mut req: Request<State>) -> Result {
-    let ip = if let Some(remote) = req.remote() {
-        if let Ok(addr) = remote.parse::<SocketAddr>() {
-            addr.ip().to_string()
-        } else {
-            "unknown".to_owned()
+impl PlotCount {
+    fn in_range(&self) -> bool {
+        (0..=MAX_PLOT_COUNT).contains(&self.count)
+    }
+}
+
+/// What `plot_complete` stored, echoed back to the client
+#[derive(Serialize, Debug, Clone)]
+pub struct PlotRecord {
+    data_id: String,
+    from_ip: String,
+    plot_count: i32,
+    create_time: NaiveDateTime,
+}
+
+impl PlotRecord {
+    fn new(from_ip: String, plot_count: i32) -> Self {
+        PlotRecord {
+            data_id: Uuid::new_v4().to_string(),
+            from_ip,
+            plot_count,
+            create_time: Local::now().naive_local(),
         }
+    }
+
+    fn response(&self) -> Result {
+        Ok(Response::builder(200).body(Body::from_json(self)?).build())
+    }
+}
+
+/// ip of the request source: the peer address, unless `TRUST_PROXY` says the peer is a
+/// reverse proxy. The client is then the address the proxy appended last to
+/// `X-Forwarded-For`, or its `X-Real-IP`. Without a trusted proxy those headers come from
+/// the client itself and are ignored.
+pub(crate) fn source_ip(req: &Request<State>) -> String {
+    let forwarded = if req.state().trust_proxy {
+        req.header("X-Forwarded-For")
+            .and_then(|values| values.last().as_str().rsplit(',').next())
+            .or_else(|| req.header("X-Real-IP").map(|values| values.last().as_str()))
     } else {
-        "unknown".to_owned()
+        None
     };
+    forwarded
+        .into_iter()
+        .chain(req.peer_addr())
+        .find_map(parse_ip)
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// the ip of an address that may come with or without a port
+fn parse_ip(addr: &str) -> Option<String> {
+    let addr = addr.trim();
+    addr.parse::<SocketAddr>()
+        .map(|addr| addr.ip())
+        .or_else(|_| addr.parse::<IpAddr>())
+        .ok()
+        .map(|ip| ip.to_string())
+}
+
+pub async fn plot_complete(mut req: Request<State>) -> Result {
+    let ip = source_ip(&req);
+    if !req.state().plot_limiter.check(&ip) {
+        log::warn!("plot complete from {} throttled", ip);
+        return Ok(Response::new(StatusCode::TooManyRequests));
+    }
 
     let data = req.body_json::<PlotCount>().await?;
+    if !data.in_range() {
+        log::warn!("plot count {} from {} rejected", data.count, ip);
+        return Err(tide::Error::from_str(
+            StatusCode::BadRequest,
+            format!("plot count must be within 0..={}", MAX_PLOT_COUNT),
+        ));
+    }
 
     log::info!("get request from {}, body {:?}", ip, data);
 
-    let insert = "insert into plot_complete_info (data_id, from_ip, plot_count, create_time) values (uuid(), ?, ?, sysdate())";
+    let insert = "insert into plot_complete_info (data_id, from_ip, plot_count, create_time) values (?, ?, ?, ?)";
     let state = req.state();
+    let record = PlotRecord::new(ip, data.count);
 
     let ret = sqlx::query(insert)
-        .bind(&ip)
-        .bind(&data.count)
+        .bind(&record.data_id)
+        .bind(&record.from_ip)
+        .bind(record.plot_count)
+        .bind(record.create_time)
         .execute(&state.db)
         .await?;
-    log::info!("plot complete from {}, {:?}", &ip, ret);
+    log::info!("plot complete from {}, {:?}", &record.from_ip, ret);
+
+    record.response()
+}
+
+/// Whether the client asked for server sent events
+pub(crate) fn wants_sse(req: &Request<State>) -> bool {
+    req.header(headers::ACCEPT)
+        .map(|accept| accept.as_str().contains("text/event-stream"))
+        .unwrap_or(false)
+}
+
+/// Relay a daemon response. Non-2xx responses are wrapped into a json envelope
+/// `{error, docker_status, docker_body}` keeping the daemon status code.
+pub async fn forward(mut response: http_types::Response) -> Result {
+    if response.status().is_success() {
+        return Ok(Response::from_res(response));
+    }
+
+    let status = response.status();
+    let body = response.body_string().await.unwrap_or_default();
+    log::warn!("docker responded {}: {}", status, body);
+
+    let docker_body = serde_json::from_str::<Value>(&body).unwrap_or(Value::String(body));
+    let error = docker_body
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or_else(|| status.canonical_reason())
+        .to_owned();
+    let envelope = json!({
+        "error": error,
+        "docker_status": status as u16,
+        "docker_body": docker_body,
+    });
+    Ok(Response::builder(status).body(envelope).build())
+}
+
+/// Relay a stream of json lines (events, stats) closed after the configured maximum
+/// duration with a final `{"status":"stream-ended"}` line.
+pub async fn forward_stream(state: &State, mut response: http_types::Response) -> Result {
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+    let content_type = response.content_type().unwrap_or(mime::JSON);
+    let terminal = format!("{}\n", json!({ "status": "stream-ended" })).into_bytes();
+    let chunks = stream::bounded(
+        stream::line_chunks(response.take_body()),
+        state.max_stream,
+        terminal,
+    );
+    let reader = stream::SyncStream::new(chunks).into_async_read();
+    Ok(Response::builder(response.status())
+        .content_type(content_type)
+        .body(Body::from_reader(reader, None))
+        .build())
+}
+
+/// Resolve the daemon of the request, send the daemon request `build` makes for it and
+/// relay the response through [`forward`]. This is all most handlers do.
+pub async fn proxy<F>(req: &Request<State>, build: F) -> Result
+where
+    F: FnOnce(&Docker) -> std::result::Result<http_types::Request, http_types::Error>,
+{
+    let response = send(req, build).await?;
+    forward(response).await
+}
+
+/// [`proxy`] for endpoints answering json, decoded into `T` and encoded again so clients
+/// get the shape this crate knows from every daemon version
+pub async fn proxy_json<T, F>(req: &Request<State>, build: F) -> Result
+where
+    T: DeserializeOwned + Serialize,
+    F: FnOnce(&Docker) -> std::result::Result<http_types::Request, http_types::Error>,
+{
+    let mut response = send(req, build).await?;
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+    let value: T = decode_json(req, &mut response).await?;
+    Ok(Response::builder(200).body(Body::from_json(&value)?).build())
+}
 
-    let response = Response::new(200);
+/// The body of a successful daemon answer as `T`. A body that is not a `T` is the fault of
+/// the daemon and answered with a 502.
+pub(crate) async fn decode_json<T: DeserializeOwned>(
+    req: &Request<State>,
+    response: &mut http_types::Response,
+) -> Result<T> {
+    response.body_json().await.map_err(|e| {
+        log::warn!("answer to {} {} not decoded: {}", req.method(), req.url().path(), e);
+        tide::Error::from_str(StatusCode::BadGateway, e.to_string())
+    })
+}
+
+/// [`proxy`] for endpoints streaming json lines, relayed through [`forward_stream`]
+pub async fn proxy_stream<F>(req: &Request<State>, build: F) -> Result
+where
+    F: FnOnce(&Docker) -> std::result::Result<http_types::Request, http_types::Error>,
+{
+    let response = send(req, build).await?;
+    forward_stream(req.state(), response).await
+}
+
+/// [`proxy`] for endpoints answering a tarball: the body is relayed as the daemon sends
+/// it and never held in memory, whatever the size of the filesystem exported.
+pub async fn proxy_tar<F>(req: &Request<State>, build: F) -> Result
+where
+    F: FnOnce(&Docker) -> std::result::Result<http_types::Request, http_types::Error>,
+{
+    let mut response = send(req, build).await?;
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+    let content_type = response.content_type().unwrap_or_else(tar);
+    let body = Body::from_reader(response.take_body(), None);
+    Ok(Response::builder(200).body(body).content_type(content_type).build())
+}
 
-    Ok(response)
+/// Resolve the daemon of the request and send the daemon request `build` makes for it
+pub(crate) async fn send<F>(req: &Request<State>, build: F) -> Result<http_types::Response>
+where
+    F: FnOnce(&Docker) -> std::result::Result<http_types::Request, http_types::Error>,
+{
+    let docker = daemon(req)?;
+    let request = build(&docker)?;
+    req.state().send(request_id(req), request).await
 }
 
-pub fn docker_not_found_error() -> tide::Error {
-    tide::Error::from_str(StatusCode::InternalServerError, "docker not found.")
+/// The daemon of the request, its paths prefixed with the api version negotiated when the
+/// daemon was resolved
+pub(crate) fn daemon(req: &Request<State>) -> Result<Docker> {
+    let url = req.ext::<Url>().ok_or(Error::DaemonNotFound)?;
+    let docker = Docker::with_endpoint(url.clone())?;
+    Ok(match req.ext::<ApiVersion>() {
+        Some(ApiVersion(version)) => docker.with_api_version(version),
+        None => docker,
+    })
 }
 
+/// The daemon `/info`, decoded into `Info` so callers get the same shape from every daemon
 pub async fn docker_info(req: Request<State>) -> Result {
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req.state().send(docker.info()?).await?;
-    Ok(tide::Response::from_res(response))
+    proxy_json::<Info, _>(&req, |docker| docker.info()).await
+}
+
+/// Check registry credentials, answering the daemon's `{Status, IdentityToken}`. Rejected
+/// credentials are a 401 with the reason of the registry.
+pub async fn docker_login(mut req: Request<State>) -> Result {
+    let auth: RegistryAuth = req.body_json().await?;
+    let mut response = send(&req, |docker| docker.auth(&auth)).await?;
+    if response.status() == StatusCode::Unauthorized {
+        let body = response.body_string().await.unwrap_or_default();
+        let reason = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|body| body.get("message").and_then(Value::as_str).map(str::to_owned))
+            .unwrap_or(body);
+        log::warn!("registry login rejected: {}", reason);
+        return Ok(Response::builder(StatusCode::Unauthorized)
+            .body(json!({ "error": "registry login failed", "reason": reason }))
+            .build());
+    }
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+    let login: AuthResponse = decode_json(&req, &mut response).await?;
+    Ok(Response::builder(200).body(Body::from_json(&login)?).build())
 }
 
 pub async fn docker_ping(req: Request<State>) -> Result {
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req.state().send(docker.ping()?).await?;
-    Ok(tide::Response::from_res(response))
+    proxy(&req, |docker| docker.ping()).await
 }
 
+/// The api version, experimental flag and os type of the daemon from its ping headers
+pub async fn docker_ping_info(req: Request<State>) -> Result {
+    let response = send(&req, |docker| docker.ping()).await?;
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+    let info = PingInfo::from_headers(&response);
+    Ok(Response::builder(200).body(Body::from_json(&info)?).build())
+}
+
+/// Relay the daemon events, as `Event`s named after their type when asked for server sent
+/// events and untouched otherwise
 pub async fn docker_events(req: Request<State>) -> Result {
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
     let options = EventsOptions::builder().build();
-    let response = req.state().send(docker.events(&options)?).await?;
-    Ok(tide::Response::from_res(response))
+    if !wants_sse(&req) {
+        return proxy_stream(&req, |docker| docker.events(&options)).await;
+    }
+    let mut response = send(&req, |docker| docker.events(&options)).await?;
+    if !response.status().is_success() {
+        return forward(response).await;
+    }
+    let events = stream::lenient_events(response.take_body()).map_ok(|event| {
+        let data = serde_json::to_string(&event).unwrap_or_default();
+        format!("event: {}\ndata: {}\n\n", event.typ, data).into_bytes()
+    });
+    let terminal = b"event: stream-ended\ndata: \n\n".to_vec();
+    let chunks = stream::bounded(events, req.state().max_stream, terminal);
+    let reader = stream::SyncStream::new(chunks).into_async_read();
+    Ok(Response::builder(200)
+        .content_type(mime::SSE)
+        .header(headers::CACHE_CONTROL, "no-cache")
+        .body(Body::from_reader(reader, None))
+        .build())
+}
+
+/// The per daemon request pools for Prometheus
+pub async fn pool_metrics(req: Request<State>) -> Result {
+    Ok(Response::builder(200)
+        .content_type("text/plain; version=0.0.4")
+        .body(req.state().pools.metrics())
+        .build())
 }
 
 pub async fn docker_version(req: Request<State>) -> Result {
-    let url = req.ext::<Url>().ok_or(docker_not_found_error())?;
-    let docker = Docker::host(url.clone());
-    let response = req.state().send(docker.version()?).await?;
-    Ok(tide::Response::from_res(response))
+    proxy(&req, |docker| docker.version()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_daemon_error() {
+        let mut daemon = http_types::Response::new(409);
+        daemon.set_body(json!({
+            "message": "Conflict. The container name \"/web\" is already in use"
+        }));
+
+        let mut response = futures::executor::block_on(forward(daemon)).unwrap();
+        assert_eq!(response.status(), 409);
+        let body: Value =
+            futures::executor::block_on(response.take_body().into_json()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "error": "Conflict. The container name \"/web\" is already in use",
+                "docker_status": 409,
+                "docker_body": {
+                    "message": "Conflict. The container name \"/web\" is already in use"
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn plot_record_response() {
+        let record = PlotRecord::new("10.0.0.1".to_owned(), 7);
+        let mut response = record.response().unwrap();
+        assert_eq!(response.status(), 200);
+        let body: Value =
+            futures::executor::block_on(response.take_body().into_json()).unwrap();
+        assert_eq!(body["data_id"], json!(record.data_id));
+        assert!(Uuid::parse_str(body["data_id"].as_str().unwrap()).is_ok());
+        assert_eq!(body["from_ip"], json!("10.0.0.1"));
+        assert_eq!(body["plot_count"], json!(7));
+        assert!(body["create_time"].is_string());
+    }
+
+    /// Test counts out of range being refused before anything is stored
+    #[async_std::test]
+    async fn plot_count_range() {
+        for count in &[0, 7, MAX_PLOT_COUNT] {
+            assert!(PlotCount { count: *count }.in_range(), "{}", count);
+        }
+
+        let mut app = tide::with_state(State::for_test());
+        app.at("/chia/plots").post(plot_complete);
+        for count in &[-1, MAX_PLOT_COUNT + 1, i32::MAX] {
+            let mut request = http_types::Request::post("http://localhost/chia/plots");
+            request.set_body(json!({ "count": count }));
+            let response: http_types::Response = app.respond(request).await.unwrap();
+            assert_eq!(response.status(), 400, "{}", count);
+        }
+    }
+
+    /// Test the source ip of direct requests, and of requests through a reverse proxy
+    /// whose forwarding headers only count when the proxy is trusted
+    #[async_std::test]
+    async fn source_ip_direct_and_forwarded() {
+        let ip = |state: State, headers: Vec<(&'static str, &'static str)>| async move {
+            let mut app = tide::with_state(state);
+            app.at("/ip").get(|req: Request<State>| async move { Ok(source_ip(&req)) });
+            let mut request = http_types::Request::get("http://localhost/ip");
+            request.set_peer_addr(Some("10.0.0.9:51234"));
+            for (name, value) in headers {
+                request.insert_header(name, value);
+            }
+            let mut response: http_types::Response = app.respond(request).await.unwrap();
+            response.body_string().await.unwrap()
+        };
+        let trusted = || State {
+            trust_proxy: true,
+            ..State::for_test()
+        };
+        let forwarded = || vec![("X-Forwarded-For", "6.6.6.6, 203.0.113.7")];
+
+        assert_eq!(ip(State::for_test(), vec![]).await, "10.0.0.9");
+        assert_eq!(ip(trusted(), vec![]).await, "10.0.0.9");
+        assert_eq!(ip(trusted(), forwarded()).await, "203.0.113.7");
+        assert_eq!(ip(trusted(), vec![("X-Real-IP", "203.0.113.8")]).await, "203.0.113.8");
+        // a client naming itself is not believed without a proxy in front
+        assert_eq!(ip(State::for_test(), forwarded()).await, "10.0.0.9");
+        assert_eq!(ip(trusted(), vec![("X-Forwarded-For", "nonsense")]).await, "10.0.0.9");
+    }
+
+    /// Test the proxy helper against a daemon answering a single canned response
+    #[async_std::test]
+    async fn proxy_mock_daemon() {
+        use async_std::{io::prelude::*, net::TcpListener};
+
+        let body = r#"{"Version":"20.10.7","ApiVersion":"1.41"}"#;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let daemon = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        async_std::task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let _ = stream.read(&mut request).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let mut app = tide::with_state(State::for_test());
+        app.at("/version")
+            .get(|req: Request<State>| async move { proxy(&req, |docker| docker.version()).await });
+
+        let mut request = http_types::Request::get("http://localhost/version");
+        request.ext_mut().insert(daemon);
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body_string().await.unwrap(), body);
+
+        // without a resolved daemon the helper never sends anything
+        let request = http_types::Request::get("http://localhost/version");
+        let response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    /// Test requests to a daemon going to the api version negotiated when it was resolved
+    #[async_std::test]
+    async fn negotiated_api_version() {
+        let mut app = tide::with_state(State::for_test());
+        app.at("/version").get(|req: Request<State>| async move {
+            let request = daemon(&req)?.version()?;
+            Ok(request.url().path().to_owned())
+        });
+
+        let mut request = http_types::Request::get("http://localhost/version");
+        request.ext_mut().insert(Url::parse("http://127.0.0.1:2375").unwrap());
+        request.ext_mut().insert(ApiVersion("1.41".to_owned()));
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(response.body_string().await.unwrap(), "/v1.41/version");
+    }
 }
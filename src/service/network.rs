@@ -0,0 +1,70 @@
+use std::net::Ipv4Addr;
+
+use crate::docker::network::{NetworkConnectOptions, NetworkDetails, NetworkInspectOptions};
+use crate::service::{proxy, proxy_json};
+use crate::State;
+
+use serde::Deserialize;
+use tide::{Request, Result, StatusCode};
+
+#[derive(Deserialize)]
+pub struct NetworkInspectQuery {
+    pub verbose: Option<bool>,
+    pub scope: Option<String>,
+}
+
+impl From<NetworkInspectQuery> for NetworkInspectOptions {
+    fn from(query: NetworkInspectQuery) -> NetworkInspectOptions {
+        let mut builder = NetworkInspectOptions::builder();
+        if let Some(verbose) = query.verbose {
+            builder.verbose(verbose);
+        }
+        if let Some(scope) = &query.scope {
+            builder.scope(scope);
+        }
+        builder.build()
+    }
+}
+
+/// Container to connect to a network, optionally reachable by `aliases` and at a
+/// static `ipv4` address
+#[derive(Deserialize)]
+pub struct NetworkConnectBody {
+    pub container: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub ipv4: Option<String>,
+}
+
+/// The network decoded into `NetworkDetails`, with the services of a swarm network when
+/// `verbose` is set
+pub async fn inspect(req: Request<State>) -> Result {
+    let id = req.param("id")?;
+    let options: NetworkInspectOptions = req.query::<NetworkInspectQuery>()?.into();
+    proxy_json::<NetworkDetails, _>(&req, |docker| {
+        docker.networks().get(id).inspect_with(&options)
+    })
+    .await
+}
+
+/// Connect a container to the network, with its aliases and static address if given
+pub async fn connect(mut req: Request<State>) -> Result {
+    let body: NetworkConnectBody = req.body_json().await?;
+    let mut builder = NetworkConnectOptions::builder();
+    builder.container(&body.container);
+    if !body.aliases.is_empty() {
+        builder.aliases(&body.aliases);
+    }
+    if let Some(ipv4) = body.ipv4.as_deref() {
+        if ipv4.parse::<Ipv4Addr>().is_err() {
+            return Err(tide::Error::from_str(
+                StatusCode::BadRequest,
+                format!("{:?} is not an ipv4 address", ipv4),
+            ));
+        }
+        builder.ipv4(ipv4);
+    }
+    let options = builder.build();
+    let id = req.param("id")?;
+    proxy(&req, |docker| docker.networks().get(id).connect(&options)).await
+}
@@ -0,0 +1,159 @@
+use std::env;
+
+use regex::Regex;
+
+use crate::service::{daemon, forward};
+use crate::request_id::request_id;
+use crate::State;
+
+use tide::{
+    http::{mime, Method},
+    Request, Response, Result, StatusCode,
+};
+
+/// Daemon api paths reachable through the raw proxy unless `DOCKER_PROXY_ALLOW` overrides it.
+/// Swarm, secrets, configs, plugins and auth are left out on purpose.
+const DEFAULT_ALLOW: &str =
+    r"^/(containers|images|networks|volumes|exec|info|version|_ping|events|system/df)(/|$)";
+
+/// Regex allowlist of the daemon api paths the raw proxy may reach
+#[derive(Debug, Clone)]
+pub struct ProxyAllowList {
+    pattern: Regex,
+    /// whether POST requests are proxied, they skip every validation of the wrapped handlers
+    writes: bool,
+}
+
+impl ProxyAllowList {
+    pub fn new(pattern: &str) -> std::result::Result<Self, regex::Error> {
+        Ok(ProxyAllowList {
+            pattern: Regex::new(pattern)?,
+            writes: false,
+        })
+    }
+
+    /// `DOCKER_PROXY_WRITE=1` enables POST requests through the raw proxy
+    pub fn from_env() -> std::result::Result<Self, regex::Error> {
+        let pattern = env::var("DOCKER_PROXY_ALLOW").unwrap_or_else(|_| DEFAULT_ALLOW.to_owned());
+        let writes = env::var("DOCKER_PROXY_WRITE").is_ok_and(|v| v == "1" || v == "true");
+        Ok(ProxyAllowList::new(&pattern)?.writes(writes))
+    }
+
+    pub fn writes(mut self, allowed: bool) -> Self {
+        self.writes = allowed;
+        self
+    }
+
+    /// dot segments are refused outright, the url join would resolve them past the check
+    pub fn allows(&self, path: &str) -> bool {
+        !path.split('/').any(|s| s == ".." || s == ".") && self.pattern.is_match(path)
+    }
+}
+
+/// Forward the method, path, query and body of the request to the daemon unchanged. The
+/// allowlist is checked on the path the daemon request resolved to, percent-encoded dot
+/// segments of the route param are only resolved by the url join.
+pub async fn raw(mut req: Request<State>) -> Result {
+    let method = req.method();
+    let path = format!("/{}", req.param("path")?);
+    let path = match req.url().query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+    let body = match method {
+        Method::Get => None,
+        _ => {
+            let mime = req.content_type().unwrap_or(mime::BYTE_STREAM);
+            Some((req.take_body(), mime))
+        }
+    };
+    let docker = daemon(&req)?;
+    let request = docker.raw(method, &path, body)?;
+
+    let path = docker.api_path(request.url());
+    let allow = &req.state().proxy_allow;
+    if !allow.allows(path) {
+        log::warn!("raw proxy to {} refused", path);
+        return Ok(Response::new(StatusCode::Forbidden));
+    }
+    if method != Method::Get && !allow.writes {
+        log::warn!("raw proxy {} to {} refused, writes are disabled", method, path);
+        return Ok(Response::new(StatusCode::MethodNotAllowed));
+    }
+    let response = req.state().send(request_id(&req), request).await?;
+    forward(response).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docker::docker::Docker;
+    use crate::service::mock;
+    use http_types::Method;
+
+    #[test]
+    fn allow_list_default() {
+        let allow = ProxyAllowList::new(DEFAULT_ALLOW).unwrap();
+        assert!(allow.allows("/containers/abc/json"));
+        assert!(allow.allows("/_ping"));
+        assert!(!allow.allows("/swarm/unlockkey"));
+        assert!(!allow.allows("/secrets"));
+        assert!(!allow.allows("/containers/../swarm/unlockkey"));
+        assert!(!allow.allows("/containersx"));
+    }
+
+    /// Test encoded dot segments being checked as the daemon path they resolve to
+    #[test]
+    fn allow_list_resolved_path() {
+        let allow = ProxyAllowList::new(DEFAULT_ALLOW).unwrap();
+        let path = "/containers/%2e%2e/swarm/unlockkey";
+        assert!(allow.allows(path));
+        for docker in &[
+            Docker::host("http://127.0.0.1:2375".parse().unwrap()),
+            Docker::host("http://127.0.0.1:2375".parse().unwrap()).with_api_version("1.41"),
+        ] {
+            let request = docker.raw(Method::Get, path, None).unwrap();
+            assert_eq!(docker.api_path(request.url()), "/swarm/unlockkey");
+            assert!(!allow.allows(docker.api_path(request.url())));
+        }
+    }
+
+    /// Test the method, path, query and body reaching the daemon as sent, and writes
+    /// being refused unless enabled
+    #[async_std::test]
+    async fn raw_preserves_method_and_path() {
+        use serde_json::{json, Value};
+
+        let daemon = mock::daemon_with_body(|method, target, body| {
+            let body = String::from_utf8_lossy(body);
+            (200, json!({ "method": method, "target": target, "body": body }).to_string())
+        })
+        .await;
+        let raw_app = |writes: bool| {
+            let mut state = State::for_test();
+            state.proxy_allow = ProxyAllowList::new(DEFAULT_ALLOW).unwrap().writes(writes);
+            let mut app = tide::with_state(state);
+            app.at("/raw/*path").get(raw).post(raw);
+            app
+        };
+
+        let app = raw_app(true);
+        let request = mock::request(Method::Get, "/raw/containers/web/json?size=true", &daemon);
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        let echo: Value = response.body_json().await.unwrap();
+        assert_eq!(echo["method"], "GET");
+        assert_eq!(echo["target"], "/containers/web/json?size=true");
+
+        let mut request = mock::request(Method::Post, "/raw/containers/web/update", &daemon);
+        request.set_body(json!({ "Memory": 314572800 }));
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        let echo: Value = response.body_json().await.unwrap();
+        assert_eq!(echo["method"], "POST");
+        assert_eq!(echo["target"], "/containers/web/update");
+        assert_eq!(echo["body"], r#"{"Memory":314572800}"#);
+
+        let request = mock::request(Method::Post, "/raw/containers/web/update", &daemon);
+        let response: http_types::Response = raw_app(false).respond(request).await.unwrap();
+        assert_eq!(response.status(), 405);
+    }
+}
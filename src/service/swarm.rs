@@ -0,0 +1,74 @@
+use crate::docker::service::{ServiceListOptions, ServiceOptions};
+use crate::docker::stream;
+use crate::service::{
+    container::{relay_frames, ContainerLogsOptions},
+    daemon, forward, proxy,
+};
+use crate::request_id::request_id;
+use crate::State;
+
+use tide::{Request, Result};
+
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct ServiceListQuery {
+    pub status: Option<bool>,
+}
+
+impl From<ServiceListQuery> for ServiceListOptions {
+    fn from(query: ServiceListQuery) -> ServiceListOptions {
+        let mut builder = ServiceListOptions::builder();
+        if let Some(true) = query.status {
+            builder.enable_status();
+        }
+        builder.build()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ServiceUpdateQuery {
+    pub version: u64,
+}
+
+pub async fn list(req: Request<State>) -> Result {
+    let args = req.query::<ServiceListQuery>()?;
+    proxy(&req, |docker| docker.services().list(&args.into())).await
+}
+
+pub async fn create(mut req: Request<State>) -> Result {
+    let options: ServiceOptions = req.body_json().await?;
+    proxy(&req, |docker| docker.services().create(&options)).await
+}
+
+pub async fn inspect(req: Request<State>) -> Result {
+    let id = req.param("id")?;
+    proxy(&req, |docker| docker.services().get(id).inspect()).await
+}
+
+pub async fn update(mut req: Request<State>) -> Result {
+    let options: ServiceOptions = req.body_json().await?;
+    let args = req.query::<ServiceUpdateQuery>()?;
+    let id = req.param("id")?;
+    proxy(&req, |docker| docker.services().get(id).update(args.version, &options)).await
+}
+
+pub async fn delete(req: Request<State>) -> Result {
+    let id = req.param("id")?;
+    proxy(&req, |docker| docker.services().get(id).delete()).await
+}
+
+pub async fn logs(req: Request<State>) -> Result {
+    let id = req.param("id")?;
+    let args = req.query::<ContainerLogsOptions>()?;
+    let follow = args.follow.unwrap_or(false);
+    let docker = daemon(&req)?;
+    let mut response = req
+        .state()
+        .send(request_id(&req), docker.services().get(id).logs(&args.into())?)
+        .await?;
+    if !follow || !response.status().is_success() {
+        return forward(response).await;
+    }
+    Ok(relay_frames(&req, stream::frames(response.take_body())))
+}
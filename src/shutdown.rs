@@ -0,0 +1,172 @@
+//! Graceful shutdown: stop accepting connections on SIGINT/SIGTERM and give the
+//! requests in flight a bounded time to finish.
+
+use std::{
+    env, io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::io::{AsyncBufRead, AsyncRead};
+use tide::{Body, Middleware, Next, Request};
+
+/// how often the in-flight count is checked while draining
+const DRAIN_POLL: Duration = Duration::from_millis(100);
+
+/// Counts requests being handled, as a middleware on the outermost server
+#[derive(Debug, Default, Clone)]
+pub struct InFlight {
+    count: Arc<AtomicUsize>,
+}
+
+/// decrements the count when the request is done, even if its handler is dropped
+struct Guard(Arc<AtomicUsize>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Response body holding the guard of its request, so a streamed response counts as in
+/// flight until it is sent or dropped rather than until its handler returns
+struct GuardedBody {
+    body: Body,
+    _guard: Guard,
+}
+
+impl AsyncRead for GuardedBody {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.body).poll_read(cx, buf)
+    }
+}
+
+impl AsyncBufRead for GuardedBody {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().body).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.body).consume(amt)
+    }
+}
+
+impl InFlight {
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    fn enter(&self) -> Guard {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        Guard(self.count.clone())
+    }
+
+    /// wait for the requests in flight to finish, returns false if `timeout` passed first
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.count() > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            async_std::task::sleep(DRAIN_POLL).await;
+        }
+        true
+    }
+}
+
+#[tide::utils::async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for InFlight {
+    async fn handle(&self, request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let guard = self.enter();
+        let mut response = next.run(request).await;
+        let body = response.take_body();
+        let (len, mime) = (body.len(), body.mime().clone());
+        let mut body = Body::from_reader(GuardedBody { body, _guard: guard }, len);
+        body.set_mime(mime);
+        response.set_body(body);
+        Ok(response)
+    }
+}
+
+/// resolves once SIGINT or SIGTERM is received
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                log::error!("SIGTERM handler not installed: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => log::info!("SIGINT received"),
+            _ = terminate.recv() => log::info!("SIGTERM received"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        log::info!("ctrl-c received");
+    }
+}
+
+/// how long in-flight requests may take to drain, `SHUTDOWN_TIMEOUT_SECONDS` overrides the default
+pub fn timeout() -> Duration {
+    let secs = env::var("SHUTDOWN_TIMEOUT_SECONDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+    Duration::from_secs(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test draining waits for a request in flight and gives up after the timeout
+    #[async_std::test]
+    async fn drain_in_flight() {
+        let in_flight = InFlight::default();
+        assert!(in_flight.drain(Duration::from_millis(10)).await);
+
+        let guard = in_flight.enter();
+        assert_eq!(in_flight.count(), 1);
+        assert!(!in_flight.drain(Duration::from_millis(10)).await);
+
+        let waiting = in_flight.clone();
+        let drained =
+            async_std::task::spawn(async move { waiting.drain(Duration::from_secs(5)).await });
+        async_std::task::sleep(Duration::from_millis(50)).await;
+        drop(guard);
+        assert!(drained.await);
+        assert_eq!(in_flight.count(), 0);
+    }
+
+    /// Test a request counting as in flight until its response body is sent
+    #[async_std::test]
+    async fn in_flight_until_body_sent() {
+        let in_flight = InFlight::default();
+        let mut app = tide::new();
+        app.with(in_flight.clone());
+        app.at("/").get(|_| async { Ok("done") });
+
+        let request = http_types::Request::get("http://localhost/");
+        let mut response: http_types::Response = app.respond(request).await.unwrap();
+        assert_eq!(in_flight.count(), 1);
+        assert_eq!(response.content_type(), Some(tide::http::mime::PLAIN));
+        assert_eq!(response.body_string().await.unwrap(), "done");
+        assert_eq!(in_flight.count(), 0);
+    }
+}
@@ -0,0 +1,140 @@
+//! Client certificates for daemons protected by TLS, configured like the docker cli with
+//! `DOCKER_TLS_VERIFY` and `DOCKER_CERT_PATH`.
+
+use std::{
+    convert::TryFrom,
+    env,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use http_client::Config;
+use rustls::{internal::pemfile, ClientConfig};
+
+use crate::Client;
+
+/// `ca.pem`, `cert.pem` and `key.pem` of a certificate directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsPaths {
+    pub ca: PathBuf,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsPaths {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        let dir = dir.as_ref();
+        TlsPaths {
+            ca: dir.join("ca.pem"),
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+        }
+    }
+
+    /// Set when `DOCKER_TLS_VERIFY` is set to anything but an empty string or `0`. The
+    /// certificates are taken from `DOCKER_CERT_PATH`, falling back on `~/.docker`.
+    pub fn from_env() -> Option<Self> {
+        let verify = env::var("DOCKER_TLS_VERIFY").unwrap_or_default();
+        if verify.is_empty() || verify == "0" {
+            return None;
+        }
+        let dir = env::var("DOCKER_CERT_PATH").map(PathBuf::from).unwrap_or_else(|_| {
+            PathBuf::from(env::var("HOME").unwrap_or_default()).join(".docker")
+        });
+        Some(TlsPaths::new(dir))
+    }
+
+    /// rustls configuration trusting `ca.pem` and presenting `cert.pem` with `key.pem`
+    pub fn client_config(&self) -> io::Result<ClientConfig> {
+        let mut config = ClientConfig::new();
+        let (added, _) = config
+            .root_store
+            .add_pem_file(&mut open(&self.ca)?)
+            .map_err(|_| invalid(&self.ca, "no valid certificate"))?;
+        if added == 0 {
+            return Err(invalid(&self.ca, "no valid certificate"));
+        }
+
+        let certs = pemfile::certs(&mut open(&self.cert)?)
+            .map_err(|_| invalid(&self.cert, "no valid certificate"))?;
+        if certs.is_empty() {
+            return Err(invalid(&self.cert, "no valid certificate"));
+        }
+
+        // keys written by openssl are either pkcs8 or the older rsa format
+        let mut keys = pemfile::pkcs8_private_keys(&mut open(&self.key)?)
+            .map_err(|_| invalid(&self.key, "no valid private key"))?;
+        if keys.is_empty() {
+            keys = pemfile::rsa_private_keys(&mut open(&self.key)?)
+                .map_err(|_| invalid(&self.key, "no valid private key"))?;
+        }
+        let key = keys
+            .into_iter()
+            .next()
+            .ok_or_else(|| invalid(&self.key, "no valid private key"))?;
+
+        config
+            .set_single_client_cert(certs, key)
+            .map_err(|e| invalid(&self.cert, &e.to_string()))?;
+        Ok(config)
+    }
+}
+
+fn open(path: &Path) -> io::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+fn invalid(path: &Path, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), reason),
+    )
+}
+
+/// Http client for the daemons, presenting the client certificates of the environment
+/// to `https://` daemons when TLS verification is enabled
+pub fn client() -> io::Result<Client> {
+    let paths = match TlsPaths::from_env() {
+        Some(paths) => paths,
+        None => return Ok(Client::new()),
+    };
+    log::info!("docker tls certificates from {}", paths.ca.display());
+    let config = Config::new().set_tls_config(Some(Arc::new(paths.client_config()?)));
+    Ok(Client::try_from(config).unwrap_or_else(|e| match e {}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn tls_paths_from_dir() {
+        let paths = TlsPaths::new("/etc/docker/certs");
+        assert_eq!(paths.ca, PathBuf::from("/etc/docker/certs/ca.pem"));
+        assert_eq!(paths.cert, PathBuf::from("/etc/docker/certs/cert.pem"));
+        assert_eq!(paths.key, PathBuf::from("/etc/docker/certs/key.pem"));
+    }
+
+    /// Test a missing or broken file being reported with its path
+    #[test]
+    fn tls_missing_file() {
+        let dir = env::temp_dir().join("beekeeper-tls-missing");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let paths = TlsPaths::new(&dir);
+
+        let err = paths.client_config().map(|_| ()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("ca.pem"));
+
+        fs::write(&paths.ca, "not a certificate").unwrap();
+        let err = paths.client_config().map(|_| ()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("ca.pem"));
+    }
+}